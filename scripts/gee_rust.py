@@ -1,15 +1,27 @@
 import ee
 import json
-from datetime import datetime
+from datetime import datetime, timezone
 import argparse
 import sys
 import calendar
+import os
 
 # -----------------------
 # Parse CLI arguments
 # -----------------------
 parser = argparse.ArgumentParser(description="Generate yearly average LST and anomaly tiles via GEE")
 parser.add_argument("--year", type=int, required=True, help="Target year for analysis")
+parser.add_argument("--output-dir", default="tiles", help="Directory to write tile_config_{year}.json into")
+parser.add_argument(
+    "--viz-file",
+    default=os.path.join(os.path.dirname(os.path.abspath(__file__)), "..", "gee", "viz.json"),
+    help="Palettes and min/max per layer, shared with the Rust backend",
+)
+parser.add_argument(
+    "--key-file",
+    default=os.environ.get("GOOGLE_APPLICATION_CREDENTIALS", "service_account.json"),
+    help="Earth Engine service account key (JSON)",
+)
 args = parser.parse_args()
 
 target_year = args.year
@@ -25,11 +37,11 @@ else:
 # -----------------------
 # Authenticate Earth Engine
 # -----------------------
-service_account = 'climate@urban-heat-464110.iam.gserviceaccount.com'
-key_file = 'C:/Users/amina/app/climate-backend/service_account.json'
-
 try:
-    credentials = ee.ServiceAccountCredentials(service_account, key_file)
+    # The service account email is taken from the key itself
+    with open(args.key_file) as f:
+        service_account = json.load(f)["client_email"]
+    credentials = ee.ServiceAccountCredentials(service_account, args.key_file)
     ee.Initialize(credentials)
 except Exception as e:
     print(f"Failed to initialize Earth Engine: {e}", file=sys.stderr)
@@ -133,9 +145,16 @@ absolute_anomaly = fused_target_lst.subtract(modis_long_term_mean).rename('T_Ano
 # -----------------------
 # Visualization parameters
 # -----------------------
-lst_vis = {"min": -20, "max": 50, "palette": ["040274","040281","0502a3","0502b8","0502ce","0502e6","0602ff","235cb1","307ef3","269db1","30c8e2","32d3ef","3be285","3ff38f","86e26f","3ae237","b5e22e","d6e21f","fff705","ffd611","ffb613","ff8b13","ff6e08","ff500d","ff0000","de0101","c21301","a71001","911003"]}
-anomaly_vis = {"min": -3, "max": 3, "palette": ['0000ff','ffffff','ff0000']}
-absolute_anomaly_vis = {"min": -5, "max": 5, "palette": ['0000ff','ffffff','ff0000']}
+with open(args.viz_file) as f:
+    viz = json.load(f)
+
+def layer_vis(name):
+    layer = viz["layers"][name]
+    return {"min": layer["min"], "max": layer["max"], "palette": viz["palettes"][layer["palette"]]}
+
+lst_vis = layer_vis("lst")
+anomaly_vis = layer_vis("anomaly")
+absolute_anomaly_vis = layer_vis("absolute_anomaly")
 
 # -----------------------
 # Generate tile URLs
@@ -152,15 +171,28 @@ if None in [lst_template, anomaly_template, absolute_anomaly_template]:
 # -----------------------
 # Save configuration
 # -----------------------
+if months[-1] == 12:
+    target_period = str(target_year)
+else:
+    target_period = f"{target_year}-01..{target_year}-{months[-1]:02d}"
+
 config_data = {
-"lst_tile_url": lst_template,
+    "lst_tile_url": lst_template,
     "anomaly_tile_url": anomaly_template,
-    "absolute_anomaly_tile_url": absolute_anomaly_template
+    "absolute_anomaly_tile_url": absolute_anomaly_template,
+    "generated_at": datetime.now(timezone.utc).isoformat(),
+    "target_period": target_period,
+    "climatology_period": f"{climatology_start}..{climatology_end}",
+    "data_source": "MODIS/061/MOD11A1 + ECMWF/ERA5_LAND/DAILY_AGGR",
+    "description": "Mean daytime land surface temperature (MODIS, gaps filled with ERA5-Land) and anomalies"
 }
 
-config_path = f"C:/Users/amina/app/climate-backend/tiles/tile_config_{target_year}.json"
-with open(config_path, "w") as f:
+# Write to a temp file and rename so the backend's watcher never reads a partial file
+config_path = os.path.join(args.output_dir, f"tile_config_{target_year}.json")
+tmp_path = config_path + ".tmp"
+with open(tmp_path, "w") as f:
     json.dump(config_data, f, indent=2)
+os.replace(tmp_path, config_path)
 
 print("="*60)
 print(f"TILE GENERATION COMPLETE for {target_year}")
@@ -0,0 +1,96 @@
+// src/api/cities.rs
+use crate::config::parse_bbox;
+use crate::models::city::City;
+use crate::services::city_catalogue::{CityCatalogue, CityFilter};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+struct CitiesQuery {
+    /// Case-insensitive part of the name
+    q: Option<String>,
+    /// ISO 3166-1 alpha-2 code
+    country: Option<String>,
+    min_population: Option<u64>,
+    /// `west,south,east,north`
+    bbox: Option<String>,
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+}
+
+// GET catalogue cities matching the filters, most populous first
+async fn list_cities(
+    State(cities): State<Arc<CityCatalogue>>,
+    Query(query): Query<CitiesQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let bbox = match query.bbox.as_deref().map(parse_bbox).transpose() {
+        Ok(bbox) => bbox,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({ "status": "error", "message": format!("Invalid bbox: {}", e) })),
+            )
+        }
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "message": format!("limit must be between 1 and {}", MAX_LIMIT),
+            })),
+        );
+    }
+
+    let filter = CityFilter {
+        query: query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()),
+        country: query.country.as_deref(),
+        min_population: query.min_population,
+        bbox,
+    };
+    let matches: Vec<&City> = cities.search(&filter).collect();
+    let page: Vec<&City> = matches.iter().copied().skip(query.offset).take(limit).collect();
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "status": "success",
+            // Matches before limit and offset
+            "total": matches.len(),
+            "data": page,
+        })),
+    )
+}
+
+async fn get_city(
+    State(cities): State<Arc<CityCatalogue>>,
+    Path(id): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    match cities.get(&id) {
+        Some(city) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "status": "success", "data": city })),
+        ),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "status": "error", "message": format!("Unknown city {:?}", id) })),
+        ),
+    }
+}
+
+pub fn routes(cities: Arc<CityCatalogue>) -> Router {
+    Router::new()
+        .route("/", get(list_cities))
+        .route("/{id}", get(get_city))
+        .with_state(cities)
+}
@@ -1,20 +1,36 @@
 // src/api/climate.rs
 
 use axum::{
-    extract::{Query, State},
+    extract::{FromRef, Query, State},
     http::StatusCode,
-    routing::get,
+    routing::{get, post},
     Router,
     Json,
     debug_handler,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Utc};
 use validator::Validate;
 use std::sync::Arc;
 use crate::services::earthengine::{EarthEngineService, LSTResponse};
-use crate::services::data_fetcher; // Your get_temperature function
+use crate::services::point_sampler::{PointError, PointSample, PointSampler};
+use crate::models::tile_config::TileLayer;
+use crate::models::zone::{GeoJson, Zone};
+use crate::services::vis_registry::VisRegistry;
+use crate::services::weather::{Units, WeatherChain};
+use crate::services::zonal_stats::{ZonalError, ZonalStats, ZonalStatsService};
+use crate::services::timeseries::{Aggregation, TimeSeries, TimeSeriesError, TimeSeriesService, Variable};
+
+#[derive(Clone, FromRef)]
+pub struct ClimateState {
+    pub earth_engine: Arc<EarthEngineService>,
+    pub point_sampler: Arc<PointSampler>,
+    pub timeseries: Arc<TimeSeriesService>,
+    pub zonal: Arc<ZonalStatsService>,
+    pub vis: Arc<VisRegistry>,
+    pub weather: Arc<WeatherChain>,
+}
 
 // -------------------- Query Structs --------------------
 #[derive(Debug, Deserialize, Validate)]
@@ -25,18 +41,65 @@ pub struct LocationQuery {
     lng: f64,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct UnitsQuery {
+    #[serde(default)]
+    units: Units,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct YearQuery {
+    year: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimeSeriesQuery {
+    from: Option<i32>,
+    to: Option<i32>,
+    #[serde(default)]
+    variable: Variable,
+    #[serde(default)]
+    aggregation: Aggregation,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ZonalRequest {
+    geometry: GeoJson,
+    year: Option<i32>,
+    #[serde(default)]
+    years: Vec<i32>,
+    layer: Option<TileLayer>,
+}
+
 // -------------------- Response Structs --------------------
 #[derive(Debug, Serialize)]
 pub struct TemperatureResponse {
     temperature: f64,
+    apparent_temperature: Option<f64>,
+    /// Temperature unit: `C`, `F` or `K`
     unit: String,
+    /// Relative humidity in %
+    humidity: Option<f64>,
+    wind_speed: Option<f64>,
+    wind_speed_unit: String,
+    wind_direction: Option<f64>,
+    /// WMO weather code
+    weather_code: Option<u8>,
+    /// Observation time reported by the provider
     timestamp: DateTime<Utc>,
+    timezone: String,
+    utc_offset_seconds: i32,
+    provider: String,
+    /// Seconds between the observation and this response
+    data_age_secs: i64,
 }
 
 // -------------------- Handlers --------------------
 #[debug_handler]
 pub async fn get_current_temperature(
-    Query(query): Query<LocationQuery>
+    Query(query): Query<LocationQuery>,
+    Query(UnitsQuery { units }): Query<UnitsQuery>,
+    State(weather): State<Arc<WeatherChain>>,
 ) -> Result<Json<TemperatureResponse>, (StatusCode, Json<serde_json::Value>)> {
     // Validate location coordinates
     if let Err(e) = query.validate() {
@@ -46,15 +109,26 @@ pub async fn get_current_temperature(
         ));
     }
 
-    // Fetch temperature from your data service
-    match data_fetcher::get_temperature(query.lat, query.lng).await {
-        Ok(temp) => Ok(Json(TemperatureResponse {
-            temperature: temp,
-            unit: "C".to_string(),
-            timestamp: Utc::now(),
+    // Primary weather provider, then the fallbacks
+    match weather.current(query.lat, query.lng).await {
+        Ok(conditions) => Ok(Json(TemperatureResponse {
+            data_age_secs: conditions.age_secs(Utc::now()),
+            temperature: units.temperature(conditions.temperature),
+            apparent_temperature: conditions.apparent_temperature.map(|t| units.temperature(t)),
+            unit: units.temperature_unit().to_string(),
+            humidity: conditions.humidity,
+            wind_speed: conditions.wind_speed.map(|speed| units.wind_speed(speed)),
+            wind_speed_unit: units.wind_speed_unit().to_string(),
+            wind_direction: conditions.wind_direction,
+            weather_code: conditions.weather_code,
+            timestamp: conditions.observed_at,
+            timezone: conditions.timezone,
+            utc_offset_seconds: conditions.utc_offset_seconds,
+            provider: conditions.provider.to_string(),
         })),
         Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
+            // No providers at all means missing credentials rather than an upstream failure
+            if e.0.is_empty() { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::BAD_GATEWAY },
             Json(json!({"error": "Failed to fetch temperature", "details": e.to_string()}))
         )),
     }
@@ -85,10 +159,142 @@ pub async fn get_lst_temperature(
     Ok(Json(response))
 }
 
+#[derive(Debug, Serialize)]
+pub struct PointResponse {
+    status: String,
+    cached: bool,
+    data: PointSample,
+}
+
+// GET LST, climatology mean, anomaly and z-score of a year at a coordinate
+#[debug_handler]
+pub async fn get_point(
+    Query(location): Query<LocationQuery>,
+    Query(query): Query<YearQuery>,
+    State(point_sampler): State<Arc<PointSampler>>,
+) -> Result<Json<PointResponse>, (StatusCode, Json<serde_json::Value>)> {
+    if let Err(e) = location.validate() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid coordinates", "details": e.to_string()}))
+        ));
+    }
+
+    match point_sampler.sample(location.lat, location.lng, query.year).await {
+        Ok((sample, cached)) => Ok(Json(PointResponse {
+            status: "success".to_string(),
+            cached,
+            data: sample,
+        })),
+        Err(e @ PointError::InvalidYear(_)) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid year", "details": e.to_string()}))
+        )),
+        Err(e @ PointError::Unavailable(_)) => Err((
+            StatusCode::BAD_GATEWAY,
+            Json(json!({"error": "Failed to sample point", "details": e.to_string()}))
+        )),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimeSeriesResponse {
+    status: String,
+    data: TimeSeries,
+}
+
+// GET monthly or yearly history at a coordinate with anomalies and trend
+#[debug_handler]
+pub async fn get_timeseries(
+    Query(location): Query<LocationQuery>,
+    Query(query): Query<TimeSeriesQuery>,
+    State(timeseries): State<Arc<TimeSeriesService>>,
+) -> Result<Json<TimeSeriesResponse>, (StatusCode, Json<serde_json::Value>)> {
+    if let Err(e) = location.validate() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid coordinates", "details": e.to_string()}))
+        ));
+    }
+
+    let from = query.from.unwrap_or(query.variable.first_year());
+    let to = query.to.unwrap_or(Utc::now().year());
+    match timeseries
+        .series(location.lat, location.lng, from, to, query.variable, query.aggregation)
+        .await
+    {
+        Ok(series) => Ok(Json(TimeSeriesResponse {
+            status: "success".to_string(),
+            data: series,
+        })),
+        Err(e @ TimeSeriesError::InvalidRange(_)) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid year range", "details": e.to_string()}))
+        )),
+        Err(e @ TimeSeriesError::Unavailable(_)) => Err((
+            StatusCode::BAD_GATEWAY,
+            Json(json!({"error": "Failed to fetch time series", "details": e.to_string()}))
+        )),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ZonalData {
+    layer: TileLayer,
+    units: String,
+    results: Vec<ZonalStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ZonalResponse {
+    status: String,
+    data: ZonalData,
+}
+
+// POST statistics of a layer over a GeoJSON polygon for one or more years
+#[debug_handler(state = ClimateState)]
+pub async fn post_zonal(
+    State(zonal): State<Arc<ZonalStatsService>>,
+    State(vis): State<Arc<VisRegistry>>,
+    Json(request): Json<ZonalRequest>,
+) -> Result<Json<ZonalResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let zone = Zone::try_from(request.geometry).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid geometry", "details": e}))
+        )
+    })?;
+    let layer = request.layer.unwrap_or(TileLayer::Lst);
+    let mut years = request.years;
+    years.extend(request.year);
+
+    match zonal.stats(&zone, layer, &years).await {
+        Ok(results) => Ok(Json(ZonalResponse {
+            status: "success".to_string(),
+            data: ZonalData {
+                layer,
+                units: vis.tile_layer(layer).units.clone(),
+                results,
+            },
+        })),
+        Err(e @ ZonalError::InvalidRequest(_)) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid request", "details": e.to_string()}))
+        )),
+        Err(e @ ZonalError::Unavailable(_)) => Err((
+            StatusCode::BAD_GATEWAY,
+            Json(json!({"error": "Failed to compute zonal statistics", "details": e.to_string()}))
+        )),
+    }
+}
+
 // -------------------- Routes --------------------
-pub fn routes(earth_engine: Arc<EarthEngineService>) -> Router {
+pub fn routes(state: ClimateState) -> Router {
     Router::new()
         .route("/current", get(get_current_temperature))
         .route("/lst", get(get_lst_temperature))
-        .with_state(earth_engine)
+        .route("/point", get(get_point))
+        .route("/timeseries", get(get_timeseries))
+        .route("/zonal", post(post_zonal))
+        .with_state(state)
 }
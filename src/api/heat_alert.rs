@@ -1,15 +1,154 @@
-use axum::{routing::get, Json, Router, extract::Extension};
+use axum::{routing::get, Json, Router, extract::{Extension, Path, Query}, http::StatusCode};
 use crate::models::heat_alert::HeatAlert;
 use crate::scheduler::heat_alert_scheduler::HeatAlertCache;
+use crate::services::city_catalogue::CityCatalogue;
+use crate::services::heat_history::HeatHistory;
+use crate::services::heatwave::HeatwaveDetector;
+use chrono::{Duration, NaiveDate, Utc};
+use serde::Deserialize;
+use serde_json::json;
 use std::sync::Arc;
 
+/// Days covered when `from` is not given
+const HISTORY_DAYS: i64 = 7;
+const CITY_HISTORY_DAYS: i64 = 30;
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    /// City id or name; every city when absent
+    city: Option<String>,
+    /// First and last day, inclusive, in each city's local time
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    /// Only ongoing (`true`) or finished (`false`) events
+    active: Option<bool>,
+    /// City id or name
+    city: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RangeQuery {
+    /// First and last local day, inclusive
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+fn error(status: StatusCode, message: String) -> (StatusCode, Json<serde_json::Value>) {
+    (status, Json(json!({ "status": "error", "message": message })))
+}
+
+/// `from..=to` in local days, spanning `days` days by default and ending on
+/// the latest date anywhere (UTC+14), so no city's today is cut off
+fn date_range(
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    days: i64,
+) -> Result<(NaiveDate, NaiveDate), (StatusCode, Json<serde_json::Value>)> {
+    let to = to.unwrap_or_else(|| (Utc::now() + Duration::hours(14)).date_naive());
+    let from = from.unwrap_or(to - Duration::days(days - 1));
+    if from > to {
+        return Err(error(StatusCode::BAD_REQUEST, "from must not be after to".to_string()));
+    }
+    Ok((from, to))
+}
+
+/// Id of the catalogue city `name` refers to; every city when absent
+fn resolve_city<'a>(
+    cities: &'a CityCatalogue,
+    name: Option<&str>,
+) -> Result<Option<&'a str>, (StatusCode, Json<serde_json::Value>)> {
+    match name.map(|name| (name, cities.resolve(name))) {
+        None => Ok(None),
+        Some((_, Some(city))) => Ok(Some(city.id.as_str())),
+        Some((name, None)) => Err(error(StatusCode::NOT_FOUND, format!("Unknown city {:?}", name))),
+    }
+}
+
 pub async fn get_heat_alerts(Extension(cache): Extension<Arc<HeatAlertCache>>) -> Json<Vec<HeatAlert>> {
     let alerts = cache.top_alerts.read().await;
     Json(alerts.clone())
 }
 
-pub fn routes(cache: Arc<HeatAlertCache>) -> Router {
+// GET the report of the latest polling run: cities that succeeded, were retried or failed
+pub async fn get_status(Extension(cache): Extension<Arc<HeatAlertCache>>) -> Json<serde_json::Value> {
+    let last_run = cache.last_run.read().await;
+    Json(json!({ "status": "success", "data": *last_run }))
+}
+
+// GET stored readings, oldest first
+pub async fn get_history(
+    Extension(history): Extension<Arc<HeatHistory>>,
+    Extension(cities): Extension<Arc<CityCatalogue>>,
+    Query(query): Query<HistoryQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let (from, to) = match date_range(query.from, query.to, HISTORY_DAYS) {
+        Ok(range) => range,
+        Err(e) => return e,
+    };
+    let city_id = match resolve_city(&cities, query.city.as_deref()) {
+        Ok(city_id) => city_id,
+        Err(e) => return e,
+    };
+    let readings = history.readings(city_id, from, to).await;
+    (
+        StatusCode::OK,
+        Json(json!({ "status": "success", "from": from, "to": to, "data": readings })),
+    )
+}
+
+// GET one city's readings with their max/min and consecutive-day heat streaks
+pub async fn get_city_history(
+    Extension(history): Extension<Arc<HeatHistory>>,
+    Extension(cities): Extension<Arc<CityCatalogue>>,
+    Path(name): Path<String>,
+    Query(query): Query<RangeQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let (from, to) = match date_range(query.from, query.to, CITY_HISTORY_DAYS) {
+        Ok(range) => range,
+        Err(e) => return e,
+    };
+    let Some(city) = cities.resolve(&name) else {
+        return error(StatusCode::NOT_FOUND, format!("Unknown city {:?}", name));
+    };
+    let summary = history.city(&city.id, from, to).await;
+    (
+        StatusCode::OK,
+        Json(json!({ "status": "success", "city": city, "from": from, "to": to, "data": summary })),
+    )
+}
+
+// GET heatwave events, newest first
+pub async fn get_events(
+    Extension(heatwaves): Extension<Arc<HeatwaveDetector>>,
+    Extension(cities): Extension<Arc<CityCatalogue>>,
+    Query(query): Query<EventsQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let city_id = match resolve_city(&cities, query.city.as_deref()) {
+        Ok(city_id) => city_id,
+        Err(e) => return e,
+    };
+    let events = heatwaves.events(query.active, city_id).await;
+    (StatusCode::OK, Json(json!({ "status": "success", "data": events })))
+}
+
+pub fn routes(
+    cache: Arc<HeatAlertCache>,
+    history: Arc<HeatHistory>,
+    heatwaves: Arc<HeatwaveDetector>,
+    cities: Arc<CityCatalogue>,
+) -> Router {
     Router::new()
         .route("/api/heat_alert", get(get_heat_alerts))
+        .route("/status", get(get_status))
+        .route("/history", get(get_history))
+        .route("/cities/{name}", get(get_city_history))
+        .route("/events", get(get_events))
         .layer(Extension(cache))
+        .layer(Extension(history))
+        .layer(Extension(heatwaves))
+        .layer(Extension(cities))
 }
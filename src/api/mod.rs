@@ -1,20 +1,53 @@
 use crate::scheduler::heat_alert_scheduler::HeatAlertCache;
-use crate::services::earthengine::EarthEngineService;
-use crate::services::tile_config_service::TileConfigService;
+use crate::services::city_catalogue::CityCatalogue;
+use crate::services::heat_history::HeatHistory;
+use crate::services::heatwave::HeatwaveDetector;
+use axum::http::{header, HeaderMap};
 use axum::Router;
 use std::sync::Arc;
 
+pub mod cities;
 pub mod climate;
 pub mod heat_alert;
 pub mod tiles;
+pub mod wmts;
+
+/// `server.public_url`, the base URL put into TileJSON and WMTS documents
+#[derive(Clone)]
+pub struct PublicUrl(pub Option<String>);
+
+impl PublicUrl {
+    /// The configured URL, or `http://{Host}` of the current request
+    pub fn base(&self, headers: &HeaderMap) -> String {
+        if let Some(url) = &self.0 {
+            return url.trim_end_matches('/').to_string();
+        }
+        let host = headers
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .unwrap_or("localhost:3000");
+        format!("http://{}", host)
+    }
+}
 
 pub fn routes(
     cache: Arc<HeatAlertCache>,
-    earth_engine_service: Arc<EarthEngineService>,
-    tile_config_service: Arc<TileConfigService>,
+    history: Arc<HeatHistory>,
+    heatwaves: Arc<HeatwaveDetector>,
+    cities: Arc<CityCatalogue>,
+    climate_state: climate::ClimateState,
+    tiles_state: tiles::TilesState,
 ) -> Router {
+    let wmts_state = wmts::WmtsState {
+        tile_config_service: tiles_state.tile_config_service.clone(),
+        public_url: tiles_state.public_url.clone(),
+        vis: tiles_state.vis.clone(),
+    };
+
     Router::new()
-        .nest("/climate", climate::routes(earth_engine_service))
-        .nest("/heat_alert", heat_alert::routes(cache))
-        .nest("/tiles", tiles::routes(tile_config_service))
+        .nest("/cities", cities::routes(cities.clone()))
+        .nest("/climate", climate::routes(climate_state))
+        .nest("/heat_alert", heat_alert::routes(cache, history, heatwaves, cities))
+        .nest("/tiles", tiles::routes(tiles_state))
+        .nest("/wmts", wmts::routes(wmts_state))
 }
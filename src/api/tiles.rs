@@ -1,25 +1,66 @@
+use crate::api::PublicUrl;
+use crate::config::parse_bbox;
+use crate::models::tile_config::{ChangeStats, TileComparison, TileConfig, TileLayer};
+use crate::services::tile_cache::{
+    TileCache, TileError, TileKey, TileSource, MAX_LATITUDE, MAX_ZOOM,
+};
+use crate::services::tile_compare::{CompareError, TileCompareService};
 use crate::services::tile_config_service::TileConfigService;
+use crate::services::tile_freshness::{TileFreshnessService, YearFreshness};
+use crate::services::tile_jobs::{CancelError, JobSubscription, TileJob, TileJobService};
+use crate::services::vis_registry::{LayerVis, LegendStop, VisRegistry};
+use crate::services::grid_renderer::ColorRamp;
+use crate::services::tile_seeder::{SeedRequest, SeedRun, TileSeeder};
 use axum::{
-    extract::{Json as AxumJson, Query, State},
+    extract::{FromRef, Json as AxumJson, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::sse::{Event, KeepAlive, Sse},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
+use futures_util::stream::{self, Stream, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
 use serde::{Deserialize, Serialize};
-use std::process::Command;
 use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Clone, FromRef)]
+pub struct TilesState {
+    pub tile_config_service: Arc<TileConfigService>,
+    pub jobs: Arc<TileJobService>,
+    pub freshness: Arc<TileFreshnessService>,
+    pub cache: Arc<TileCache>,
+    pub seeder: Arc<TileSeeder>,
+    pub public_url: PublicUrl,
+    pub vis: Arc<VisRegistry>,
+    pub compare: Arc<TileCompareService>,
+}
 
 #[derive(Serialize)]
 struct TileUrlsResponse {
     status: String,
-    data: Option<TileUrlsData>,
+    data: Option<TileConfig>,
     message: Option<String>,
 }
 
 #[derive(Serialize)]
-struct TileUrlsData {
-    lst_tile_url: String,
-    anomaly_tile_url: String,
-    absolute_anomaly_tile_url: String,
+struct YearTileConfig {
+    year: i32,
+    #[serde(flatten)]
+    config: TileConfig,
+}
+
+#[derive(Serialize)]
+struct TileConfigsResponse {
+    status: String,
+    data: Vec<YearTileConfig>,
+}
+
+#[derive(Serialize)]
+struct FreshnessResponse {
+    status: String,
+    data: Vec<YearFreshness>,
 }
 
 #[derive(Deserialize)]
@@ -28,10 +69,97 @@ struct GenerateRequest {
 }
 
 #[derive(Serialize)]
-struct GenerateResponse {
+struct JobsResponse {
+    status: String,
+    data: Vec<TileJob>,
+}
+
+#[derive(Serialize)]
+struct JobResponse {
+    status: String,
+    message: Option<String>,
+    data: Option<TileJob>,
+}
+
+#[derive(Serialize)]
+struct SeedsResponse {
+    status: String,
+    data: Vec<SeedRun>,
+}
+
+#[derive(Serialize)]
+struct SeedResponse {
+    status: String,
+    message: Option<String>,
+    data: Option<SeedRun>,
+}
+
+/// TileJSON 3.0.0 document for one layer and year
+#[derive(Serialize)]
+struct TileJson {
+    tilejson: &'static str,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    version: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attribution: Option<String>,
+    scheme: &'static str,
+    tiles: Vec<String>,
+    minzoom: u32,
+    maxzoom: u32,
+    bounds: [f64; 4],
+    center: [f64; 3],
+}
+
+#[derive(Serialize)]
+struct LegendResponse<'a> {
+    status: String,
+    layer: &'a str,
+    #[serde(flatten)]
+    vis: &'a LayerVis,
+    stops: Vec<LegendStop>,
+}
+
+#[derive(Deserialize)]
+struct LegendQuery {
+    #[serde(default = "default_legend_width")]
+    width: u32,
+    #[serde(default = "default_legend_height")]
+    height: u32,
+}
+
+fn default_legend_width() -> u32 {
+    256
+}
+
+fn default_legend_height() -> u32 {
+    16
+}
+
+#[derive(Deserialize)]
+struct CompareQuery {
+    from: i32,
+    to: i32,
+    layer: Option<TileLayer>,
+    /// `west,south,east,north` for the statistics; the whole world by default
+    bbox: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Comparison {
+    #[serde(flatten)]
+    comparison: TileComparison,
+    legend_url: String,
+    stats: ChangeStats,
+}
+
+#[derive(Serialize)]
+struct CompareResponse {
     status: String,
-    message: String,
-    data: Option<TileUrlsData>, // <-- Return URLs directly
+    message: Option<String>,
+    cached: bool,
+    data: Option<Comparison>,
 }
 
 #[derive(Deserialize)]
@@ -39,125 +167,680 @@ struct UrlsQuery {
     year: i32,
 }
 
-pub fn routes(tile_config_service: Arc<TileConfigService>) -> Router {
+pub fn routes(state: TilesState) -> Router {
     Router::new()
         .route("/urls", get(get_tile_urls))
         .route("/health", get(tile_health_check))
+        .route("/configs", get(list_tile_configs))
+        .route("/configs/{year}", get(get_tile_config))
+        .route("/freshness", get(list_freshness))
+        .route("/freshness/{year}", get(get_freshness))
         .route("/generate", post(generate_tiles))
-        .with_state(tile_config_service)
+        .route("/generate/{year}/events", get(generation_events))
+        .route("/jobs", get(list_jobs))
+        .route("/jobs/{id}", get(get_job).delete(cancel_job))
+        .route("/seed", get(list_seeds).post(start_seed))
+        .route("/seed/{id}", get(get_seed))
+        .route("/compare", get(compare_years))
+        .route("/compare/{layer}/{from}/{to}/{z}/{x}/{tile}", get(get_compare_tile))
+        // `{layer}.png` renders the legend bar, as with tiles
+        .route("/legend/{layer}", get(get_legend))
+        .route("/{layer}/{year}/tilejson.json", get(get_tilejson))
+        // `{y}.png`: the router can't match a suffix after a parameter
+        .route("/{layer}/{year}/{z}/{x}/{tile}", get(get_tile))
+        .with_state(state)
 }
 
 // GET tile URLs (optional fallback)
 async fn get_tile_urls(
-    State(tile_config_service): State<Arc<TileConfigService>>,
+    State(state): State<TilesState>,
     Query(query): Query<UrlsQuery>,
 ) -> Json<TileUrlsResponse> {
-    let year = query.year;
+    Json(tile_config_response(&state, query.year).await)
+}
 
-    if let Some((lst_tile_url, anomaly_tile_url, absolute_tile_url)) =
-        tile_config_service.get_tile_urls_for_year(year).await
-    {
-        Json(TileUrlsResponse {
+// GET every loaded year with its period, climatology window and source dataset
+async fn list_tile_configs(
+    State(tile_config_service): State<Arc<TileConfigService>>,
+) -> Json<TileConfigsResponse> {
+    let data = tile_config_service
+        .get_all_configs()
+        .await
+        .into_iter()
+        .map(|(year, config)| YearTileConfig {
+            year,
+            config: proxied_if_local(config, year),
+        })
+        .collect();
+
+    Json(TileConfigsResponse {
+        status: "success".to_string(),
+        data,
+    })
+}
+
+// GET the full config (URLs and metadata) for one year
+async fn get_tile_config(
+    State(state): State<TilesState>,
+    Path(year): Path<i32>,
+) -> (StatusCode, Json<TileUrlsResponse>) {
+    let response = tile_config_response(&state, year).await;
+    let status = match response.status.as_str() {
+        "success" => StatusCode::OK,
+        "expired" => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::NOT_FOUND,
+    };
+    (status, Json(response))
+}
+
+/// The year's config, withheld while its map IDs are known to be dead so the
+/// frontend never receives URLs that only return errors.
+async fn tile_config_response(state: &TilesState, year: i32) -> TileUrlsResponse {
+    match state.tile_config_service.get_config_for_year(year).await {
+        Some(config) if state.freshness.is_dead(year, &config).await => {
+            let message = match state.freshness.refresh(year).await {
+                Some(job_id) => format!(
+                    "Tile URLs for year {} have expired; regenerating (job {})",
+                    year, job_id
+                ),
+                None => format!(
+                    "Tile URLs for year {} have expired; POST /tiles/generate to regenerate",
+                    year
+                ),
+            };
+            TileUrlsResponse {
+                status: "expired".to_string(),
+                data: None,
+                message: Some(message),
+            }
+        }
+        Some(config) => TileUrlsResponse {
             status: "success".to_string(),
-            data: Some(TileUrlsData {
-                lst_tile_url: lst_tile_url,
-                anomaly_tile_url: anomaly_tile_url,
-                absolute_anomaly_tile_url: absolute_tile_url,
-            }),
+            data: Some(proxied_if_local(config, year)),
             message: None,
-        })
-    } else {
-        Json(TileUrlsResponse {
+        },
+        None => TileUrlsResponse {
             status: "error".to_string(),
             data: None,
             message: Some(format!("Tile config not available yet for year {}", year)),
-        })
+        },
     }
 }
 
-async fn tile_health_check(
-    State(tile_config_service): State<Arc<TileConfigService>>,
-) -> Json<serde_json::Value> {
-    let loaded_years = tile_config_service.get_loaded_years().await;
+/// `local://` URLs only mean something to this server, so hand out the
+/// proxy paths that render them instead.
+fn proxied_if_local(mut config: TileConfig, year: i32) -> TileConfig {
+    if config.is_local() {
+        let proxy = |layer: TileLayer| format!("/tiles/{}/{}/{{z}}/{{x}}/{{y}}.png", layer.as_str(), year);
+        config.lst_tile_url = proxy(TileLayer::Lst);
+        config.anomaly_tile_url = proxy(TileLayer::Anomaly);
+        config.absolute_anomaly_tile_url = proxy(TileLayer::AbsoluteAnomaly);
+    }
+    config
+}
+
+async fn tile_health_check(State(state): State<TilesState>) -> Json<serde_json::Value> {
+    let loaded_years = state.tile_config_service.get_loaded_years().await;
     Json(serde_json::json!({
         "status": "ok",
         "tile_config_loaded_years": loaded_years,
+        "tile_config_reloads": state.tile_config_service.reload_stats(),
+        "tile_cache": state.cache.stats(),
         "endpoints": {
             "get_urls": "/tiles/urls?year={year}",
+            "configs": "/tiles/configs",
+            "config": "/tiles/configs/{year}",
+            "tile": "/tiles/{layer}/{year}/{z}/{x}/{y}.png",
+            "tilejson": "/tiles/{layer}/{year}/tilejson.json",
+            "legend": "/tiles/legend/{layer}",
+            "wmts": "/wmts/1.0.0/WMTSCapabilities.xml",
+            "freshness": "/tiles/freshness",
             "generate": "/tiles/generate",
+            "generate_events": "/tiles/generate/{year}/events",
+            "jobs": "/tiles/jobs/{id}",
+            "seed": "/tiles/seed",
+            "compare": "/tiles/compare?from={year}&to={year}&layer={layer}&bbox={west,south,east,north}",
             "health": "/tiles/health"
         }
     }))
 }
 
-// POST generate tiles AND return URLs
-// POST generate tiles AND return URLs
+// GET one tile through the on-disk cache, e.g. /tiles/lst/2024/3/5/2.png
+async fn get_tile(
+    State(cache): State<Arc<TileCache>>,
+    Path((layer, year, z, x, tile)): Path<(TileLayer, i32, u32, u32, String)>,
+    headers: HeaderMap,
+) -> Response {
+    let key = match tile.strip_suffix(".png").map(str::parse) {
+        Some(Ok(y)) => TileKey::new(layer, year, z, x, y),
+        _ => Err(TileError::InvalidTile(format!("expected {{y}}.png, got {}", tile))),
+    };
+    let result = match key {
+        Ok(key) => cache.get_tile(&key).await,
+        Err(e) => Err(e),
+    };
+
+    let tile = match result {
+        Ok(tile) => tile,
+        Err(e) => return tile_error_response(e),
+    };
+
+    let cache_status = match tile.source {
+        TileSource::Cache => "HIT",
+        TileSource::Upstream => "MISS",
+    };
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|value| value.as_bytes() == tile.etag.as_bytes());
+    let headers = [
+        (header::ETAG, tile.etag),
+        (header::CACHE_CONTROL, "public, max-age=86400".to_string()),
+        (header::HeaderName::from_static("x-tile-cache"), cache_status.to_string()),
+    ];
+
+    if not_modified {
+        (StatusCode::NOT_MODIFIED, headers).into_response()
+    } else {
+        (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "image/png")],
+            headers,
+            tile.bytes,
+        )
+            .into_response()
+    }
+}
+
+fn tile_error_response(e: TileError) -> Response {
+    let status = match e {
+        TileError::InvalidTile(_) => StatusCode::BAD_REQUEST,
+        TileError::NotConfigured(_) => StatusCode::NOT_FOUND,
+        TileError::Upstream(_) => StatusCode::BAD_GATEWAY,
+        TileError::Render(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    let body = serde_json::json!({ "status": "error", "message": e.to_string() });
+    (status, Json(body)).into_response()
+}
+
+// GET the difference layer between two years with statistics over a bbox
+async fn compare_years(
+    State(compare): State<Arc<TileCompareService>>,
+    Query(query): Query<CompareQuery>,
+) -> (StatusCode, Json<CompareResponse>) {
+    let error = |status: StatusCode, message: String| {
+        (
+            status,
+            Json(CompareResponse {
+                status: "error".to_string(),
+                message: Some(message),
+                cached: false,
+                data: None,
+            }),
+        )
+    };
+
+    let bbox = match query.bbox.as_deref().map(parse_bbox) {
+        None => [-180.0, -90.0, 180.0, 90.0],
+        Some(Ok(bbox)) => bbox,
+        Some(Err(e)) => return error(StatusCode::BAD_REQUEST, format!("Invalid bbox: {}", e)),
+    };
+    let layer = query.layer.unwrap_or(TileLayer::Lst);
+
+    match compare.compare(layer, query.from, query.to, bbox).await {
+        Ok((comparison, stats, cached)) => (
+            StatusCode::OK,
+            Json(CompareResponse {
+                status: "success".to_string(),
+                message: None,
+                cached,
+                data: Some(Comparison {
+                    comparison,
+                    legend_url: format!("/tiles/legend/{}_difference", layer.as_str()),
+                    stats,
+                }),
+            }),
+        ),
+        Err(e @ CompareError::InvalidRequest(_)) => error(StatusCode::BAD_REQUEST, e.to_string()),
+        Err(e @ CompareError::Unavailable(_)) => error(StatusCode::BAD_GATEWAY, e.to_string()),
+    }
+}
+
+// GET a difference tile rendered from local grids
+async fn get_compare_tile(
+    State(compare): State<Arc<TileCompareService>>,
+    Path((layer, from, to, z, x, tile)): Path<(TileLayer, i32, i32, u32, u32, String)>,
+) -> Response {
+    let Some(Ok(y)) = tile.strip_suffix(".png").map(str::parse) else {
+        return tile_error_response(TileError::InvalidTile(format!("expected {{y}}.png, got {}", tile)));
+    };
+    match compare.render_local_tile(layer, from, to, z, x, y).await {
+        Ok(png) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "image/png"),
+                (header::CACHE_CONTROL, "public, max-age=86400"),
+            ],
+            png,
+        )
+            .into_response(),
+        Err(e) => tile_error_response(e),
+    }
+}
+
+// GET TileJSON for MapLibre/Leaflet, pointing at the caching tile proxy
+async fn get_tilejson(
+    State(state): State<TilesState>,
+    Path((layer, year)): Path<(TileLayer, i32)>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(config) = state.tile_config_service.get_config_for_year(year).await else {
+        let body = serde_json::json!({
+            "status": "error",
+            "message": format!("Tile config not available yet for year {}", year),
+        });
+        return (StatusCode::NOT_FOUND, Json(body)).into_response();
+    };
+
+    let base = state.public_url.base(&headers);
+    Json(tilejson(config, layer, year, &base, &state.vis)).into_response()
+}
+
+fn tilejson(config: TileConfig, layer: TileLayer, year: i32, base: &str, vis: &VisRegistry) -> TileJson {
+    let period = config
+        .target_period
+        .clone()
+        .unwrap_or_else(|| year.to_string());
+    TileJson {
+        tilejson: "3.0.0",
+        name: format!("{} {}", vis.tile_layer(layer).title, period),
+        description: config.description,
+        version: "1.0.0",
+        attribution: config.data_source,
+        scheme: "xyz",
+        tiles: vec![format!("{}/tiles/{}/{}/{{z}}/{{x}}/{{y}}.png", base, layer.as_str(), year)],
+        minzoom: 0,
+        maxzoom: MAX_ZOOM,
+        bounds: [-180.0, -MAX_LATITUDE, 180.0, MAX_LATITUDE],
+        center: [0.0, 20.0, 2.0],
+    }
+}
+
+// GET the colour scale of a layer: JSON stops, or a PNG bar for `{layer}.png`
+async fn get_legend(
+    State(vis): State<Arc<VisRegistry>>,
+    Path(layer): Path<String>,
+    Query(query): Query<LegendQuery>,
+) -> Response {
+    let (name, png) = match layer.strip_suffix(".png") {
+        Some(name) => (name, true),
+        None => (layer.as_str(), false),
+    };
+    let Some(layer_vis) = vis.layer(name) else {
+        let body = serde_json::json!({
+            "status": "error",
+            "message": format!("Unknown layer {:?}; expected one of {:?}", name, vis.names()),
+        });
+        return (StatusCode::NOT_FOUND, Json(body)).into_response();
+    };
+
+    if !png {
+        return Json(LegendResponse {
+            status: "success".to_string(),
+            layer: name,
+            vis: layer_vis,
+            stops: layer_vis.stops(),
+        })
+        .into_response();
+    }
+
+    let width = query.width.clamp(2, 2048);
+    let height = query.height.clamp(1, 256);
+    let ramp = match ColorRamp::new(&layer_vis.vis_params()) {
+        Ok(ramp) => ramp,
+        Err(e) => {
+            let body = serde_json::json!({ "status": "error", "message": e.to_string() });
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+        }
+    };
+    let bar = image::RgbaImage::from_fn(width, height, |x, _| {
+        let t = x as f64 / (width - 1) as f64;
+        ramp.color(layer_vis.min + (layer_vis.max - layer_vis.min) * t)
+    });
+
+    let mut png_bytes = std::io::Cursor::new(Vec::new());
+    if let Err(e) = bar.write_to(&mut png_bytes, image::ImageFormat::Png) {
+        let body = serde_json::json!({ "status": "error", "message": e.to_string() });
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+    }
+    (
+        [
+            (header::CONTENT_TYPE, "image/png"),
+            (header::CACHE_CONTROL, "public, max-age=3600"),
+        ],
+        png_bytes.into_inner(),
+    )
+        .into_response()
+}
+
+// GET age, expiry and last probe result of every loaded year's map IDs
+async fn list_freshness(
+    State(freshness): State<Arc<TileFreshnessService>>,
+) -> Json<FreshnessResponse> {
+    Json(FreshnessResponse {
+        status: "success".to_string(),
+        data: freshness.all().await,
+    })
+}
+
+async fn get_freshness(
+    State(freshness): State<Arc<TileFreshnessService>>,
+    Path(year): Path<i32>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    match freshness.freshness(year).await {
+        Some(data) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "status": "success", "data": data })),
+        ),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "status": "error",
+                "message": format!("Tile config not available yet for year {}", year),
+            })),
+        ),
+    }
+}
+
+// POST queue tile generation and return the job
 async fn generate_tiles(
-    State(tile_config_service): State<Arc<TileConfigService>>,
+    State(jobs): State<Arc<TileJobService>>,
     AxumJson(payload): AxumJson<GenerateRequest>,
-) -> Json<GenerateResponse> {
-    use std::env;
-
+) -> (StatusCode, Json<JobResponse>) {
     let year = payload.year;
-    let script_path = format!(
-        "{}/scripts/gee_rust.py",
-        env::current_dir().unwrap().display()
-    );
-
-    // Run Python with --year
-    let output = Command::new("python")
-        .arg(script_path)
-        .arg("--year")
-        .arg(year.to_string())
-        .output();
-
-    match output {
-        Ok(out) if out.status.success() => {
-            // Reload JSON config (with retries)
-            let mut retries = 3;
-            let mut urls = None;
-
-            while retries > 0 {
-                let _ = tile_config_service.load_config_for_year(year).await;
-
-                if let Some((lst_url, anomaly_url, absolute_url)) =
-                    tile_config_service.get_tile_urls_for_year(year).await
-                {
-                    urls = Some((lst_url, anomaly_url, absolute_url));
-                    break;
-                }
+    let (job, created) = jobs.submit(year).await;
 
-                tokio::time::sleep(std::time::Duration::from_millis(500)).await;
-                retries -= 1;
-            }
+    if created {
+        (
+            StatusCode::ACCEPTED,
+            Json(JobResponse {
+                status: "accepted".to_string(),
+                message: Some(format!("Tile generation queued for year {}", year)),
+                data: Some(job),
+            }),
+        )
+    } else {
+        (
+            StatusCode::OK,
+            Json(JobResponse {
+                status: "accepted".to_string(),
+                message: Some(format!("Tile generation already in progress for year {}", year)),
+                data: Some(job),
+            }),
+        )
+    }
+}
+
+// GET progress of the year's generation job as Server-Sent Events. Events
+// emitted before the client connected are replayed first; the stream ends
+// after the `completed`, `failed` or `cancelled` event.
+async fn generation_events(
+    State(jobs): State<Arc<TileJobService>>,
+    Path(year): Path<i32>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, (StatusCode, Json<JobResponse>)> {
+    let Some(JobSubscription { history, live, .. }) = jobs.subscribe_year(year).await else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(JobResponse {
+                status: "error".to_string(),
+                message: Some(format!("No tile generation job for year {}", year)),
+                data: None,
+            }),
+        ));
+    };
 
-            if let Some((lst_tile_url, anomaly_tile_url, absolute_tile_url)) = urls {
-                Json(GenerateResponse {
-                    status: "success".to_string(),
-                    message: format!("Tiles generated for year {}", year),
-                    data: Some(TileUrlsData {
-                        lst_tile_url: lst_tile_url,
-                        anomaly_tile_url: anomaly_tile_url,
-                        absolute_anomaly_tile_url: absolute_tile_url,
-                    }),
-                })
-            } else {
-                Json(GenerateResponse {
-                    status: "error".to_string(),
-                    message: format!("Tiles generated but URLs not found for year {}", year),
-                    data: None,
-                })
+    let live = stream::unfold(live, |receiver| async move {
+        let mut receiver = receiver?;
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let next = (!event.is_terminal()).then_some(receiver);
+                    return Some((event, next));
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
             }
         }
-        Ok(out) => Json(GenerateResponse {
-            status: "error".to_string(),
-            message: format!("Python failed: {}", String::from_utf8_lossy(&out.stderr)),
-            data: None,
-        }),
-        Err(e) => Json(GenerateResponse {
+    });
+
+    let events = stream::iter(history)
+        .chain(live)
+        .map(|event| Event::default().event(event.name()).json_data(&event));
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+async fn list_jobs(State(jobs): State<Arc<TileJobService>>) -> Json<JobsResponse> {
+    Json(JobsResponse {
+        status: "success".to_string(),
+        data: jobs.list().await,
+    })
+}
+
+async fn get_job(
+    State(jobs): State<Arc<TileJobService>>,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<JobResponse>) {
+    match jobs.get(id).await {
+        Some(job) => (
+            StatusCode::OK,
+            Json(JobResponse {
+                status: "success".to_string(),
+                message: None,
+                data: Some(job),
+            }),
+        ),
+        None => job_not_found(id),
+    }
+}
+
+async fn cancel_job(
+    State(jobs): State<Arc<TileJobService>>,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<JobResponse>) {
+    match jobs.cancel(id).await {
+        Ok(job) => (
+            StatusCode::OK,
+            Json(JobResponse {
+                status: "success".to_string(),
+                message: Some(format!("Job {} cancelled", id)),
+                data: Some(job),
+            }),
+        ),
+        Err(CancelError::NotFound) => job_not_found(id),
+        Err(CancelError::AlreadyFinished(state)) => (
+            StatusCode::CONFLICT,
+            Json(JobResponse {
+                status: "error".to_string(),
+                message: Some(format!("Job {} already finished ({:?})", id, state)),
+                data: None,
+            }),
+        ),
+    }
+}
+
+fn job_not_found(id: Uuid) -> (StatusCode, Json<JobResponse>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(JobResponse {
             status: "error".to_string(),
-            message: format!("Failed to run Python: {}", e),
+            message: Some(format!("Job {} not found", id)),
             data: None,
         }),
+    )
+}
+
+// POST warm the tile cache for a bbox and zoom range; poll /tiles/seed/{id}
+async fn start_seed(
+    State(seeder): State<Arc<TileSeeder>>,
+    AxumJson(request): AxumJson<SeedRequest>,
+) -> (StatusCode, Json<SeedResponse>) {
+    match seeder.start(request).await {
+        Ok(run) => (
+            StatusCode::ACCEPTED,
+            Json(SeedResponse {
+                status: "accepted".to_string(),
+                message: Some(format!("Seeding {} tiles", run.totals.total)),
+                data: Some(run),
+            }),
+        ),
+        Err(message) => (
+            StatusCode::BAD_REQUEST,
+            Json(SeedResponse {
+                status: "error".to_string(),
+                message: Some(message),
+                data: None,
+            }),
+        ),
+    }
+}
+
+async fn list_seeds(State(seeder): State<Arc<TileSeeder>>) -> Json<SeedsResponse> {
+    Json(SeedsResponse {
+        status: "success".to_string(),
+        data: seeder.list(),
+    })
+}
+
+async fn get_seed(
+    State(seeder): State<Arc<TileSeeder>>,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<SeedResponse>) {
+    match seeder.get(id) {
+        Some(run) => (
+            StatusCode::OK,
+            Json(SeedResponse {
+                status: "success".to_string(),
+                message: None,
+                data: Some(run),
+            }),
+        ),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(SeedResponse {
+                status: "error".to_string(),
+                message: Some(format!("Seed {} not found", id)),
+                data: None,
+            }),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::tile_jobs::tests::{service, wait_for};
+    use axum::response::IntoResponse;
+
+    #[tokio::test]
+    async fn generation_events_replay_history_then_follow_the_job() {
+        let dir = std::env::temp_dir().join(format!("tile-events-{}", Uuid::new_v4()));
+        let jobs = service(&dir, 1);
+        let (job, _) = jobs.submit(2024).await;
+        wait_for(&jobs, job.id, |job| job.stdout.contains("MODIS")).await;
+
+        // Subscribe mid-run: the first stages are replayed, the rest arrive live
+        let response = generation_events(State(jobs.clone()), Path(2024))
+            .await
+            .ok()
+            .unwrap()
+            .into_response();
+        wait_for(&jobs, job.id, |job| job.stdout.contains("ERA5")).await;
+        jobs.cancel(job.id).await.ok().unwrap();
+
+        // The stream ends after the terminal event
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let events: Vec<&str> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("event: "))
+            .collect();
+        assert_eq!(events, ["stage", "stage", "stage", "cancelled"], "{}", body);
+        let started = body.find("\"stage\":\"started\"").unwrap();
+        let modis = body.find("\"stage\":\"modis\"").unwrap();
+        let era5 = body.find("\"stage\":\"era5\"").unwrap();
+        assert!(started < modis && modis < era5);
+
+        // Unknown years are a 404
+        let missing = generation_events(State(jobs), Path(1990)).await.err().unwrap();
+        assert_eq!(missing.0, StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn legend_serves_stops_a_clamped_bar_and_404s_unknown_layers() {
+        let vis = Arc::new(VisRegistry::builtin());
+        let legend = |layer: &str, width: u32, height: u32| {
+            get_legend(State(vis.clone()), Path(layer.to_string()), Query(LegendQuery { width, height }))
+        };
+
+        let response = legend("anomaly", 256, 16).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["status"], "success");
+        assert_eq!(json["layer"], "anomaly");
+        assert_eq!(
+            json["stops"],
+            serde_json::json!([
+                { "value": -3.0, "color": "#0000ff" },
+                { "value": 0.0, "color": "#ffffff" },
+                { "value": 3.0, "color": "#ff0000" },
+            ])
+        );
+
+        // Out-of-range sizes are clamped to 2048x256
+        let response = legend("lst.png", 10_000, 1_000).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let bar = image::load_from_memory(&body).unwrap();
+        assert_eq!((bar.width(), bar.height()), (2048, 256));
+
+        let response = legend("rainfall", 256, 16).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = legend("rainfall.png", 256, 16).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn tilejson_points_at_the_caching_proxy() {
+        let vis = VisRegistry::builtin();
+        let config = TileConfig {
+            lst_tile_url: "https://earthengine.googleapis.com/v1/maps/abc/tiles/{z}/{x}/{y}".to_string(),
+            anomaly_tile_url: String::new(),
+            absolute_anomaly_tile_url: String::new(),
+            generated_at: None,
+            target_period: Some("2025-01..2025-07".to_string()),
+            climatology_period: None,
+            data_source: Some("MODIS/061/MOD11A1".to_string()),
+            description: None,
+        };
+
+        let json = serde_json::to_value(tilejson(config, TileLayer::Lst, 2025, "https://maps.example.org", &vis)).unwrap();
+        assert_eq!(json["tilejson"], "3.0.0");
+        assert_eq!(json["scheme"], "xyz");
+        assert_eq!(
+            json["name"],
+            format!("{} 2025-01..2025-07", vis.tile_layer(TileLayer::Lst).title)
+        );
+        assert_eq!(json["attribution"], "MODIS/061/MOD11A1");
+        assert!(json.get("description").is_none());
+        assert_eq!(
+            json["tiles"],
+            serde_json::json!(["https://maps.example.org/tiles/lst/2025/{z}/{x}/{y}.png"])
+        );
+        assert_eq!(json["maxzoom"], MAX_ZOOM);
+        assert_eq!(json["bounds"][1], -MAX_LATITUDE);
     }
 }
@@ -0,0 +1,185 @@
+// src/api/wmts.rs
+//
+// OGC WMTS 1.0.0 capabilities for QGIS and other GIS clients. Each tile layer
+// is published once, with the loaded years as its `Time` dimension; tiles
+// resolve to the caching proxy under `/tiles`.
+use crate::api::PublicUrl;
+use crate::models::tile_config::TileLayer;
+use crate::services::tile_cache::{MAX_LATITUDE, MAX_ZOOM};
+use crate::services::tile_config_service::TileConfigService;
+use crate::services::vis_registry::VisRegistry;
+use axum::{
+    extract::{FromRef, State},
+    http::{header, HeaderMap},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// Scale denominator of zoom 0 in the GoogleMapsCompatible tile matrix set
+const ZOOM_0_SCALE_DENOMINATOR: f64 = 559_082_264.028_717_8;
+
+/// Half the width of the Web Mercator plane in metres
+const MERCATOR_HALF_EXTENT: f64 = 20_037_508.342_789_2;
+
+#[derive(Clone, FromRef)]
+pub struct WmtsState {
+    pub tile_config_service: Arc<TileConfigService>,
+    pub public_url: PublicUrl,
+    pub vis: Arc<VisRegistry>,
+}
+
+pub fn routes(state: WmtsState) -> Router {
+    Router::new()
+        .route("/1.0.0/WMTSCapabilities.xml", get(capabilities))
+        .with_state(state)
+}
+
+// GET WMTS capabilities listing every layer with its loaded years
+async fn capabilities(
+    State(state): State<WmtsState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let base = state.public_url.base(&headers);
+    let years = state
+        .tile_config_service
+        .get_all_configs()
+        .await
+        .into_iter()
+        .map(|(year, _)| year)
+        .collect::<Vec<_>>();
+
+    (
+        [(header::CONTENT_TYPE, "application/xml")],
+        capabilities_xml(&base, &years, &state.vis),
+    )
+}
+
+fn capabilities_xml(base: &str, years: &[i32], vis: &VisRegistry) -> String {
+    let mut xml = String::new();
+    xml.push_str(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<Capabilities xmlns="http://www.opengis.net/wmts/1.0" xmlns:ows="http://www.opengis.net/ows/1.1" xmlns:xlink="http://www.w3.org/1999/xlink" version="1.0.0">
+  <ows:ServiceIdentification>
+    <ows:Title>Climate Time Machine</ows:Title>
+    <ows:ServiceType>OGC WMTS</ows:ServiceType>
+    <ows:ServiceTypeVersion>1.0.0</ows:ServiceTypeVersion>
+  </ows:ServiceIdentification>
+  <Contents>
+"#,
+    );
+
+    if let Some(latest) = years.iter().max() {
+        for layer in TileLayer::ALL {
+            let _ = write!(
+                xml,
+                r#"    <Layer>
+      <ows:Title>{title}</ows:Title>
+      <ows:Identifier>{id}</ows:Identifier>
+      <ows:WGS84BoundingBox>
+        <ows:LowerCorner>-180 {min_lat}</ows:LowerCorner>
+        <ows:UpperCorner>180 {max_lat}</ows:UpperCorner>
+      </ows:WGS84BoundingBox>
+      <Style isDefault="true">
+        <ows:Identifier>default</ows:Identifier>
+      </Style>
+      <Format>image/png</Format>
+      <Dimension>
+        <ows:Identifier>Time</ows:Identifier>
+        <Default>{latest}</Default>
+"#,
+                title = escape(&vis.tile_layer(layer).title),
+                id = layer.as_str(),
+                min_lat = -MAX_LATITUDE,
+                max_lat = MAX_LATITUDE,
+                latest = latest,
+            );
+            for year in years {
+                let _ = writeln!(xml, "        <Value>{}</Value>", year);
+            }
+            let _ = write!(
+                xml,
+                r#"      </Dimension>
+      <TileMatrixSetLink>
+        <TileMatrixSet>GoogleMapsCompatible</TileMatrixSet>
+      </TileMatrixSetLink>
+      <ResourceURL format="image/png" resourceType="tile" template="{base}/tiles/{id}/{{Time}}/{{TileMatrix}}/{{TileCol}}/{{TileRow}}.png"/>
+    </Layer>
+"#,
+                base = escape(base),
+                id = layer.as_str(),
+            );
+        }
+    }
+
+    xml.push_str(
+        r#"    <TileMatrixSet>
+      <ows:Identifier>GoogleMapsCompatible</ows:Identifier>
+      <ows:SupportedCRS>urn:ogc:def:crs:EPSG::3857</ows:SupportedCRS>
+      <WellKnownScaleSet>urn:ogc:def:wkss:OGC:1.0:GoogleMapsCompatible</WellKnownScaleSet>
+"#,
+    );
+    for z in 0..=MAX_ZOOM {
+        let size = 1u64 << z;
+        let _ = write!(
+            xml,
+            r#"      <TileMatrix>
+        <ows:Identifier>{z}</ows:Identifier>
+        <ScaleDenominator>{scale}</ScaleDenominator>
+        <TopLeftCorner>{left} {top}</TopLeftCorner>
+        <TileWidth>256</TileWidth>
+        <TileHeight>256</TileHeight>
+        <MatrixWidth>{size}</MatrixWidth>
+        <MatrixHeight>{size}</MatrixHeight>
+      </TileMatrix>
+"#,
+            z = z,
+            scale = ZOOM_0_SCALE_DENOMINATOR / size as f64,
+            left = -MERCATOR_HALF_EXTENT,
+            top = MERCATOR_HALF_EXTENT,
+            size = size,
+        );
+    }
+    xml.push_str("    </TileMatrixSet>\n  </Contents>\n</Capabilities>\n");
+    xml
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_text_and_attributes() {
+        assert_eq!(
+            escape(r#"Heat & "anomaly" <°C>"#),
+            "Heat &amp; &quot;anomaly&quot; &lt;°C&gt;"
+        );
+
+        let xml = capabilities_xml("https://maps.example.org/?a=1&b=2", &[2023, 2024], &VisRegistry::builtin());
+        assert!(xml.contains(
+            r#"template="https://maps.example.org/?a=1&amp;b=2/tiles/lst/{Time}/{TileMatrix}/{TileCol}/{TileRow}.png""#
+        ));
+        assert!(!xml.contains("a=1&b=2"));
+        assert!(xml.contains("<Default>2024</Default>"));
+        assert!(xml.contains("<Value>2023</Value>"));
+        assert_eq!(xml.matches("<Layer>").count(), TileLayer::ALL.len());
+        assert_eq!(xml.matches("<TileMatrix>").count(), MAX_ZOOM as usize + 1);
+    }
+
+    #[test]
+    fn lists_no_layers_before_any_year_is_loaded() {
+        let xml = capabilities_xml("http://localhost:3000", &[], &VisRegistry::builtin());
+        assert!(!xml.contains("<Layer>"));
+        assert!(xml.contains("<TileMatrixSet>"));
+    }
+}
@@ -0,0 +1,764 @@
+// src/config.rs
+//
+// Runtime configuration. Values are layered, lowest priority first:
+// built-in defaults, a TOML file, `CLIMATE_*` environment variables
+// (including those from `.env`) and finally command-line flags.
+use axum::http::HeaderValue;
+use crate::models::heat_alert::HeatMetric;
+use crate::models::tile_config::TileLayer;
+use crate::secrets::Secret;
+use crate::services::tile_seeder::SeedRequest;
+use clap::{Args, Parser, Subcommand};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Config file picked up from the working directory when `--config` is not given.
+const DEFAULT_CONFIG_FILE: &str = "climate.toml";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read config file {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to parse config file {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("invalid value for `{key}`: {message}")]
+    Invalid { key: &'static str, message: String },
+}
+
+// -------------------- Config Structs --------------------
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub server: ServerConfig,
+    pub tiles: TilesConfig,
+    pub earth_engine: EarthEngineConfig,
+    pub scheduler: SchedulerConfig,
+    pub heat_alert: HeatAlertConfig,
+    pub cities: CitiesConfig,
+    pub weather: WeatherConfig,
+    pub secrets: SecretsConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_addr: String,
+    pub cors_origins: Vec<String>,
+    /// Externally visible base URL used in TileJSON and WMTS documents;
+    /// taken from the request's `Host` header when unset
+    pub public_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TilesConfig {
+    /// Directory holding the generated `tile_config_{year}.json` files
+    pub config_dir: PathBuf,
+    pub generator: TileGenerator,
+    /// Generation jobs allowed to run at once; the rest wait in the queue
+    pub max_concurrent_jobs: usize,
+    pub python_bin: String,
+    pub generator_script: PathBuf,
+    /// How long Earth Engine map IDs stay valid after generation
+    pub map_ttl_secs: u64,
+    /// Regenerate this long before a year's map IDs expire
+    pub refresh_margin_secs: u64,
+    /// How often the tile probe and refresh check runs
+    pub freshness_check_secs: u64,
+    /// Regenerate expiring years in the background
+    pub auto_refresh: bool,
+    /// Root of the proxied tile store (`{layer}/{year}/{z}/{x}/{y}`)
+    pub cache_dir: PathBuf,
+    /// Size bound of the tile store; least recently used tiles go first
+    pub cache_max_mb: u64,
+    /// Palettes and per-layer min/max shared by generation, rendering and legends
+    pub viz_file: PathBuf,
+    /// Directory of `.npy` grids served through `local://` tile URLs
+    pub grid_dir: PathBuf,
+    /// Memory bound of the loaded grids; least recently used grids go first
+    pub grid_cache_max_mb: u64,
+    /// Tiles downloaded at once while seeding
+    pub seed_concurrency: usize,
+    /// Retries per tile when the tile server errors during seeding
+    pub seed_retries: u32,
+}
+
+/// How `POST /tiles/generate` produces a year's tiles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum TileGenerator {
+    /// Run `scripts/gee_rust.py`
+    #[default]
+    Python,
+    /// Call the Earth Engine REST API from Rust
+    EarthEngine,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EarthEngineConfig {
+    /// REST endpoint; point this at a stub server in tests
+    pub api_url: String,
+    /// Cloud project; defaults to the service account key's `project_id`
+    pub project: Option<String>,
+    pub service_account_key: PathBuf,
+    /// Pre-issued OAuth token used instead of the service account (development only);
+    /// also read from the `ee_access_token` secret
+    pub access_token: Option<Secret>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchedulerConfig {
+    pub heat_alert_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeatAlertConfig {
+    /// Heat-stress metric severities are judged on
+    pub metric: HeatMetric,
+    /// Past years whose daily maxima around today's date give each city's
+    /// percentiles; 0 uses the absolute thresholds only
+    pub climatology_years: u32,
+    /// Cities polled at once
+    pub concurrency: usize,
+    /// Retries per city when every weather provider fails
+    pub retries: u32,
+    /// Append-only JSON-lines file of every run's readings
+    pub history_file: PathBuf,
+    /// Readings older than this are dropped at startup
+    pub history_days: u32,
+    /// Consecutive hot days before a heatwave event opens
+    pub heatwave_min_days: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CitiesConfig {
+    /// Directory of `.geojson`/`.json`/`.csv` city files watched for heat alerts;
+    /// the built-in list is used when it holds none
+    pub dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WeatherConfig {
+    /// Providers for current conditions, forecasts and history, tried in order:
+    /// the first is the primary, the rest are fallbacks
+    /// `openweathermap` needs the `openweathermap_api_key` secret
+    pub providers: Vec<WeatherProviderKind>,
+    /// Per-request timeout before moving on to the next provider
+    pub timeout_secs: u64,
+    /// Request caps shared by every caller; the free tiers allow 600 and 60
+    pub open_meteo_per_minute: u32,
+    pub openweathermap_per_minute: u32,
+    /// Longest an interactive request waits for a provider's cap before
+    /// falling through to the next provider; the scheduler always waits
+    pub interactive_max_wait_ms: u64,
+    /// Temperature (°C) reported by the `mock` provider
+    pub mock_temperature: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecretsConfig {
+    /// Directory of mounted secret files, one file per secret
+    pub dir: PathBuf,
+    /// Secrets sealed with `climate-backend secrets encrypt`; opened with `CLIMATE_SECRETS_KEY`
+    pub encrypted_file: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum WeatherProviderKind {
+    #[value(name = "open_meteo")]
+    OpenMeteo,
+    #[serde(rename = "openweathermap")]
+    #[value(name = "openweathermap")]
+    OpenWeatherMap,
+    /// Fixed temperature, no network access
+    Mock,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_addr: "127.0.0.1:3000".to_string(),
+            cors_origins: vec!["http://localhost:8080".to_string()],
+            public_url: None,
+        }
+    }
+}
+
+impl Default for TilesConfig {
+    fn default() -> Self {
+        Self {
+            config_dir: PathBuf::from("tiles"),
+            generator: TileGenerator::default(),
+            max_concurrent_jobs: 1,
+            python_bin: "python".to_string(),
+            generator_script: PathBuf::from("scripts/gee_rust.py"),
+            map_ttl_secs: 4 * 60 * 60,
+            refresh_margin_secs: 30 * 60,
+            freshness_check_secs: 5 * 60,
+            auto_refresh: true,
+            cache_dir: PathBuf::from("tile_cache"),
+            cache_max_mb: 512,
+            viz_file: PathBuf::from("gee/viz.json"),
+            grid_dir: PathBuf::from("grids"),
+            grid_cache_max_mb: 1024,
+            seed_concurrency: 8,
+            seed_retries: 3,
+        }
+    }
+}
+
+impl Default for EarthEngineConfig {
+    fn default() -> Self {
+        Self {
+            api_url: "https://earthengine.googleapis.com".to_string(),
+            project: None,
+            service_account_key: PathBuf::from("service_account.json"),
+            access_token: None,
+        }
+    }
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            heat_alert_interval_secs: 6 * 3600, // 6 hours
+        }
+    }
+}
+
+impl Default for HeatAlertConfig {
+    fn default() -> Self {
+        Self {
+            metric: HeatMetric::HeatIndex,
+            climatology_years: 10,
+            concurrency: 8,
+            retries: 2,
+            history_file: PathBuf::from("data/heat_alert_history.jsonl"),
+            history_days: 365,
+            heatwave_min_days: 3,
+        }
+    }
+}
+
+impl Default for CitiesConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("cities"),
+        }
+    }
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        Self {
+            providers: vec![WeatherProviderKind::OpenMeteo, WeatherProviderKind::OpenWeatherMap],
+            timeout_secs: 10,
+            open_meteo_per_minute: 600,
+            openweathermap_per_minute: 60,
+            interactive_max_wait_ms: 2000,
+            mock_temperature: 30.0,
+        }
+    }
+}
+
+impl Default for SecretsConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("/run/secrets"),
+            encrypted_file: PathBuf::from("secrets.enc"),
+        }
+    }
+}
+
+// -------------------- Command Line --------------------
+/// Command-line flags. Every flag can also be set through its `CLIMATE_*`
+/// environment variable; flags win over the environment.
+#[derive(Debug, Parser)]
+#[command(name = "climate-backend", about = "Climate Time Machine backend")]
+pub struct Cli {
+    /// Path to a TOML config file (defaults to ./climate.toml when present)
+    #[arg(long, env = "CLIMATE_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Address the HTTP server binds to, e.g. 0.0.0.0:3000
+    #[arg(long, env = "CLIMATE_BIND_ADDR")]
+    pub bind_addr: Option<String>,
+
+    /// Allowed CORS origins (comma separated)
+    #[arg(long, env = "CLIMATE_CORS_ORIGINS", value_delimiter = ',')]
+    pub cors_origins: Option<Vec<String>>,
+
+    /// Public base URL of this server, e.g. https://climate.example.org
+    #[arg(long, env = "CLIMATE_PUBLIC_URL")]
+    pub public_url: Option<String>,
+
+    /// Directory containing tile_config_{year}.json files
+    #[arg(long, env = "CLIMATE_TILES_DIR")]
+    pub tiles_dir: Option<PathBuf>,
+
+    /// Tile generator backend
+    #[arg(long, env = "CLIMATE_TILE_GENERATOR", value_enum)]
+    pub tile_generator: Option<TileGenerator>,
+
+    /// Earth Engine REST API base URL
+    #[arg(long, env = "CLIMATE_EE_API_URL")]
+    pub ee_api_url: Option<String>,
+
+    /// Google Cloud project used for Earth Engine requests
+    #[arg(long, env = "CLIMATE_EE_PROJECT")]
+    pub ee_project: Option<String>,
+
+    /// Path to the Earth Engine service account key (JSON)
+    #[arg(long, env = "CLIMATE_EE_SERVICE_ACCOUNT_KEY")]
+    pub ee_service_account_key: Option<PathBuf>,
+
+    /// Pre-issued OAuth access token, bypassing the service account
+    #[arg(long, env = "CLIMATE_EE_ACCESS_TOKEN", hide_env_values = true)]
+    pub ee_access_token: Option<Secret>,
+
+    /// Seconds between heat-alert refreshes
+    #[arg(long, env = "CLIMATE_HEAT_ALERT_INTERVAL_SECS")]
+    pub heat_alert_interval_secs: Option<u64>,
+
+    /// Directory of city catalogue files (GeoJSON or CSV)
+    #[arg(long, env = "CLIMATE_CITIES_DIR")]
+    pub cities_dir: Option<PathBuf>,
+
+    /// Weather providers in fallback order (comma separated)
+    #[arg(long, env = "CLIMATE_WEATHER_PROVIDERS", value_delimiter = ',', value_enum)]
+    pub weather_providers: Option<Vec<WeatherProviderKind>>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Runs instead of the HTTP server when given.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Download the tiles covering a bounding box into the tile cache
+    Seed(SeedArgs),
+    /// Manage the encrypted secrets file
+    #[command(subcommand)]
+    Secrets(SecretsCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SecretsCommand {
+    /// Print a new random key to use as CLIMATE_SECRETS_KEY
+    GenerateKey,
+    /// Encrypt a JSON object of secrets into secrets.encrypted_file with CLIMATE_SECRETS_KEY
+    Encrypt {
+        #[arg(long)]
+        input: PathBuf,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct SeedArgs {
+    #[arg(long, value_enum)]
+    pub layer: TileLayer,
+
+    #[arg(long)]
+    pub year: i32,
+
+    /// west,south,east,north in degrees
+    #[arg(long, value_parser = parse_bbox, allow_hyphen_values = true)]
+    pub bbox: [f64; 4],
+
+    #[arg(long, default_value_t = 0)]
+    pub min_zoom: u32,
+
+    #[arg(long)]
+    pub max_zoom: u32,
+}
+
+pub fn parse_bbox(value: &str) -> Result<[f64; 4], String> {
+    let parts = value
+        .split(',')
+        .map(|part| part.trim().parse::<f64>().map_err(|e| e.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    parts
+        .try_into()
+        .map_err(|_| "expected four comma-separated numbers".to_string())
+}
+
+impl From<SeedArgs> for SeedRequest {
+    fn from(args: SeedArgs) -> Self {
+        SeedRequest {
+            layer: args.layer,
+            year: args.year,
+            bbox: args.bbox,
+            min_zoom: args.min_zoom,
+            max_zoom: args.max_zoom,
+        }
+    }
+}
+
+impl AppConfig {
+    /// Load `.env`, parse the command line and build a validated config,
+    /// returning the subcommand to run, if any.
+    pub fn load() -> Result<(Self, Option<Command>), ConfigError> {
+        let _ = dotenvy::dotenv();
+        let mut cli = Cli::parse();
+        let command = cli.command.take();
+        Ok((Self::from_cli(cli)?, command))
+    }
+
+    /// Build a validated config from already-parsed flags.
+    pub fn from_cli(cli: Cli) -> Result<Self, ConfigError> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Self::default(),
+        };
+
+        config.apply_overrides(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let raw = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&raw).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    fn apply_overrides(&mut self, cli: Cli) {
+        if let Some(bind_addr) = cli.bind_addr {
+            self.server.bind_addr = bind_addr;
+        }
+        if let Some(cors_origins) = cli.cors_origins {
+            self.server.cors_origins = cors_origins;
+        }
+        if let Some(public_url) = cli.public_url {
+            self.server.public_url = Some(public_url);
+        }
+        if let Some(tiles_dir) = cli.tiles_dir {
+            self.tiles.config_dir = tiles_dir;
+        }
+        if let Some(generator) = cli.tile_generator {
+            self.tiles.generator = generator;
+        }
+        if let Some(api_url) = cli.ee_api_url {
+            self.earth_engine.api_url = api_url;
+        }
+        if let Some(project) = cli.ee_project {
+            self.earth_engine.project = Some(project);
+        }
+        if let Some(key) = cli.ee_service_account_key {
+            self.earth_engine.service_account_key = key;
+        }
+        if let Some(token) = cli.ee_access_token {
+            self.earth_engine.access_token = Some(token);
+        }
+        if let Some(secs) = cli.heat_alert_interval_secs {
+            self.scheduler.heat_alert_interval_secs = secs;
+        }
+        if let Some(dir) = cli.cities_dir {
+            self.cities.dir = dir;
+        }
+        if let Some(providers) = cli.weather_providers {
+            self.weather.providers = providers;
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.bind_addr()?;
+        self.cors_origins()?;
+        if let Some(public_url) = &self.server.public_url {
+            validate_http_url("server.public_url", public_url)?;
+        }
+
+        if !self.tiles.config_dir.is_dir() {
+            return Err(ConfigError::Invalid {
+                key: "tiles.config_dir",
+                message: format!("{} is not a directory", self.tiles.config_dir.display()),
+            });
+        }
+
+        if self.tiles.max_concurrent_jobs == 0 {
+            return Err(ConfigError::Invalid {
+                key: "tiles.max_concurrent_jobs",
+                message: "must be greater than zero".to_string(),
+            });
+        }
+
+        if self.tiles.refresh_margin_secs >= self.tiles.map_ttl_secs {
+            return Err(ConfigError::Invalid {
+                key: "tiles.refresh_margin_secs",
+                message: "must be shorter than tiles.map_ttl_secs".to_string(),
+            });
+        }
+
+        if self.tiles.cache_max_mb == 0 {
+            return Err(ConfigError::Invalid {
+                key: "tiles.cache_max_mb",
+                message: "must be greater than zero".to_string(),
+            });
+        }
+
+        if self.tiles.grid_cache_max_mb == 0 {
+            return Err(ConfigError::Invalid {
+                key: "tiles.grid_cache_max_mb",
+                message: "must be greater than zero".to_string(),
+            });
+        }
+
+        if self.tiles.seed_concurrency == 0 {
+            return Err(ConfigError::Invalid {
+                key: "tiles.seed_concurrency",
+                message: "must be greater than zero".to_string(),
+            });
+        }
+
+        if self.tiles.freshness_check_secs == 0 {
+            return Err(ConfigError::Invalid {
+                key: "tiles.freshness_check_secs",
+                message: "must be greater than zero".to_string(),
+            });
+        }
+
+        validate_http_url("earth_engine.api_url", &self.earth_engine.api_url)?;
+
+        if self.earth_engine.access_token.is_some() && self.earth_engine.project.is_none() {
+            return Err(ConfigError::Invalid {
+                key: "earth_engine.project",
+                message: "required when earth_engine.access_token is set".to_string(),
+            });
+        }
+
+        if self.scheduler.heat_alert_interval_secs == 0 {
+            return Err(ConfigError::Invalid {
+                key: "scheduler.heat_alert_interval_secs",
+                message: "must be greater than zero".to_string(),
+            });
+        }
+
+        if self.heat_alert.climatology_years > 40 {
+            return Err(ConfigError::Invalid {
+                key: "heat_alert.climatology_years",
+                message: "must be at most 40".to_string(),
+            });
+        }
+
+        if self.heat_alert.history_days == 0 {
+            return Err(ConfigError::Invalid {
+                key: "heat_alert.history_days",
+                message: "must be greater than zero".to_string(),
+            });
+        }
+
+        if self.heat_alert.heatwave_min_days == 0 {
+            return Err(ConfigError::Invalid {
+                key: "heat_alert.heatwave_min_days",
+                message: "must be greater than zero".to_string(),
+            });
+        }
+
+        if self.heat_alert.concurrency == 0 {
+            return Err(ConfigError::Invalid {
+                key: "heat_alert.concurrency",
+                message: "must be greater than zero".to_string(),
+            });
+        }
+
+        if self.weather.providers.is_empty() {
+            return Err(ConfigError::Invalid {
+                key: "weather.providers",
+                message: "at least one provider is required".to_string(),
+            });
+        }
+
+        if self.weather.timeout_secs == 0 {
+            return Err(ConfigError::Invalid {
+                key: "weather.timeout_secs",
+                message: "must be greater than zero".to_string(),
+            });
+        }
+
+        for (key, per_minute) in [
+            ("weather.open_meteo_per_minute", self.weather.open_meteo_per_minute),
+            ("weather.openweathermap_per_minute", self.weather.openweathermap_per_minute),
+        ] {
+            if per_minute == 0 {
+                return Err(ConfigError::Invalid {
+                    key,
+                    message: "must be greater than zero".to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn bind_addr(&self) -> Result<SocketAddr, ConfigError> {
+        self.server
+            .bind_addr
+            .parse()
+            .map_err(|e| ConfigError::Invalid {
+                key: "server.bind_addr",
+                message: format!("{:?} is not a socket address ({})", self.server.bind_addr, e),
+            })
+    }
+
+    pub fn cors_origins(&self) -> Result<Vec<HeaderValue>, ConfigError> {
+        if self.server.cors_origins.is_empty() {
+            return Err(ConfigError::Invalid {
+                key: "server.cors_origins",
+                message: "at least one origin is required".to_string(),
+            });
+        }
+
+        self.server
+            .cors_origins
+            .iter()
+            .map(|origin| {
+                validate_http_url("server.cors_origins", origin)?;
+                origin.parse::<HeaderValue>().map_err(|e| ConfigError::Invalid {
+                    key: "server.cors_origins",
+                    message: format!("{:?}: {}", origin, e),
+                })
+            })
+            .collect()
+    }
+
+    pub fn heat_alert_interval(&self) -> Duration {
+        Duration::from_secs(self.scheduler.heat_alert_interval_secs)
+    }
+}
+
+fn validate_http_url(key: &'static str, value: &str) -> Result<(), ConfigError> {
+    if value.starts_with("http://") || value.starts_with("https://") {
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid {
+            key,
+            message: format!("{:?} must start with http:// or https://", value),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("climate-config-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn layers_defaults_file_environment_and_flags() {
+        let dir = temp_dir();
+        let file = dir.join("climate.toml");
+        std::fs::write(
+            &file,
+            format!(
+                r#"
+                [server]
+                bind_addr = "127.0.0.1:4000"
+                public_url = "https://file.example.org"
+
+                [tiles]
+                config_dir = {:?}
+
+                [scheduler]
+                heat_alert_interval_secs = 60
+                "#,
+                dir
+            ),
+        )
+        .unwrap();
+
+        // The environment beats the file, flags beat both
+        std::env::set_var("CLIMATE_HEAT_ALERT_INTERVAL_SECS", "900");
+        std::env::set_var("CLIMATE_BIND_ADDR", "127.0.0.1:4001");
+        let cli = Cli::try_parse_from([
+            "climate-backend",
+            "--config",
+            file.to_str().unwrap(),
+            "--bind-addr",
+            "0.0.0.0:5000",
+        ])
+        .unwrap();
+        std::env::remove_var("CLIMATE_HEAT_ALERT_INTERVAL_SECS");
+        std::env::remove_var("CLIMATE_BIND_ADDR");
+        let config = AppConfig::from_cli(cli).unwrap();
+
+        assert_eq!(config.server.bind_addr, "0.0.0.0:5000");
+        assert_eq!(config.scheduler.heat_alert_interval_secs, 900);
+        assert_eq!(config.server.public_url.as_deref(), Some("https://file.example.org"));
+        assert_eq!(config.tiles.config_dir, dir);
+        // Untouched keys keep their defaults
+        assert_eq!(config.server.cors_origins, ServerConfig::default().cors_origins);
+        assert_eq!(config.heat_alert.history_days, 365);
+
+        // Unknown keys are rejected rather than ignored
+        std::fs::write(&file, "[server]\nbind_adress = \"127.0.0.1:3000\"\n").unwrap();
+        let cli = Cli::try_parse_from(["climate-backend", "--config", file.to_str().unwrap()]).unwrap();
+        assert!(matches!(AppConfig::from_cli(cli), Err(ConfigError::Parse { .. })));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    type Breakage = fn(&mut AppConfig);
+
+    #[test]
+    fn validate_names_the_offending_key() {
+        let dir = temp_dir();
+        let valid = AppConfig {
+            tiles: TilesConfig {
+                config_dir: dir.clone(),
+                ..TilesConfig::default()
+            },
+            ..AppConfig::default()
+        };
+        valid.validate().unwrap();
+
+        let cases: [(&str, Breakage); 9] = [
+            ("server.bind_addr", |c| c.server.bind_addr = "localhost".to_string()),
+            ("server.cors_origins", |c| c.server.cors_origins = vec!["example.org".to_string()]),
+            ("tiles.config_dir", |c| c.tiles.config_dir = PathBuf::from("/nonexistent/tiles")),
+            ("tiles.refresh_margin_secs", |c| c.tiles.refresh_margin_secs = c.tiles.map_ttl_secs),
+            ("tiles.grid_cache_max_mb", |c| c.tiles.grid_cache_max_mb = 0),
+            ("earth_engine.project", |c| c.earth_engine.access_token = Some("token".parse().unwrap())),
+            ("heat_alert.climatology_years", |c| c.heat_alert.climatology_years = 41),
+            ("weather.providers", |c| c.weather.providers.clear()),
+            ("weather.openweathermap_per_minute", |c| c.weather.openweathermap_per_minute = 0),
+        ];
+        for (key, break_it) in cases {
+            let mut config = valid.clone();
+            break_it(&mut config);
+            match config.validate() {
+                Err(ConfigError::Invalid { key: found, .. }) => assert_eq!(found, key),
+                other => panic!("{}: expected a rejection, got {:?}", key, other),
+            }
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
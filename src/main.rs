@@ -1,82 +1,226 @@
 // src/main.rs
 mod api;
+mod config;
 mod models;
 mod scheduler;
+mod secrets;
 mod services;
 mod utils;
 
+use crate::api::climate::ClimateState;
+use crate::api::tiles::TilesState;
+use crate::api::PublicUrl;
+use crate::config::{AppConfig, Command, WeatherProviderKind};
 use crate::scheduler::heat_alert_scheduler::{run_scheduler, HeatAlertCache};
+use crate::secrets::{Feature, SecretStore};
+use crate::services::city_catalogue::CityCatalogue;
 use crate::services::earthengine::EarthEngineService;
+use crate::services::grid_renderer::GridRenderer;
+use crate::services::heat_alert_services::HeatAlertPoller;
+use crate::services::heat_history::HeatHistory;
+use crate::services::heat_severity::HeatSeverityEngine;
+use crate::services::heatwave::HeatwaveDetector;
+use crate::services::point_sampler::PointSampler;
+use crate::services::tile_cache::TileCache;
+use crate::services::tile_compare::TileCompareService;
 use crate::services::tile_config_service::TileConfigService;
+use crate::services::tile_freshness::TileFreshnessService;
+use crate::services::tile_jobs::TileJobService;
+use crate::services::tile_seeder::{SeedRequest, TileSeeder};
+use crate::services::timeseries::TimeSeriesService;
+use crate::services::vis_registry::VisRegistry;
+use crate::services::weather::WeatherChain;
+use crate::services::zonal_stats::ZonalStatsService;
 use axum::serve;
+use chrono::NaiveDate;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::fs;
 use tokio::net::TcpListener;
 use tokio::spawn;
 use tower_http::cors::{Any, CorsLayer};
 
 #[tokio::main]
 async fn main() {
+    let (config, command) = match AppConfig::load() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Configuration error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(Command::Seed(args)) = command {
+        let failed = seed(&config, args.into()).await;
+        std::process::exit(if failed { 1 } else { 0 });
+    }
+    if let Some(Command::Secrets(command)) = command {
+        let failed = secrets::run_command(&config.secrets, command);
+        std::process::exit(if failed { 1 } else { 0 });
+    }
+
     // Configure CORS
     let cors = CorsLayer::new()
-        .allow_origin(
-            "http://localhost:8080"
-                .parse::<axum::http::HeaderValue>()
-                .unwrap(),
-        )
-        .allow_methods([axum::http::Method::GET, axum::http::Method::POST])
+        .allow_origin(config.cors_origins().expect("validated at load"))
+        .allow_methods([axum::http::Method::GET, axum::http::Method::POST, axum::http::Method::DELETE])
         .allow_headers(Any);
 
     // Shared cache
     let cache = Arc::new(HeatAlertCache::new());
 
+    // Palettes and min/max per layer, shared by generation, rendering and legends
+    let vis = match VisRegistry::load_or_builtin(&config.tiles.viz_file) {
+        Ok(vis) => Arc::new(vis),
+        Err(e) => {
+            eprintln!("Visualization config error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Cities watched for heat alerts and listed by /cities
+    let cities = match CityCatalogue::load_or_builtin(&config.cities.dir) {
+        Ok(cities) => Arc::new(cities),
+        Err(e) => {
+            eprintln!("City catalogue error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("{} cities in the catalogue", cities.all().len());
+
+    // Every run's readings, kept across restarts
+    let history = match HeatHistory::open(&config.heat_alert).await {
+        Ok(history) => Arc::new(history),
+        Err(e) => {
+            eprintln!("Heat-alert history error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    let heatwaves = Arc::new(HeatwaveDetector::new(&config.heat_alert));
+    heatwaves.update(&history.readings(None, NaiveDate::MIN, NaiveDate::MAX).await).await;
+
+    // Credentials; a missing one disables only the feature that needs it
+    let secrets = SecretStore::open(&config.secrets);
+
     // Earth Engine service
-    let earth_engine_service =
-        Arc::new(EarthEngineService::new("http://localhost:8080".to_string()));
+    let mut earth_engine_config = config.earth_engine.clone();
+    let earth_engine_service = match secrets.earth_engine_token(&earth_engine_config) {
+        Ok(token) => {
+            earth_engine_config.access_token = token;
+            EarthEngineService::new(&earth_engine_config, vis.clone())
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            EarthEngineService::new(&earth_engine_config, vis.clone()).disabled(e.to_string())
+        }
+    };
+    let earth_engine_service = Arc::new(earth_engine_service);
 
     // Tile Config Service (pass the directory, not a file)
-    let tile_config_service =
-        Arc::new(TileConfigService::new("C:/Users/amina/app/climate-backend"));
-
-    // Preload existing tile_config_{year}.json files
-    if let Ok(mut entries) = fs::read_dir("C:/Users/amina/app/climate-backend").await {
-        while let Ok(Some(entry)) = entries.next_entry().await {
-            let file_name = entry.file_name().into_string().unwrap_or_default();
-            if file_name.starts_with("tile_config_") && file_name.ends_with(".json") {
-                if let Some(year_str) = file_name
-                    .strip_prefix("tile_config_")
-                    .and_then(|s| s.strip_suffix(".json"))
-                {
-                    if let Ok(year) = year_str.parse::<i32>() {
-                        if let Err(e) = tile_config_service.load_config_for_year(year).await {
-                            eprintln!("Failed to load {}: {}", file_name, e);
-                        } else {
-                            println!("Preloaded tile config for year {}", year);
-                        }
-                    }
-                }
-            }
-        }
+    let tiles_dir = &config.tiles.config_dir;
+    let tile_config_service = Arc::new(TileConfigService::new(tiles_dir));
+
+    // Preload existing tile_config_{year}.json files and follow later changes
+    tile_config_service.load_all().await;
+    if let Err(e) = tile_config_service.watch() {
+        eprintln!("Tile config hot-reload disabled: {}", e);
     }
 
+    // Background tile generation
+    let tile_jobs = Arc::new(TileJobService::new(
+        &config.tiles,
+        tile_config_service.clone(),
+        earth_engine_service.clone(),
+    ));
+
+    // Probe map IDs and regenerate years before their tile URLs expire
+    let tile_freshness = Arc::new(TileFreshnessService::new(
+        &config.tiles,
+        tile_config_service.clone(),
+        tile_jobs.clone(),
+    ));
+    tile_freshness.spawn();
+
+    // Local .npy grids, shared by tile rendering and point sampling
+    let grid_renderer = Arc::new(GridRenderer::new(&config.tiles));
+
+    // On-disk store behind the /tiles/{layer}/{year}/{z}/{x}/{y}.png proxy
+    let tile_cache = Arc::new(TileCache::new(
+        &config.tiles,
+        tile_config_service.clone(),
+        grid_renderer.clone(),
+        vis.clone(),
+    ));
+    tile_cache.load_index().await;
+    let tile_seeder = Arc::new(TileSeeder::new(&config.tiles, tile_cache.clone()));
+
+    // Weather providers in fallback order; /climate/current and the scheduler share their rate limits
+    let openweathermap_api_key = if config.weather.providers.contains(&WeatherProviderKind::OpenWeatherMap) {
+        secrets
+            .get("openweathermap_api_key", Feature::OpenWeatherMap)
+            .map_err(|e| eprintln!("{}", e))
+            .ok()
+    } else {
+        None
+    };
+    let (weather, interactive_weather) = WeatherChain::from_config(&config.weather, openweathermap_api_key);
+    let (weather, interactive_weather) = (Arc::new(weather), Arc::new(interactive_weather));
+
     // Scheduler
-    {
+    if weather.is_empty() {
+        eprintln!("Heat alerts disabled: no weather provider is enabled");
+    } else {
+        println!("Weather providers: {}", weather.names().join(" -> "));
         let cache_clone = cache.clone();
+        let severity = Arc::new(HeatSeverityEngine::new(&config.heat_alert, weather.clone()));
+        let poller = Arc::new(HeatAlertPoller::new(
+            &config.heat_alert,
+            cities.clone(),
+            weather.clone(),
+            severity,
+        ));
+        let history = history.clone();
+        let heatwaves = heatwaves.clone();
+        let interval = config.heat_alert_interval();
         spawn(async move {
-            run_scheduler(cache_clone).await;
+            run_scheduler(cache_clone, poller, history, heatwaves, interval).await;
         });
     }
 
     // Build app
-    let app = api::routes(
-        cache.clone(),
-        earth_engine_service.clone(),
-        tile_config_service.clone(),
-    )
-    .layer(cors);
+    let climate_state = ClimateState {
+        earth_engine: earth_engine_service.clone(),
+        point_sampler: Arc::new(PointSampler::new(
+            earth_engine_service.clone(),
+            tile_config_service.clone(),
+            grid_renderer.clone(),
+        )),
+        timeseries: Arc::new(TimeSeriesService::new(earth_engine_service.clone())),
+        zonal: Arc::new(ZonalStatsService::new(
+            earth_engine_service.clone(),
+            tile_config_service.clone(),
+            grid_renderer.clone(),
+        )),
+        vis: vis.clone(),
+        weather: interactive_weather,
+    };
+    let tiles_state = TilesState {
+        tile_config_service: tile_config_service.clone(),
+        jobs: tile_jobs,
+        freshness: tile_freshness,
+        cache: tile_cache,
+        seeder: tile_seeder,
+        public_url: PublicUrl(config.server.public_url.clone()),
+        compare: Arc::new(TileCompareService::new(
+            &config.tiles,
+            earth_engine_service.clone(),
+            tile_config_service.clone(),
+            grid_renderer.clone(),
+            vis.clone(),
+        )),
+        vis,
+    };
+    let app = api::routes(cache.clone(), history, heatwaves, cities, climate_state, tiles_state).layer(cors);
 
-    let addr: SocketAddr = "127.0.0.1:3000".parse().unwrap();
+    let addr: SocketAddr = config.bind_addr().expect("validated at load");
     let listener = TcpListener::bind(addr)
         .await
         .expect("Failed to bind address");
@@ -85,7 +229,63 @@ async fn main() {
         "Server running at http://{}",
         listener.local_addr().unwrap()
     );
-    println!("Tile configs loaded from: C:/Users/amina/app/climate-backend");
+    println!("Tile configs loaded from: {}", tiles_dir.display());
 
     serve(listener, app.into_make_service()).await.unwrap();
 }
+
+/// `climate-backend seed`: fill the tile cache for a region and exit.
+/// Returns true if any tile could not be fetched.
+async fn seed(config: &AppConfig, request: SeedRequest) -> bool {
+    let tile_config_service = Arc::new(TileConfigService::new(&config.tiles.config_dir));
+    tile_config_service.load_all().await;
+    let vis = match VisRegistry::load_or_builtin(&config.tiles.viz_file) {
+        Ok(vis) => Arc::new(vis),
+        Err(e) => {
+            eprintln!("Visualization config error: {:#}", e);
+            return true;
+        }
+    };
+    let tile_cache = Arc::new(TileCache::new(
+        &config.tiles,
+        tile_config_service,
+        Arc::new(GridRenderer::new(&config.tiles)),
+        vis,
+    ));
+    tile_cache.load_index().await;
+    let seeder = TileSeeder::new(&config.tiles, tile_cache);
+
+    let tiles = match seeder.plan(&request).await {
+        Ok(tiles) => tiles,
+        Err(e) => {
+            eprintln!("Invalid seed request: {}", e);
+            return true;
+        }
+    };
+    println!(
+        "Seeding {} {} tiles for {} (zoom {}..={})",
+        tiles.len(),
+        request.layer.as_str(),
+        request.year,
+        request.min_zoom,
+        request.max_zoom
+    );
+
+    let step = (tiles.len() / 20).max(1);
+    let totals = seeder
+        .seed(tiles, |totals| {
+            if totals.done() % step == 0 || totals.done() == totals.total {
+                println!(
+                    "{}/{} tiles ({} fetched, {} cached, {} failed)",
+                    totals.done(),
+                    totals.total,
+                    totals.fetched,
+                    totals.cached,
+                    totals.failed
+                );
+            }
+        })
+        .await;
+
+    totals.failed > 0
+}
@@ -0,0 +1,28 @@
+// models/city.rs
+use serde::Serialize;
+
+/// A city watched by the heat-alert scheduler.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct City {
+    /// Unique slug, `{name}-{country}` (or `{lat}-{lon}-{country}` for names
+    /// without Latin letters) unless the file gives one
+    pub id: String,
+    pub name: String,
+    /// ISO 3166-1 alpha-2 code
+    pub country: String,
+    pub population: Option<u64>,
+    pub lat: f64,
+    pub lon: f64,
+    /// IANA timezone name
+    pub timezone: Option<String>,
+    pub thresholds: Option<CityThresholds>,
+}
+
+/// Advisory, warning and extreme levels (°C of the heat-alert metric) used
+/// instead of the metric's default absolute thresholds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CityThresholds {
+    pub advisory: f64,
+    pub warning: f64,
+    pub extreme: f64,
+}
@@ -1,9 +1,142 @@
-use serde::Serialize;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Clone)]
 pub struct HeatAlert {
+    /// Catalogue id of the city
+    pub city_id: String,
     pub city: String,
+    pub country: String,
     pub temperature: f64,
-    pub severity: String,
+    /// Relative humidity in %, when the provider reported it
+    pub humidity: Option<f64>,
+    pub severity: Severity,
+    /// Heat-stress metric the severity was judged on
+    pub metric: HeatMetric,
+    /// Value of `metric` in °C
+    pub metric_value: f64,
+    /// Highest threshold `metric_value` reached; `None` when `severity` is `normal`
+    pub threshold: Option<Threshold>,
     pub time_ago: String,
+    /// Heatwave event the city is in, and which day of it this is
+    pub heatwave_id: Option<String>,
+    pub heatwave_day: Option<u32>,
+}
+
+/// How unusual and how dangerous the heat is, lowest first. A city gets the
+/// higher of its climatological level and its absolute (physiological) level;
+/// custom thresholds in the city catalogue replace the absolute levels.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Below the city's 90th percentile and below the metric's caution level
+    Normal,
+    /// At or above the 90th percentile, or the metric's caution level
+    /// (e.g. heat index 32 °C, NWS "extreme caution")
+    Advisory,
+    /// At or above the 95th percentile, or the metric's danger level
+    /// (e.g. heat index 41 °C, NWS "danger")
+    Warning,
+    /// At or above the 99th percentile, or the metric's extreme-danger level
+    /// (e.g. heat index 54 °C, NWS "extreme danger")
+    Extreme,
+}
+
+/// Heat-stress metrics, all in °C.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HeatMetric {
+    /// US National Weather Service heat index (Rothfusz regression)
+    HeatIndex,
+    /// Environment Canada humidex
+    Humidex,
+    /// Wet-bulb globe temperature, Australian Bureau of Meteorology shade approximation
+    Wbgt,
+    /// Plain air temperature, used when no humidity is available
+    AirTemperature,
+}
+
+/// The threshold a metric value reached.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Threshold {
+    pub basis: ThresholdBasis,
+    /// Threshold in °C
+    pub value: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThresholdBasis {
+    /// Percentile of the city's daily maxima around this date in past years
+    P90,
+    P95,
+    P99,
+    /// Fixed physiological level of the metric
+    Absolute,
+    /// Custom level from the city catalogue
+    City,
+}
+
+/// One city's conditions from one polling run, as kept in the history.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HeatReading {
+    pub city_id: String,
+    pub city: String,
+    pub observed_at: DateTime<Utc>,
+    /// Offset of the city's local time, which decides the day a reading counts for
+    pub utc_offset_seconds: i32,
+    pub temperature: f64,
+    pub humidity: Option<f64>,
+    pub metric: HeatMetric,
+    pub metric_value: f64,
+    pub severity: Severity,
+    pub threshold: Option<Threshold>,
+    /// Lowest `metric_value` counted as hot; absent in readings stored before it was recorded
+    #[serde(default)]
+    pub heat_threshold: Option<f64>,
+    pub provider: String,
+}
+
+/// Outcome of one polling run over the city catalogue.
+#[derive(Serialize, Clone, Debug)]
+pub struct PollReport {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub cities: usize,
+    pub succeeded: usize,
+    /// Cities that succeeded only after a retry
+    pub retried: usize,
+    pub failed: Vec<CityFailure>,
+}
+
+/// A city no weather provider answered for, even after retries.
+#[derive(Serialize, Clone, Debug)]
+pub struct CityFailure {
+    pub city_id: String,
+    pub city: String,
+    pub attempts: u32,
+    pub error: String,
+}
+
+/// Consecutive days above a city's heat threshold, once they reach
+/// `heat_alert.heatwave_min_days`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct HeatwaveEvent {
+    /// `{city_id}-{start}`
+    pub id: String,
+    pub city_id: String,
+    pub city: String,
+    /// First and latest hot local day
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub days: u32,
+    /// False once a cooler day is observed or readings stop
+    pub active: bool,
+    pub metric: HeatMetric,
+    pub peak_metric_value: f64,
+    pub peak_temperature: f64,
+    pub peak_severity: Severity,
+    /// Sum over the days of the daily peak's excess over the heat threshold, in °C·days
+    pub degree_days: f64,
 }
@@ -0,0 +1,103 @@
+// models/tile_config.rs
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Scheme of tile URLs rendered by this server from a `.npy` grid, e.g.
+/// `local://lst_2024/{z}/{x}/{y}` for `lst_2024.npy` in `tiles.grid_dir`
+pub const LOCAL_SCHEME: &str = "local://";
+
+/// Contents of a `tile_config_{year}.json` file. The metadata fields are
+/// optional because older generator runs only wrote the three URLs.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TileConfig {
+    pub lst_tile_url: String,
+    pub anomaly_tile_url: String,
+    pub absolute_anomaly_tile_url: String,
+    #[serde(default)]
+    pub generated_at: Option<String>,
+    /// Period averaged for the map, e.g. `2024` or `2025-01..2025-07`
+    #[serde(default)]
+    pub target_period: Option<String>,
+    /// Baseline the anomalies are computed against, e.g. `2000-01-01..2025-07-31`
+    #[serde(default)]
+    pub climatology_period: Option<String>,
+    /// Source dataset(s), e.g. `MODIS/061/MOD11A1`
+    #[serde(default)]
+    pub data_source: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// One of the three layers of a `TileConfig`, as named in `/tiles/{layer}/...` paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum TileLayer {
+    Lst,
+    Anomaly,
+    AbsoluteAnomaly,
+}
+
+impl TileLayer {
+    pub const ALL: [TileLayer; 3] = [TileLayer::Lst, TileLayer::Anomaly, TileLayer::AbsoluteAnomaly];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TileLayer::Lst => "lst",
+            TileLayer::Anomaly => "anomaly",
+            TileLayer::AbsoluteAnomaly => "absolute_anomaly",
+        }
+    }
+}
+
+impl TileConfig {
+    /// True for configs rendered locally, whose URLs never expire
+    pub fn is_local(&self) -> bool {
+        self.lst_tile_url.starts_with(LOCAL_SCHEME)
+    }
+
+    /// Grid name of a `local://{grid}/{z}/{x}/{y}` layer
+    pub fn local_grid(&self, layer: TileLayer) -> Option<&str> {
+        self.tile_url(layer)
+            .strip_prefix(LOCAL_SCHEME)
+            .and_then(|path| path.split('/').next())
+    }
+
+    /// `{z}/{x}/{y}` URL template of `layer`
+    pub fn tile_url(&self, layer: TileLayer) -> &str {
+        match layer {
+            TileLayer::Lst => &self.lst_tile_url,
+            TileLayer::Anomaly => &self.anomaly_tile_url,
+            TileLayer::AbsoluteAnomaly => &self.absolute_anomaly_tile_url,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComparisonSource {
+    EarthEngine,
+    LocalGrid,
+}
+
+/// Difference layer of `to` minus `from`, cached per layer and year pair
+#[derive(Debug, Clone, Serialize)]
+pub struct TileComparison {
+    pub layer: TileLayer,
+    pub from: i32,
+    pub to: i32,
+    /// `{z}/{x}/{y}` template; Earth Engine maps expire like the yearly ones
+    pub tile_url: String,
+    pub source: ComparisonSource,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// Summary of the change over a bbox; `None` where every pixel is masked
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ChangeStats {
+    /// `[west, south, east, north]` in degrees
+    pub bbox: [f64; 4],
+    pub mean: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
@@ -0,0 +1,165 @@
+// models/zone.rs
+use serde::Deserialize;
+
+/// Upper bound on vertices across all rings of a zone
+pub const MAX_VERTICES: usize = 5000;
+
+/// GeoJSON accepted by the zonal statistics endpoint: a Polygon or
+/// MultiPolygon, bare or wrapped in a Feature.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+pub enum GeoJson {
+    Polygon { coordinates: Vec<Vec<Vec<f64>>> },
+    MultiPolygon { coordinates: Vec<Vec<Vec<Vec<f64>>>> },
+    Feature { geometry: Box<GeoJson> },
+}
+
+/// A validated area: polygons of closed `(lon, lat)` rings, the first ring of
+/// each being the exterior and any others holes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zone {
+    pub polygons: Vec<Vec<Vec<(f64, f64)>>>,
+}
+
+impl TryFrom<GeoJson> for Zone {
+    type Error = String;
+
+    fn try_from(geojson: GeoJson) -> Result<Self, String> {
+        let polygons = match geojson {
+            GeoJson::Polygon { coordinates } => vec![coordinates],
+            GeoJson::MultiPolygon { coordinates } => coordinates,
+            GeoJson::Feature { geometry } => match *geometry {
+                GeoJson::Feature { .. } => return Err("a Feature's geometry cannot be a Feature".to_string()),
+                geometry => return Zone::try_from(geometry),
+            },
+        };
+        if polygons.is_empty() {
+            return Err("geometry has no polygons".to_string());
+        }
+
+        let vertices: usize = polygons.iter().flatten().map(Vec::len).sum();
+        if vertices > MAX_VERTICES {
+            return Err(format!("{} vertices exceeds the limit of {}", vertices, MAX_VERTICES));
+        }
+
+        let polygons = polygons
+            .into_iter()
+            .enumerate()
+            .map(|(p, rings)| {
+                if rings.is_empty() {
+                    return Err(format!("polygon {} has no rings", p));
+                }
+                rings
+                    .into_iter()
+                    .enumerate()
+                    .map(|(r, ring)| ring_points(ring).map_err(|e| format!("polygon {} ring {}: {}", p, r, e)))
+                    .collect()
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Zone { polygons })
+    }
+}
+
+/// Check a ring's positions and reduce them to `(lon, lat)`.
+fn ring_points(ring: Vec<Vec<f64>>) -> Result<Vec<(f64, f64)>, String> {
+    if ring.len() < 4 {
+        return Err("a linear ring needs at least 4 positions".to_string());
+    }
+    let points = ring
+        .iter()
+        .map(|position| match position.as_slice() {
+            // A third value is an altitude and is ignored
+            [lon, lat] | [lon, lat, _] => {
+                if !(-180.0..=180.0).contains(lon) || !(-90.0..=90.0).contains(lat) {
+                    Err(format!("position [{}, {}] is outside longitude -180..180 / latitude -90..90", lon, lat))
+                } else {
+                    Ok((*lon, *lat))
+                }
+            }
+            _ => Err("positions must be [longitude, latitude]".to_string()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if points.first() != points.last() {
+        return Err("ring is not closed; the last position must repeat the first".to_string());
+    }
+    Ok(points)
+}
+
+impl Zone {
+    /// `[west, south, east, north]` around every ring
+    pub fn bbox(&self) -> [f64; 4] {
+        self.polygons.iter().flatten().flatten().fold(
+            [f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY],
+            |[west, south, east, north], &(lon, lat)| [west.min(lon), south.min(lat), east.max(lon), north.max(lat)],
+        )
+    }
+
+    /// Whether a point is inside, by the even-odd rule within each polygon
+    pub fn contains(&self, lon: f64, lat: f64) -> bool {
+        self.polygons.iter().any(|rings| {
+            rings
+                .iter()
+                .filter(|ring| crosses_ring(ring, lon, lat))
+                .count()
+                % 2
+                == 1
+        })
+    }
+
+    /// Coordinates nested as GeoJSON MultiPolygon coordinates
+    pub fn coordinates(&self) -> Vec<Vec<Vec<[f64; 2]>>> {
+        self.polygons
+            .iter()
+            .map(|rings| {
+                rings
+                    .iter()
+                    .map(|ring| ring.iter().map(|&(lon, lat)| [lon, lat]).collect())
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Ray casting: an odd number of edge crossings means the point is inside the ring
+fn crosses_ring(ring: &[(f64, f64)], lon: f64, lat: f64) -> bool {
+    let mut inside = false;
+    for edge in ring.windows(2) {
+        let ((x1, y1), (x2, y2)) = (edge[0], edge[1]);
+        if (y1 > lat) != (y2 > lat) && lon < x1 + (lat - y1) / (y2 - y1) * (x2 - x1) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn zone(geojson: &str) -> Result<Zone, String> {
+        Zone::try_from(serde_json::from_str::<GeoJson>(geojson).unwrap())
+    }
+
+    #[test]
+    fn validates_geojson_rings() {
+        let square = zone(r#"{ "type": "Polygon", "coordinates": [[[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]],
+                                                                    [[4, 4], [6, 4], [6, 6], [4, 6], [4, 4]]] }"#)
+        .unwrap();
+        assert_eq!(square.bbox(), [0.0, 0.0, 10.0, 10.0]);
+        assert!(square.contains(2.0, 2.0));
+        assert!(!square.contains(5.0, 5.0), "inside the hole");
+        assert!(!square.contains(12.0, 5.0));
+
+        let feature = zone(r#"{ "type": "Feature", "geometry": { "type": "MultiPolygon",
+                                 "coordinates": [[[[0, 0], [1, 0], [1, 1], [0, 0]]]] } }"#);
+        assert!(feature.is_ok());
+
+        assert!(zone(r#"{ "type": "Polygon", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 1]]] }"#)
+            .unwrap_err()
+            .contains("not closed"));
+        assert!(zone(r#"{ "type": "Polygon", "coordinates": [[[0, 0], [200, 0], [1, 1], [0, 0]]] }"#)
+            .unwrap_err()
+            .contains("outside"));
+        assert!(zone(r#"{ "type": "Polygon", "coordinates": [[[0, 0], [1, 1], [0, 0]]] }"#).is_err());
+    }
+}
@@ -1,28 +1,53 @@
-use crate::models::heat_alert::HeatAlert;
+use crate::models::heat_alert::{HeatAlert, PollReport};
 use tokio::sync::RwLock;
 use std::sync::Arc;
 use tokio::time::{interval, Duration};
-use crate::services::heat_alert_services::get_top_heat_alerts;
+use crate::services::heat_alert_services::HeatAlertPoller;
+use crate::services::heat_history::HeatHistory;
+use crate::services::heatwave::HeatwaveDetector;
 
 pub struct HeatAlertCache {
     pub top_alerts: RwLock<Vec<HeatAlert>>,
+    /// Report of the latest finished run
+    pub last_run: RwLock<Option<PollReport>>,
 }
 
 impl HeatAlertCache {
     pub fn new() -> Self {
-        HeatAlertCache { top_alerts: RwLock::new(vec![]) }
+        HeatAlertCache {
+            top_alerts: RwLock::new(vec![]),
+            last_run: RwLock::new(None),
+        }
     }
 }
 
-pub async fn run_scheduler(cache: Arc<HeatAlertCache>) {
-    let mut ticker = interval(Duration::from_secs(6 * 3600)); // 6 hours
+pub async fn run_scheduler(
+    cache: Arc<HeatAlertCache>,
+    poller: Arc<HeatAlertPoller>,
+    history: Arc<HeatHistory>,
+    heatwaves: Arc<HeatwaveDetector>,
+    every: Duration,
+) {
+    let mut ticker = interval(every);
 
     loop {
         ticker.tick().await;
 
-        let top_alerts = get_top_heat_alerts().await;
+        let mut run = poller.poll().await;
+        let report = run.report;
+        println!(
+            "Heat alert run: {}/{} cities in {} ms ({} retried, {} failed)",
+            report.succeeded,
+            report.cities,
+            report.duration_ms,
+            report.retried,
+            report.failed.len()
+        );
 
-        let mut cache_write = cache.top_alerts.write().await;
-        *cache_write = top_alerts;
+        history.record(&run.readings).await;
+        heatwaves.update(&run.readings).await;
+        heatwaves.annotate(&mut run.top_alerts).await;
+        *cache.top_alerts.write().await = run.top_alerts;
+        *cache.last_run.write().await = Some(report);
     }
 }
@@ -0,0 +1,332 @@
+// src/secrets.rs
+//
+// Credentials, kept out of the source tree and the config file. A secret
+// `name` is looked up, first match wins, in:
+//   1. the `CLIMATE_{NAME}` environment variable,
+//   2. the file named by `CLIMATE_{NAME}_FILE`,
+//   3. `{secrets.dir}/{name}`, where Docker and Kubernetes mount secrets,
+//   4. the `name` entry of `secrets.encrypted_file`, a JSON object sealed with
+//      ChaCha20-Poly1305 under the base64 key in `CLIMATE_SECRETS_KEY`.
+// Values are wrapped in `Secret`, which never prints its contents.
+use crate::config::{EarthEngineConfig, SecretsConfig};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Environment variable holding the key of `secrets.encrypted_file`
+pub const SECRETS_KEY_ENV: &str = "CLIMATE_SECRETS_KEY";
+
+/// Query parameters whose values are credentials in the URLs we call
+const SENSITIVE_PARAMS: &[&str] = &["appid", "api_key", "apikey", "key", "access_token", "token"];
+
+// -------------------- Secret --------------------
+/// A credential. `Debug` and `Display` show `[redacted]`; use `expose` at the
+/// point where the value is sent.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[redacted]")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[redacted]")
+    }
+}
+
+impl FromStr for Secret {
+    type Err = std::convert::Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(Secret(value.to_string()))
+    }
+}
+
+// -------------------- Errors --------------------
+/// Features that stay off when their credentials are missing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    EarthEngine,
+    OpenWeatherMap,
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Feature::EarthEngine => write!(f, "Earth Engine"),
+            Feature::OpenWeatherMap => write!(f, "OpenWeatherMap weather provider"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error("{feature} disabled: no `{name}` secret (set {env} or {env}_FILE, or mount {path})")]
+    Missing {
+        feature: Feature,
+        name: &'static str,
+        env: String,
+        path: PathBuf,
+    },
+    #[error("Earth Engine disabled: no access token and no service account key at {path}")]
+    NoEarthEngineCredentials { path: PathBuf },
+    #[error("{feature} disabled: failed to read secret file {path}: {source}")]
+    Read {
+        feature: Feature,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{feature} disabled: cannot open {path}: {message}")]
+    Encrypted {
+        feature: Feature,
+        path: PathBuf,
+        message: String,
+    },
+}
+
+// -------------------- Store --------------------
+pub struct SecretStore {
+    dir: PathBuf,
+    encrypted_file: PathBuf,
+    /// Entries of the encrypted file; empty when there is none
+    encrypted: Result<HashMap<String, Secret>, String>,
+}
+
+impl SecretStore {
+    /// Decrypts `secrets.encrypted_file` up front if it exists. A file that
+    /// cannot be opened only fails lookups that reach it.
+    pub fn open(config: &SecretsConfig) -> Self {
+        let encrypted = if config.encrypted_file.is_file() {
+            load_encrypted(&config.encrypted_file)
+        } else {
+            Ok(HashMap::new())
+        };
+        Self {
+            dir: config.dir.clone(),
+            encrypted_file: config.encrypted_file.clone(),
+            encrypted,
+        }
+    }
+
+    pub fn get(&self, name: &'static str, feature: Feature) -> Result<Secret, SecretError> {
+        let env = format!("CLIMATE_{}", name.to_uppercase());
+        if let Some(value) = std::env::var(&env).ok().filter(|value| !value.is_empty()) {
+            return Ok(Secret(value));
+        }
+        if let Ok(path) = std::env::var(format!("{}_FILE", env)) {
+            return read_secret_file(Path::new(&path), feature);
+        }
+        let mounted = self.dir.join(name);
+        if mounted.is_file() {
+            return read_secret_file(&mounted, feature);
+        }
+        match &self.encrypted {
+            Ok(entries) => entries.get(name).cloned().ok_or(SecretError::Missing {
+                feature,
+                name,
+                env,
+                path: mounted,
+            }),
+            Err(message) => Err(SecretError::Encrypted {
+                feature,
+                path: self.encrypted_file.clone(),
+                message: message.clone(),
+            }),
+        }
+    }
+
+    /// The Earth Engine access token, if one is configured. Without a token the
+    /// service account key file must exist.
+    pub fn earth_engine_token(&self, config: &EarthEngineConfig) -> Result<Option<Secret>, SecretError> {
+        if let Some(token) = &config.access_token {
+            return Ok(Some(token.clone()));
+        }
+        match self.get("ee_access_token", Feature::EarthEngine) {
+            Ok(token) => Ok(Some(token)),
+            // The key file is enough, even when the encrypted file cannot be opened
+            Err(SecretError::Missing { .. } | SecretError::Encrypted { .. }) if config.service_account_key.is_file() => {
+                Ok(None)
+            }
+            Err(SecretError::Missing { .. }) => Err(SecretError::NoEarthEngineCredentials {
+                path: config.service_account_key.clone(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn read_secret_file(path: &Path, feature: Feature) -> Result<Secret, SecretError> {
+    std::fs::read_to_string(path)
+        // Mounted files usually end with a newline
+        .map(|value| Secret(value.trim_end().to_string()))
+        .map_err(|source| SecretError::Read {
+            feature,
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+fn load_encrypted(path: &Path) -> Result<HashMap<String, Secret>, String> {
+    let key = std::env::var(SECRETS_KEY_ENV).map_err(|_| format!("{} is not set", SECRETS_KEY_ENV))?;
+    let sealed = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let plaintext = decrypt(&key, &sealed)?;
+    serde_json::from_slice(&plaintext).map_err(|e| format!("not a JSON object of strings: {}", e))
+}
+
+// -------------------- Encryption --------------------
+/// A new random key for `CLIMATE_SECRETS_KEY`, base64 encoded.
+pub fn generate_key() -> String {
+    let mut key = [0u8; 32];
+    SystemRandom::new().fill(&mut key).expect("system random source");
+    BASE64.encode(key)
+}
+
+fn aead_key(key: &str) -> Result<LessSafeKey, String> {
+    let bytes = BASE64
+        .decode(key.trim())
+        .map_err(|_| format!("{} is not base64", SECRETS_KEY_ENV))?;
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &bytes)
+        .map_err(|_| format!("{} must decode to 32 bytes", SECRETS_KEY_ENV))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Seal `plaintext` as base64 of `nonce || ciphertext || tag`.
+pub fn encrypt(key: &str, plaintext: &[u8]) -> Result<String, String> {
+    let key = aead_key(key)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| "system random source failed".to_string())?;
+
+    let mut sealed = plaintext.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut sealed)
+        .map_err(|_| "encryption failed".to_string())?;
+    Ok(BASE64.encode([nonce.as_slice(), &sealed].concat()))
+}
+
+pub fn decrypt(key: &str, sealed: &str) -> Result<Vec<u8>, String> {
+    let key = aead_key(key)?;
+    let bytes = BASE64
+        .decode(sealed.trim())
+        .map_err(|_| "file is not base64".to_string())?;
+    if bytes.len() < NONCE_LEN {
+        return Err("file is truncated".to_string());
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "file is truncated".to_string())?;
+    let mut ciphertext = ciphertext.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, Aad::empty(), &mut ciphertext)
+        .map_err(|_| format!("wrong {} or corrupted file", SECRETS_KEY_ENV))?;
+    Ok(plaintext.to_vec())
+}
+
+// -------------------- Redaction --------------------
+/// Replace credential query parameters (`appid=...`, `token=...`) in text that
+/// may contain request URLs.
+pub fn redact(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(['?', '&']) {
+        let (before, after) = rest.split_at(start + 1);
+        out.push_str(before);
+        rest = after;
+        let Some((param, value)) = rest.split_once('=') else {
+            continue;
+        };
+        if SENSITIVE_PARAMS.iter().any(|name| param.eq_ignore_ascii_case(name)) {
+            let end = value
+                .find(|c: char| c == '&' || c == '#' || c == ')' || c == '"' || c.is_whitespace())
+                .unwrap_or(value.len());
+            out.push_str(param);
+            out.push_str("=[redacted]");
+            rest = &value[end..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A reqwest error with its URL's credentials redacted.
+pub fn redact_reqwest(e: reqwest::Error) -> anyhow::Error {
+    match e.url().map(|url| redact(url.as_str())) {
+        Some(url) => anyhow::anyhow!("{} ({})", e.without_url(), url),
+        None => e.into(),
+    }
+}
+
+// -------------------- Command --------------------
+/// `climate-backend secrets ...`; returns true on failure.
+pub fn run_command(config: &SecretsConfig, command: crate::config::SecretsCommand) -> bool {
+    use crate::config::SecretsCommand;
+
+    match command {
+        SecretsCommand::GenerateKey => {
+            println!("{}", generate_key());
+            false
+        }
+        SecretsCommand::Encrypt { input } => {
+            let result = (|| {
+                let key = std::env::var(SECRETS_KEY_ENV).map_err(|_| format!("{} is not set", SECRETS_KEY_ENV))?;
+                let plaintext = std::fs::read(&input).map_err(|e| format!("{}: {}", input.display(), e))?;
+                serde_json::from_slice::<HashMap<String, String>>(&plaintext)
+                    .map_err(|e| format!("{} is not a JSON object of strings: {}", input.display(), e))?;
+                let sealed = encrypt(&key, &plaintext)?;
+                std::fs::write(&config.encrypted_file, sealed)
+                    .map_err(|e| format!("{}: {}", config.encrypted_file.display(), e))
+            })();
+            match result {
+                Ok(()) => {
+                    println!("Wrote {}", config.encrypted_file.display());
+                    false
+                }
+                Err(e) => {
+                    eprintln!("Failed to encrypt secrets: {}", e);
+                    true
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_credentials_in_urls() {
+        let error = "error sending request for url (https://api.openweathermap.org/data/2.5/weather?lat=1&lon=2&appid=abc123&units=metric)";
+        assert_eq!(
+            redact(error),
+            "error sending request for url (https://api.openweathermap.org/data/2.5/weather?lat=1&lon=2&appid=[redacted]&units=metric)"
+        );
+        assert_eq!(redact("GET /x?token=t0k"), "GET /x?token=[redacted]");
+        assert_eq!(redact("no url here"), "no url here");
+        assert_eq!(format!("{:?}", Secret("abc123".to_string())), "[redacted]");
+    }
+
+    #[test]
+    fn encrypted_file_round_trips() {
+        let key = generate_key();
+        let sealed = encrypt(&key, br#"{"openweathermap_api_key": "abc123"}"#).unwrap();
+        assert!(!sealed.contains("abc123"));
+        assert_eq!(decrypt(&key, &sealed).unwrap(), br#"{"openweathermap_api_key": "abc123"}"#);
+        assert!(decrypt(&generate_key(), &sealed).is_err());
+    }
+}
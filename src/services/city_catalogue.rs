@@ -0,0 +1,448 @@
+// src/services/city_catalogue.rs
+//
+// The cities watched for heat alerts, loaded at startup from every `.geojson`,
+// `.json` and `.csv` file in `cities.dir` (see `cities/cities.geojson`).
+// GeoJSON files are FeatureCollections of Points whose properties hold
+// `name`, `country`, `population`, `timezone`, an optional `id` and optional
+// `thresholds: {advisory, warning, extreme}`. CSV files have a header row with
+// `name,country,lat,lon` and optionally `id,population,timezone,advisory,warning,extreme`.
+use crate::models::city::{City, CityThresholds};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Copy of `cities/cities.geojson` compiled in, used when the directory is missing or empty
+const BUILTIN_CITIES: &str = include_str!("../../cities/cities.geojson");
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum CityFile {
+    FeatureCollection { features: Vec<Feature> },
+}
+
+#[derive(Deserialize)]
+struct Feature {
+    geometry: Point,
+    properties: Properties,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum Point {
+    Point { coordinates: Vec<f64> },
+}
+
+#[derive(Deserialize)]
+struct Properties {
+    id: Option<String>,
+    name: String,
+    country: String,
+    population: Option<u64>,
+    timezone: Option<String>,
+    thresholds: Option<Thresholds>,
+}
+
+#[derive(Deserialize)]
+struct Thresholds {
+    advisory: f64,
+    warning: f64,
+    extreme: f64,
+}
+
+/// A validated record and whether its id came from the file
+#[derive(Debug)]
+struct Parsed {
+    city: City,
+    explicit_id: bool,
+}
+
+/// Filters for `search`; every given filter must match.
+#[derive(Debug, Default)]
+pub struct CityFilter<'a> {
+    /// Case-insensitive substring of the name
+    pub query: Option<&'a str>,
+    /// ISO country code, case-insensitive
+    pub country: Option<&'a str>,
+    pub min_population: Option<u64>,
+    /// `[west, south, east, north]`
+    pub bbox: Option<[f64; 4]>,
+}
+
+#[derive(Debug)]
+pub struct CityCatalogue {
+    /// Most populous first
+    cities: Vec<City>,
+    by_id: HashMap<String, usize>,
+}
+
+impl CityCatalogue {
+    /// The compiled-in `cities/cities.geojson`
+    pub fn builtin() -> Self {
+        let parsed = parse_geojson(BUILTIN_CITIES).expect("cities/cities.geojson is valid");
+        Self::new(parsed).expect("cities/cities.geojson is valid")
+    }
+
+    /// Every city file in `dir`, or the built-in list when there are none
+    pub fn load_or_builtin(dir: &Path) -> Result<Self> {
+        let catalogue = if dir.is_dir() { Self::load(dir)? } else { None };
+        Ok(catalogue.unwrap_or_else(|| {
+            println!("No city files in {}, using the built-in city list", dir.display());
+            Self::builtin()
+        }))
+    }
+
+    fn load(dir: &Path) -> Result<Option<Self>> {
+        let mut paths: Vec<_> = std::fs::read_dir(dir)
+            .with_context(|| format!("reading {}", dir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                matches!(
+                    path.extension().and_then(|ext| ext.to_str()),
+                    Some("geojson" | "json" | "csv")
+                )
+            })
+            .collect();
+        if paths.is_empty() {
+            return Ok(None);
+        }
+        paths.sort();
+
+        let mut cities = Vec::new();
+        for path in paths {
+            let raw = std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+            let parsed = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("csv") => parse_csv(&raw),
+                _ => parse_geojson(&raw),
+            };
+            cities.extend(parsed.with_context(|| format!("invalid {}", path.display()))?);
+        }
+        Self::new(cities).map(Some)
+    }
+
+    /// Ids given in the files must be unique; generated ones that clash get
+    /// a `-2`, `-3`, ... suffix in file order.
+    fn new(parsed: Vec<Parsed>) -> Result<Self> {
+        let mut seen = HashSet::new();
+        for record in parsed.iter().filter(|record| record.explicit_id) {
+            if !seen.insert(record.city.id.clone()) {
+                bail!("duplicate city id {:?}", record.city.id);
+            }
+        }
+        let mut cities = Vec::with_capacity(parsed.len());
+        for Parsed { mut city, explicit_id } in parsed {
+            if !explicit_id {
+                let base = std::mem::take(&mut city.id);
+                city.id = (1..)
+                    .map(|n| if n == 1 { base.clone() } else { format!("{}-{}", base, n) })
+                    .find(|id| !seen.contains(id))
+                    .expect("some suffix is free");
+                seen.insert(city.id.clone());
+            }
+            cities.push(city);
+        }
+        cities.sort_by(|a, b| b.population.cmp(&a.population).then_with(|| a.name.cmp(&b.name)));
+        let by_id = cities
+            .iter()
+            .enumerate()
+            .map(|(i, city)| (city.id.clone(), i))
+            .collect();
+        Ok(Self { cities, by_id })
+    }
+
+    pub fn all(&self) -> &[City] {
+        &self.cities
+    }
+
+    pub fn get(&self, id: &str) -> Option<&City> {
+        self.by_id.get(id).map(|&i| &self.cities[i])
+    }
+
+    /// The city with id `name`, or else the most populous one called `name` (any case)
+    pub fn resolve(&self, name: &str) -> Option<&City> {
+        self.get(name)
+            .or_else(|| self.cities.iter().find(|city| city.name.eq_ignore_ascii_case(name)))
+    }
+
+    /// Cities matching `filter`, most populous first
+    pub fn search<'a>(&'a self, filter: &'a CityFilter<'a>) -> impl Iterator<Item = &'a City> + 'a {
+        let query = filter.query.map(str::to_lowercase);
+        self.cities.iter().filter(move |city| {
+            query
+                .as_ref()
+                .is_none_or(|query| city.name.to_lowercase().contains(query.as_str()))
+                && filter
+                    .country
+                    .is_none_or(|country| city.country.eq_ignore_ascii_case(country))
+                && filter
+                    .min_population
+                    .is_none_or(|min| city.population.is_some_and(|population| population >= min))
+                && filter.bbox.is_none_or(|[west, south, east, north]| {
+                    (west..=east).contains(&city.lon) && (south..=north).contains(&city.lat)
+                })
+        })
+    }
+}
+
+fn parse_geojson(raw: &str) -> Result<Vec<Parsed>> {
+    let CityFile::FeatureCollection { features } = serde_json::from_str(raw)?;
+    features
+        .into_iter()
+        .enumerate()
+        .map(|(i, feature)| {
+            let Point::Point { coordinates } = feature.geometry;
+            let [lon, lat] = coordinates[..] else {
+                bail!("feature {}: coordinates must be [longitude, latitude]", i);
+            };
+            let p = feature.properties;
+            city(
+                p.id,
+                p.name,
+                p.country,
+                p.population,
+                lat,
+                lon,
+                p.timezone,
+                p.thresholds.map(|t| [t.advisory, t.warning, t.extreme]),
+            )
+            .with_context(|| format!("feature {}", i))
+        })
+        .collect()
+}
+
+fn parse_csv(raw: &str) -> Result<Vec<Parsed>> {
+    let mut lines = raw.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let Some((_, header)) = lines.next() else {
+        return Ok(Vec::new());
+    };
+    let header: Vec<String> = split_csv_line(header)?
+        .into_iter()
+        .map(|column| column.trim().to_lowercase())
+        .collect();
+    let column = |name: &str| header.iter().position(|column| column == name);
+    let (Some(name), Some(country), Some(lat), Some(lon)) =
+        (column("name"), column("country"), column("lat"), column("lon"))
+    else {
+        bail!("header must include name, country, lat and lon");
+    };
+    let (id, population, timezone) = (column("id"), column("population"), column("timezone"));
+    let thresholds = (column("advisory"), column("warning"), column("extreme"));
+
+    let row = |line: &str| -> Result<Parsed> {
+        let fields = split_csv_line(line)?;
+        // Empty optional fields count as missing
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|index| fields.get(index))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+        let number = |index: usize, label: &str| -> Result<f64> {
+            let value = field(Some(index)).with_context(|| format!("missing {}", label))?;
+            value.parse().with_context(|| format!("{} {:?} is not a number", label, value))
+        };
+        let optional = |index: Option<usize>, label: &str| -> Result<Option<f64>> {
+            index.filter(|_| field(index).is_some()).map(|i| number(i, label)).transpose()
+        };
+
+        let thresholds = match (
+            optional(thresholds.0, "advisory")?,
+            optional(thresholds.1, "warning")?,
+            optional(thresholds.2, "extreme")?,
+        ) {
+            (Some(advisory), Some(warning), Some(extreme)) => Some([advisory, warning, extreme]),
+            (None, None, None) => None,
+            _ => bail!("advisory, warning and extreme must be given together"),
+        };
+        city(
+            field(id).map(str::to_string),
+            field(Some(name)).unwrap_or_default().to_string(),
+            field(Some(country)).unwrap_or_default().to_string(),
+            field(population)
+                .map(|value| value.parse().with_context(|| format!("population {:?} is not a whole number", value)))
+                .transpose()?,
+            number(lat, "lat")?,
+            number(lon, "lon")?,
+            field(timezone).map(str::to_string),
+            thresholds,
+        )
+    };
+
+    lines
+        .map(|(n, line)| row(line).with_context(|| format!("line {}", n + 1)))
+        .collect()
+}
+
+/// Fields of one CSV line; double quotes wrap fields containing commas and `""` escapes a quote.
+fn split_csv_line(line: &str) -> Result<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        bail!("unterminated quoted field");
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// Validate one record.
+#[allow(clippy::too_many_arguments)]
+fn city(
+    id: Option<String>,
+    name: String,
+    country: String,
+    population: Option<u64>,
+    lat: f64,
+    lon: f64,
+    timezone: Option<String>,
+    thresholds: Option<[f64; 3]>,
+) -> Result<Parsed> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        bail!("name is empty");
+    }
+    let country = country.trim().to_uppercase();
+    if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+        bail!("{}: country {:?} is not an ISO 3166-1 alpha-2 code", name, country);
+    }
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        bail!("{}: [{}, {}] is outside latitude -90..90 / longitude -180..180", name, lat, lon);
+    }
+    if let Some(timezone) = &timezone {
+        if timezone != "UTC" && !timezone.contains('/') {
+            bail!("{}: timezone {:?} is not an IANA name like Asia/Karachi", name, timezone);
+        }
+    }
+    let thresholds = match thresholds {
+        Some([advisory, warning, extreme]) if advisory < warning && warning < extreme => Some(CityThresholds {
+            advisory,
+            warning,
+            extreme,
+        }),
+        Some(_) => bail!("{}: thresholds must rise from advisory to warning to extreme", name),
+        None => None,
+    };
+    let explicit_id = id.is_some();
+    Ok(Parsed {
+        city: City {
+            id: id.unwrap_or_else(|| generated_id(&name, &country, lat, lon)),
+            name,
+            country,
+            population,
+            lat,
+            lon,
+            timezone,
+            thresholds,
+        },
+        explicit_id,
+    })
+}
+
+/// `{name}-{country}` as a slug, e.g. `lahore-pk`. Names without Latin
+/// letters (東京, القاهرة) use the coordinates instead: `35-68n-139-69e-jp`.
+fn generated_id(name: &str, country: &str, lat: f64, lon: f64) -> String {
+    let name = slug(name);
+    let name = if name.chars().any(|c| c.is_ascii_alphabetic()) {
+        name
+    } else {
+        slug(&format!(
+            "{:.2}{} {:.2}{}",
+            lat.abs(),
+            if lat < 0.0 { 's' } else { 'n' },
+            lon.abs(),
+            if lon < 0.0 { 'w' } else { 'e' }
+        ))
+    };
+    format!("{}-{}", name, country.to_lowercase())
+}
+
+/// Lowercase ASCII letters and digits joined by single dashes
+fn slug(text: &str) -> String {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_geojson_and_csv_and_filters() {
+        let builtin = CityCatalogue::builtin();
+        let lahore = builtin.get("lahore-pk").unwrap();
+        assert_eq!((lahore.country.as_str(), lahore.timezone.as_deref()), ("PK", Some("Asia/Karachi")));
+
+        let csv = "name,country,lat,lon,population,advisory,warning,extreme\n\
+                   \"Jacobabad, Sindh\",pk,28.2769,68.4514,200000,35,40,45\n\
+                   Mexicali,MX,32.6245,-115.4523,,,,\n";
+        let cities = CityCatalogue::new(parse_csv(csv).unwrap()).unwrap();
+        let jacobabad = cities.get("jacobabad-sindh-pk").unwrap();
+        assert_eq!(jacobabad.thresholds.map(|t| t.warning), Some(40.0));
+        assert_eq!(cities.get("mexicali-mx").unwrap().population, None);
+
+        let filter = CityFilter {
+            country: Some("pk"),
+            min_population: Some(100_000),
+            ..Default::default()
+        };
+        assert_eq!(cities.search(&filter).count(), 1);
+        let filter = CityFilter {
+            query: Some("MEXI"),
+            ..Default::default()
+        };
+        assert_eq!(cities.search(&filter).next().map(|city| city.id.as_str()), Some("mexicali-mx"));
+
+        let error = parse_csv("name,country,lat,lon\nNowhere,XX,95,0\n").unwrap_err();
+        assert!(format!("{:#}", error).contains("line 2"));
+        assert!(parse_csv("name,country,lat,lon\nA,PK,1,1,\"open\n").is_err());
+    }
+
+    #[test]
+    fn generates_unique_ids_for_non_latin_names() {
+        let csv = "name,country,lat,lon\n\
+                   東京,JP,35.6762,139.6503\n\
+                   大阪,JP,34.6937,135.5023\n\
+                   Москва,RU,55.7558,37.6173\n\
+                   Springfield,US,39.7817,-89.6501\n\
+                   Springfield,US,37.2090,-93.2923\n";
+        let cities = CityCatalogue::new(parse_csv(csv).unwrap()).unwrap();
+        let id = |name: &str, lat: f64| {
+            cities
+                .all()
+                .iter()
+                .find(|city| city.name == name && city.lat == lat)
+                .map(|city| city.id.clone())
+                .unwrap()
+        };
+        assert_eq!(id("東京", 35.6762), "35-68n-139-65e-jp");
+        assert_eq!(id("大阪", 34.6937), "34-69n-135-50e-jp");
+        assert_eq!(id("Москва", 55.7558), "55-76n-37-62e-ru");
+        // Same name and country: the later one gets a suffix
+        assert_eq!(id("Springfield", 39.7817), "springfield-us");
+        assert_eq!(id("Springfield", 37.2090), "springfield-us-2");
+        assert_eq!(cities.resolve("東京").map(|city| city.country.as_str()), Some("JP"));
+
+        // Explicit ids stay strict, and a generated id never takes one
+        let csv = "id,name,country,lat,lon\nx,A,PK,1,1\nx,B,PK,2,2\n";
+        assert!(CityCatalogue::new(parse_csv(csv).unwrap()).is_err());
+        let csv = "id,name,country,lat,lon\n,Lahore,PK,31.5,74.3\nlahore-pk,Old Lahore,PK,31.6,74.3\n";
+        let cities = CityCatalogue::new(parse_csv(csv).unwrap()).unwrap();
+        assert_eq!(cities.get("lahore-pk").unwrap().name, "Old Lahore");
+        assert_eq!(cities.get("lahore-pk-2").unwrap().name, "Lahore");
+    }
+}
@@ -1,15 +1,33 @@
 // src/services/earthengine.rs
+//
+// Client for the Earth Engine REST API (https://earthengine.googleapis.com/v1).
+// Expressions are sent as serialized `ValueNode` graphs, the same shape as
+// `gee/expression.json`.
 
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use reqwest::Client;
 use anyhow::{Result, Context};
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Mutex;
+use crate::config::EarthEngineConfig;
+use crate::services::gee_auth::{self, AccessToken, ServiceAccountKey};
+use crate::services::gee_composites::{self, Dataset, YearBands, ERA5, MODIS};
+use crate::services::gee_expression::{
+    Expression, Geometry, Image, ImageCollection, Reducer, ValueNode, VisParams,
+};
+use crate::services::vis_registry::VisRegistry;
+use crate::models::tile_config::{TileConfig, TileLayer};
 
-#[derive(Debug, Serialize)]
-struct LSTRequest {
-    bbox: Vec<f64>,
-    width: u32,
-    height: u32,
-}
+/// Number of days averaged for the point LST thumbnail
+const LST_WINDOW_DAYS: i64 = 30;
+
+/// Sampling scale for point values, the MODIS LST resolution in metres
+const POINT_SCALE_METERS: f64 = 1000.0;
 
 #[derive(Debug, Serialize, Deserialize)] // <- Added Serialize
 pub struct LSTResponse {
@@ -18,51 +36,538 @@ pub struct LSTResponse {
     pub units: String,
 }
 
+/// A year's composite values at one point; `None` where the pixel is masked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PointValues {
+    pub lst: Option<f64>,
+    pub climatology_mean: Option<f64>,
+    /// Difference from the climatology mean in °C
+    pub anomaly: Option<f64>,
+    /// Difference in climatological standard deviations
+    pub z_score: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct NamedResource {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ComputeValueResponse {
+    result: Value,
+}
+
+/// Credentials resolved so far. The key file is read once, when a token or
+/// the project is first needed.
+struct Auth {
+    key: Option<ServiceAccountKey>,
+    project: Option<String>,
+    token: Option<AccessToken>,
+}
+
 pub struct EarthEngineService {
     client: Client,
-    python_service_url: String,
+    api_url: String,
+    service_account_key: PathBuf,
+    auth: Mutex<Auth>,
+    /// Why requests are refused, when no credentials were found at startup
+    disabled: Option<String>,
+    vis: Arc<VisRegistry>,
+    /// Months with data of past years, which no longer change
+    complete_months: StdMutex<HashMap<(&'static str, i32), Vec<u32>>>,
 }
 
 impl EarthEngineService {
-    pub fn new(python_service_url: String) -> Self {
+    pub fn new(config: &EarthEngineConfig, vis: Arc<VisRegistry>) -> Self {
         Self {
             client: Client::new(),
-            python_service_url,
+            api_url: config.api_url.trim_end_matches('/').to_string(),
+            service_account_key: config.service_account_key.clone(),
+            auth: Mutex::new(Auth {
+                key: None,
+                project: config.project.clone(),
+                token: config
+                    .access_token
+                    .as_ref()
+                    .map(|token| AccessToken::fixed(token.expose().to_string())),
+            }),
+            disabled: None,
+            vis,
+            complete_months: StdMutex::new(HashMap::new()),
         }
     }
 
+    /// Refuse every request with `reason` instead of trying to authenticate.
+    pub fn disabled(mut self, reason: String) -> Self {
+        self.disabled = Some(reason);
+        self
+    }
+
+    pub fn service_account_key(&self) -> &Path {
+        &self.service_account_key
+    }
+
+    /// Create a map from an expression and return its `{z}/{x}/{y}` tile URL template.
+    pub async fn create_map(&self, image: Image) -> Result<String> {
+        let body = json!({
+            "expression": Expression::new(image),
+            "fileFormat": "PNG",
+        });
+
+        let map: NamedResource = self.post("maps", &body).await?;
+        Ok(format!("{}/v1/{}/tiles/{{z}}/{{x}}/{{y}}", self.api_url, map.name))
+    }
+
+    /// Evaluate an expression server-side and return its JSON result (`value:compute`).
+    pub async fn compute_value(&self, value: impl Into<ValueNode>) -> Result<Value> {
+        let body = json!({ "expression": Expression::new(value) });
+
+        let response: ComputeValueResponse = self.post("value:compute", &body).await?;
+        Ok(response.result)
+    }
+
+    /// Mean daytime LST over the last 30 days as a PNG thumbnail covering `bbox`
+    /// (`[west, south, east, north]`).
     pub async fn get_lst_data(
         &self,
         bbox: Vec<f64>,
         width: u32,
         height: u32,
     ) -> Result<LSTResponse> {
-        let request = LSTRequest {
-            bbox,
-            width,
-            height,
-        };
+        let [west, south, east, north]: [f64; 4] = bbox
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("bbox must have 4 elements"))?;
+
+        let end = Utc::now().date_naive();
+        let start = end - Duration::days(LST_WINDOW_DAYS);
+        let image = recent_lst_expression(
+            &start.to_string(),
+            &end.to_string(),
+            [west, south, east, north],
+        );
+
+        let body = json!({
+            "expression": Expression::new(image),
+            "fileFormat": "PNG",
+            "grid": {
+                "dimensions": { "width": width, "height": height },
+                "affineTransform": {
+                    "scaleX": (east - west) / width as f64,
+                    "translateX": west,
+                    "scaleY": -(north - south) / height as f64,
+                    "translateY": north,
+                },
+                "crsCode": "EPSG:4326",
+            },
+        });
+
+        let thumbnail: NamedResource = self.post("thumbnails", &body).await?;
+
+        Ok(LSTResponse {
+            url: format!("{}/v1/{}:getPixels", self.api_url, thumbnail.name),
+            date_range: format!("{}/{}", start, end),
+            units: "C".to_string(),
+        })
+    }
+
+    /// Build the LST, anomaly and absolute-anomaly maps for `year` directly on
+    /// Earth Engine (the native counterpart of `scripts/gee_rust.py`). Progress
+    /// lines are the same ones the script prints, sent to `progress`.
+    pub async fn generate_year_tiles(
+        &self,
+        year: i32,
+        progress: &UnboundedSender<String>,
+    ) -> Result<TileConfig> {
+        let today = Utc::now().date_naive();
+        let last_month = if year == today.year() { today.month() } else { 12 };
+
+        report(progress, "Processing MODIS data...".to_string());
+        let modis_monthly = self.available_months(MODIS, year, last_month, progress).await?;
+        if modis_monthly.is_empty() {
+            anyhow::bail!("No MODIS data available for the target year.");
+        }
+
+        report(progress, "Processing ERA5 data...".to_string());
+        let era5_monthly = self.available_months(ERA5, year, last_month, progress).await?;
+        if era5_monthly.is_empty() {
+            anyhow::bail!("No ERA5 data available for the target year.");
+        }
+
+        report(progress, "Generating tile URLs...".to_string());
+        let layers = gee_composites::year_layers(modis_monthly, era5_monthly, &self.vis);
+        Ok(TileConfig {
+            lst_tile_url: self.create_map(layers.lst).await?,
+            anomaly_tile_url: self.create_map(layers.anomaly).await?,
+            absolute_anomaly_tile_url: self.create_map(layers.absolute_anomaly).await?,
+            generated_at: Some(Utc::now().to_rfc3339()),
+            target_period: Some(gee_composites::target_period(year, last_month)),
+            climatology_period: Some(gee_composites::climatology_period()),
+            data_source: Some(gee_composites::data_source()),
+            description: Some(gee_composites::DESCRIPTION.to_string()),
+        })
+    }
+
+    /// Values of `year`'s composites at a point, from the same expression
+    /// graph as the year's maps.
+    pub async fn sample_point(&self, year: i32, lat: f64, lng: f64) -> Result<PointValues> {
+        let bands = self.year_bands(year).await?;
+        let stack = bands
+            .lst
+            .rename("lst")
+            .add_bands(bands.climatology_mean.rename("climatology_mean"))
+            .add_bands(bands.absolute_anomaly.rename("anomaly"))
+            .add_bands(bands.anomaly.rename("z_score"));
+        let values = self
+            .compute_value(stack.reduce_region(
+                Reducer::first(),
+                Geometry::point(lng, lat),
+                POINT_SCALE_METERS,
+            ))
+            .await?;
+
+        serde_json::from_value(values).context("Unexpected reduceRegion result")
+    }
+
+    /// Unvisualized `layer` of `to` minus that of `from`, band `difference`.
+    pub async fn year_difference(&self, layer: TileLayer, from: i32, to: i32) -> Result<Image> {
+        let from = self.year_bands(from).await?.layer(layer);
+        let to = self.year_bands(to).await?.layer(layer);
+        Ok(to.subtract(from).rename("difference"))
+    }
+
+    /// The composites of `year` built from its months with data.
+    pub async fn year_bands(&self, year: i32) -> Result<YearBands> {
+        let today = Utc::now().date_naive();
+        let last_month = if year == today.year() { today.month() } else { 12 };
+        let (progress, _) = tokio::sync::mpsc::unbounded_channel();
+
+        let modis_monthly = self.available_months(MODIS, year, last_month, &progress).await?;
+        let era5_monthly = self.available_months(ERA5, year, last_month, &progress).await?;
+        if modis_monthly.is_empty() || era5_monthly.is_empty() {
+            anyhow::bail!("No data available for {}", year);
+        }
+        Ok(gee_composites::year_bands(modis_monthly, era5_monthly))
+    }
+
+    /// Mean in °C and number of days with data of `dataset` at a point, per
+    /// calendar month over `[start, end)` with every year pooled. Reduced on
+    /// Earth Engine, so the answer is 24 numbers however long the period.
+    pub async fn monthly_point_means(
+        &self,
+        dataset: Dataset,
+        start: NaiveDate,
+        end: NaiveDate,
+        lat: f64,
+        lng: f64,
+    ) -> Result<[Option<(f64, u32)>; 12]> {
+        let collection = dataset.collection().filter_date(&start.to_string(), &end.to_string());
+        // Bands `m01_mean`, `m01_count`, ...; a month without images adds none
+        let stack = (1..=12)
+            .map(|month| {
+                collection
+                    .clone()
+                    .filter_calendar_range(month, month, "month")
+                    .reduce(Reducer::mean().combine(Reducer::count()))
+                    .regexp_rename(&format!("^{}", dataset.band), &format!("m{:02}", month))
+            })
+            .reduce(Image::add_bands)
+            .expect("twelve months");
+        let values = self
+            .compute_value(stack.reduce_region(Reducer::first(), Geometry::point(lng, lat), POINT_SCALE_METERS))
+            .await?;
+        let values = values.as_object().context("Unexpected reduceRegion result")?;
+
+        Ok(std::array::from_fn(|month0| {
+            let value = |output: &str| values.get(&format!("m{:02}_{}", month0 + 1, output));
+            let raw = value("mean")?.as_f64()?;
+            let days = value("count").and_then(Value::as_u64).unwrap_or(0);
+            (days > 0).then_some((raw * dataset.scale - 273.15, days as u32))
+        }))
+    }
+
+    /// Monthly collections for January..=`last_month` that contain at least one image.
+    async fn available_months(
+        &self,
+        dataset: Dataset,
+        year: i32,
+        last_month: u32,
+        progress: &UnboundedSender<String>,
+    ) -> Result<Vec<ImageCollection>> {
+        let complete = year < Utc::now().year();
+        if complete {
+            if let Some(months) = self.complete_months.lock().unwrap().get(&(dataset.id, year)) {
+                return Ok(months.iter().map(|&month| dataset.month(year, month)).collect());
+            }
+        }
+
+        let mut months = Vec::new();
+        for month in 1..=last_month {
+            let collection = dataset.month(year, month);
+            let count = self.compute_value(collection.clone().size()).await?;
+            if count.as_u64().unwrap_or(0) == 0 {
+                report(progress, format!("No data found for {}-{:02}", year, month));
+                continue;
+            }
+            months.push(month);
+        }
+
+        if complete {
+            self.complete_months
+                .lock()
+                .unwrap()
+                .insert((dataset.id, year), months.clone());
+        }
+        Ok(months.into_iter().map(|month| dataset.month(year, month)).collect())
+    }
+
+    /// POST `body` to `/v1/projects/{project}/{method}` and decode the JSON reply.
+    async fn post<T: for<'de> Deserialize<'de>>(&self, method: &str, body: &Value) -> Result<T> {
+        let (token, project) = self.credentials().await?;
+        let url = format!("{}/v1/projects/{}/{}", self.api_url, project, method);
 
         let response = self.client
-            .post(&format!("{}/get-lst-tile", self.python_service_url))
-            .json(&request)
+            .post(&url)
+            .bearer_auth(token)
+            .json(body)
             .send()
             .await
-            .context("Failed to send request to Python service")?;
+            .with_context(|| format!("Failed to send request to Earth Engine ({})", method))?;
 
         if !response.status().is_success() {
+            let status = response.status();
             let error_text = response.text().await.unwrap_or_default();
             return Err(anyhow::anyhow!(
-                "Python service returned error: {}",
+                "Earth Engine {} returned {}: {}",
+                method,
+                status,
                 error_text
             ));
         }
 
-        let lst_response = response
-            .json::<LSTResponse>()
+        response
+            .json::<T>()
             .await
-            .context("Failed to parse Python service response")?;
+            .with_context(|| format!("Failed to parse Earth Engine {} response", method))
+    }
+
+    /// Current access token and project, minting a new token when the cached one is stale.
+    async fn credentials(&self) -> Result<(String, String)> {
+        if let Some(reason) = &self.disabled {
+            anyhow::bail!("{}", reason);
+        }
+        let mut guard = self.auth.lock().await;
+        let auth = &mut *guard;
+        let fresh = auth
+            .token
+            .as_ref()
+            .filter(|token| token.is_fresh())
+            .map(|token| token.token.clone());
+
+        if let (Some(token), Some(project)) = (&fresh, &auth.project) {
+            return Ok((token.clone(), project.clone()));
+        }
+
+        let key = match auth.key.take() {
+            Some(key) => key,
+            None => ServiceAccountKey::from_file(&self.service_account_key).await?,
+        };
+        let key = &*auth.key.insert(key);
+        let project = auth
+            .project
+            .clone()
+            .or_else(|| key.project_id.clone())
+            .context("No Earth Engine project configured and none in the service account key")?;
+
+        let token = match fresh {
+            Some(token) => token,
+            None => {
+                let minted = gee_auth::fetch_access_token(&self.client, key).await?;
+                let token = minted.token.clone();
+                auth.token = Some(minted);
+                token
+            }
+        };
+        auth.project = Some(project.clone());
+
+        Ok((token, project))
+    }
+}
+
+/// Publish a progress line to the job running the generation, which records
+/// and streams it; nothing is printed from here.
+fn report(progress: &UnboundedSender<String>, line: String) {
+    let _ = progress.send(line);
+}
+
+/// MODIS daytime LST (°C) averaged over `[start, end)`, clipped to `bbox` and visualized.
+fn recent_lst_expression(start: &str, end: &str, bbox: [f64; 4]) -> Image {
+    let vis = VisParams {
+        min: 20.0,
+        max: 45.0,
+        palette: ["blue", "cyan", "green", "yellow", "orange", "red"]
+            .map(String::from)
+            .to_vec(),
+    };
+
+    MODIS
+        .to_celsius(MODIS.collection().filter_date(start, end).mean())
+        .clip(Geometry::rectangle(bbox))
+        .visualize(&vis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, http::HeaderMap, routing::post, Json, Router};
+    use tokio::net::TcpListener;
+
+    // Throwaway 2048-bit key generated for these tests only.
+    const TEST_PRIVATE_KEY: &str = include_str!("../../tests/fixtures/test_service_account_key.pem");
+
+    type Seen = Arc<StdMutex<Vec<(String, Option<String>, Value)>>>;
+
+    /// Stand-in for earthengine.googleapis.com and oauth2.googleapis.com on a random port.
+    async fn spawn_stub() -> (String, Seen) {
+        let seen: Seen = Arc::default();
+
+        async fn record(seen: &Seen, route: &str, headers: &HeaderMap, body: Value) {
+            let auth = headers
+                .get("authorization")
+                .map(|v| v.to_str().unwrap().to_string());
+            seen.lock().unwrap().push((route.to_string(), auth, body));
+        }
+
+        let app = Router::new()
+            .route(
+                "/token",
+                post(|State(seen): State<Seen>, body: String| async move {
+                    record(&seen, "token", &HeaderMap::new(), json!(body)).await;
+                    Json(json!({ "access_token": "minted-token", "expires_in": 3600, "token_type": "Bearer" }))
+                }),
+            )
+            .route(
+                "/v1/projects/test-project/maps",
+                post(|State(seen): State<Seen>, headers: HeaderMap, Json(body): Json<Value>| async move {
+                    record(&seen, "maps", &headers, body).await;
+                    Json(json!({ "name": "projects/test-project/maps/abc123" }))
+                }),
+            )
+            .route(
+                "/v1/projects/test-project/value:compute",
+                post(|State(seen): State<Seen>, headers: HeaderMap, Json(body): Json<Value>| async move {
+                    record(&seen, "compute", &headers, body).await;
+                    Json(json!({ "result": { "LST_Day_1km": 301.5 } }))
+                }),
+            )
+            .with_state(seen.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (base, seen)
+    }
+
+    fn config(api_url: &str) -> EarthEngineConfig {
+        EarthEngineConfig {
+            api_url: api_url.to_string(),
+            project: Some("test-project".to_string()),
+            access_token: Some("static-token".parse().unwrap()),
+            ..EarthEngineConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn create_map_returns_tile_template() {
+        let (base, seen) = spawn_stub().await;
+        let service = EarthEngineService::new(&config(&base), Arc::new(VisRegistry::builtin()));
+        let image = ImageCollection::load("MODIS/061/MOD11A1").mean();
+
+        let template = service.create_map(image.clone()).await.unwrap();
+
+        assert_eq!(
+            template,
+            format!("{}/v1/projects/test-project/maps/abc123/tiles/{{z}}/{{x}}/{{y}}", base)
+        );
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].1.as_deref(), Some("Bearer static-token"));
+        assert_eq!(
+            seen[0].2["expression"],
+            serde_json::to_value(Expression::new(image)).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn compute_value_returns_result() {
+        let (base, _) = spawn_stub().await;
+        let service = EarthEngineService::new(&config(&base), Arc::new(VisRegistry::builtin()));
+
+        let size = ImageCollection::load("MODIS/061/MOD11A1").size();
+        let result = service.compute_value(size).await.unwrap();
+
+        assert_eq!(result["LST_Day_1km"], json!(301.5));
+    }
+
+    #[tokio::test]
+    async fn mints_token_from_service_account_key() {
+        let (base, seen) = spawn_stub().await;
+        let key_path = std::env::temp_dir().join(format!("ee-key-{}.json", std::process::id()));
+        let key = json!({
+            "type": "service_account",
+            "client_email": "tests@example.iam.gserviceaccount.com",
+            "private_key": TEST_PRIVATE_KEY,
+            "private_key_id": "test-key",
+            "token_uri": format!("{}/token", base),
+        });
+        std::fs::write(&key_path, key.to_string()).unwrap();
+
+        let service = EarthEngineService::new(
+            &EarthEngineConfig {
+                access_token: None,
+                service_account_key: key_path.clone(),
+                ..config(&base)
+            },
+            Arc::new(VisRegistry::builtin()),
+        );
+        service.create_map(Image::constant(1.0)).await.unwrap();
+        service.create_map(Image::constant(2.0)).await.unwrap();
+        std::fs::remove_file(key_path).unwrap();
+
+        let seen = seen.lock().unwrap();
+        let routes: Vec<&str> = seen.iter().map(|(route, _, _)| route.as_str()).collect();
+        assert_eq!(routes, ["token", "maps", "maps"], "token should be cached");
+        assert!(seen[0].2.as_str().unwrap().contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer"));
+        assert_eq!(seen[1].1.as_deref(), Some("Bearer minted-token"));
+    }
+
+    #[tokio::test]
+    async fn reads_the_project_from_the_key_only_once() {
+        let (base, seen) = spawn_stub().await;
+        let key_path = std::env::temp_dir().join(format!("ee-key-{}.json", uuid::Uuid::new_v4()));
+        let key = json!({
+            "type": "service_account",
+            "client_email": "tests@example.iam.gserviceaccount.com",
+            "private_key": TEST_PRIVATE_KEY,
+            "token_uri": format!("{}/token", base),
+            "project_id": "test-project",
+        });
+        std::fs::write(&key_path, key.to_string()).unwrap();
+
+        let service = EarthEngineService::new(
+            &EarthEngineConfig {
+                project: None,
+                access_token: None,
+                service_account_key: key_path.clone(),
+                ..config(&base)
+            },
+            Arc::new(VisRegistry::builtin()),
+        );
+        service.create_map(Image::constant(1.0)).await.unwrap();
+        // Later requests need neither the file nor a new token
+        std::fs::remove_file(key_path).unwrap();
+        service.create_map(Image::constant(2.0)).await.unwrap();
 
-        Ok(lst_response)
+        let seen = seen.lock().unwrap();
+        let routes: Vec<&str> = seen.iter().map(|(route, _, _)| route.as_str()).collect();
+        assert_eq!(routes, ["token", "maps", "maps"]);
     }
 }
@@ -0,0 +1,119 @@
+// src/services/gee_auth.rs
+//
+// OAuth2 access tokens for Earth Engine, minted from a service account key
+// with the JWT bearer grant (RFC 7523).
+use anyhow::{Context, Result};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const EARTH_ENGINE_SCOPES: &str =
+    "https://www.googleapis.com/auth/earthengine https://www.googleapis.com/auth/cloud-platform";
+
+/// Lifetime requested for the signed assertion (Google caps this at one hour)
+const ASSERTION_LIFETIME_SECS: i64 = 3600;
+
+/// Tokens are refreshed this long before they actually expire
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServiceAccountKey {
+    pub client_email: String,
+    pub private_key: String,
+    pub private_key_id: Option<String>,
+    pub token_uri: String,
+    pub project_id: Option<String>,
+}
+
+impl ServiceAccountKey {
+    pub async fn from_file(path: &Path) -> Result<Self> {
+        let raw = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read service account key {}", path.display()))?;
+        serde_json::from_str(&raw)
+            .with_context(|| format!("Invalid service account key {}", path.display()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AccessToken {
+    pub token: String,
+    expires_at: Option<Instant>,
+}
+
+impl AccessToken {
+    /// A token supplied from outside (e.g. `gcloud auth print-access-token`), never refreshed
+    pub fn fixed(token: String) -> Self {
+        Self {
+            token,
+            expires_at: None,
+        }
+    }
+
+    pub fn is_fresh(&self) -> bool {
+        self.expires_at
+            .is_none_or(|expires_at| Instant::now() + EXPIRY_MARGIN < expires_at)
+    }
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Exchange a signed JWT assertion for an access token at the key's `token_uri`.
+pub async fn fetch_access_token(client: &Client, key: &ServiceAccountKey) -> Result<AccessToken> {
+    let iat = chrono::Utc::now().timestamp();
+    let claims = Claims {
+        iss: &key.client_email,
+        scope: EARTH_ENGINE_SCOPES,
+        aud: &key.token_uri,
+        iat,
+        exp: iat + ASSERTION_LIFETIME_SECS,
+    };
+
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = key.private_key_id.clone();
+    let signing_key = EncodingKey::from_rsa_pem(key.private_key.as_bytes())
+        .context("Service account private key is not a valid RSA PEM")?;
+    let assertion = encode(&header, &claims, &signing_key).context("Failed to sign JWT")?;
+
+    let requested_at = Instant::now();
+    let response = client
+        .post(&key.token_uri)
+        .form(&[
+            ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+            ("assertion", assertion.as_str()),
+        ])
+        .send()
+        .await
+        .context("Failed to reach OAuth token endpoint")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        anyhow::bail!("Token endpoint returned {}: {}", status, error_text);
+    }
+
+    let token = response
+        .json::<TokenResponse>()
+        .await
+        .context("Failed to parse OAuth token response")?;
+
+    Ok(AccessToken {
+        token: token.access_token,
+        expires_at: Some(requested_at + Duration::from_secs(token.expires_in)),
+    })
+}
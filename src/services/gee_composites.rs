@@ -0,0 +1,302 @@
+// src/services/gee_composites.rs
+//
+// Earth Engine expression graphs for the yearly LST composites, mirroring
+// `scripts/gee_rust.py`: MODIS daytime LST with ERA5-Land filling the gaps,
+// compared against a 2000–2025 climatology.
+use crate::models::tile_config::TileLayer;
+use crate::services::gee_expression::{Image, ImageCollection, Reducer};
+use crate::services::vis_registry::VisRegistry;
+
+pub const MODIS_LST: &str = "MODIS/061/MOD11A1";
+pub const ERA5_DAILY: &str = "ECMWF/ERA5_LAND/DAILY_AGGR";
+/// First year of MODIS Terra LST
+pub const FIRST_YEAR: i32 = 2000;
+pub const CLIMATOLOGY_START: &str = "2000-01-01";
+pub const CLIMATOLOGY_END: &str = "2025-07-31";
+pub const DESCRIPTION: &str =
+    "Mean daytime land surface temperature (MODIS, gaps filled with ERA5-Land) and anomalies";
+
+/// `2024` for a full year, `2025-01..2025-07` for a year to date.
+pub fn target_period(year: i32, last_month: u32) -> String {
+    if last_month == 12 {
+        year.to_string()
+    } else {
+        format!("{}-01..{}-{:02}", year, year, last_month)
+    }
+}
+
+pub fn climatology_period() -> String {
+    format!("{}..{}", CLIMATOLOGY_START, CLIMATOLOGY_END)
+}
+
+pub fn data_source() -> String {
+    format!("{} + {}", MODIS_LST, ERA5_DAILY)
+}
+
+/// Source dataset with the band and unit conversion used for the composites.
+#[derive(Debug, Clone, Copy)]
+pub struct Dataset {
+    pub id: &'static str,
+    pub band: &'static str,
+    pub scale: f64,
+}
+
+pub const MODIS: Dataset = Dataset {
+    id: MODIS_LST,
+    band: "LST_Day_1km",
+    scale: 0.02,
+};
+
+pub const ERA5: Dataset = Dataset {
+    id: ERA5_DAILY,
+    band: "temperature_2m",
+    scale: 1.0,
+};
+
+impl Dataset {
+    /// The dataset reduced to its temperature band.
+    pub fn collection(self) -> ImageCollection {
+        ImageCollection::load(self.id).select(&[self.band])
+    }
+
+    /// Raw Kelvin (scaled) values to °C.
+    pub fn to_celsius(self, image: Image) -> Image {
+        let scaled = if self.scale == 1.0 {
+            image
+        } else {
+            image.multiply(self.scale)
+        };
+        scaled.subtract(273.15)
+    }
+
+    /// Collection of this dataset's images for one calendar month.
+    pub fn month(self, year: i32, month: u32) -> ImageCollection {
+        let (start, end) = month_bounds(year, month);
+        self.collection().filter_date(&start, &end)
+    }
+
+    /// Mean of the given monthly collections, in °C.
+    pub fn yearly_celsius(self, monthly: Vec<ImageCollection>) -> Image {
+        let images = monthly.into_iter().map(ImageCollection::mean).collect();
+        self.to_celsius(ImageCollection::from_images(images).mean())
+    }
+
+    fn climatology(self) -> ImageCollection {
+        self.collection().filter_date(CLIMATOLOGY_START, CLIMATOLOGY_END)
+    }
+
+    pub fn climatology_mean_celsius(self) -> Image {
+        self.to_celsius(self.climatology().mean())
+    }
+
+    pub fn climatology_std(self) -> Image {
+        let std = self.climatology().reduce(Reducer::std_dev());
+        if self.scale == 1.0 {
+            std
+        } else {
+            std.multiply(self.scale)
+        }
+    }
+}
+
+/// The three visualized layers written to `tile_config_{year}.json`.
+pub struct YearLayers {
+    pub lst: Image,
+    pub anomaly: Image,
+    pub absolute_anomaly: Image,
+}
+
+/// Unvisualized per-pixel values of a year, in °C except the z-score.
+pub struct YearBands {
+    pub lst: Image,
+    pub climatology_mean: Image,
+    pub absolute_anomaly: Image,
+    pub anomaly: Image,
+}
+
+impl YearBands {
+    /// The band behind a tile layer
+    pub fn layer(self, layer: TileLayer) -> Image {
+        match layer {
+            TileLayer::Lst => self.lst,
+            TileLayer::Anomaly => self.anomaly,
+            TileLayer::AbsoluteAnomaly => self.absolute_anomaly,
+        }
+    }
+}
+
+/// MODIS LST filled with ERA5, plus z-score and absolute anomalies against
+/// the MODIS climatology. ERA5 is not resampled, as in `scripts/gee_rust.py`.
+pub fn year_bands(modis_monthly: Vec<ImageCollection>, era5_monthly: Vec<ImageCollection>) -> YearBands {
+    let modis_lst = MODIS.yearly_celsius(modis_monthly).rename("LST");
+    let era5_lst = ERA5.yearly_celsius(era5_monthly).rename("ERA5_LST");
+    let fused = modis_lst.unmask(era5_lst);
+
+    let climatology_mean = MODIS.climatology_mean_celsius();
+    let difference = fused.clone().subtract(climatology_mean.clone());
+    let anomaly = difference
+        .clone()
+        .divide(MODIS.climatology_std())
+        .rename("T_Anomaly");
+    let absolute_anomaly = difference.rename("T_Anomaly_C");
+
+    YearBands {
+        lst: fused,
+        climatology_mean,
+        absolute_anomaly,
+        anomaly,
+    }
+}
+
+pub fn year_layers(
+    modis_monthly: Vec<ImageCollection>,
+    era5_monthly: Vec<ImageCollection>,
+    vis: &VisRegistry,
+) -> YearLayers {
+    let bands = year_bands(modis_monthly, era5_monthly);
+    YearLayers {
+        lst: bands.lst.visualize(&vis.tile_layer(TileLayer::Lst).vis_params()),
+        anomaly: bands.anomaly.visualize(&vis.tile_layer(TileLayer::Anomaly).vis_params()),
+        absolute_anomaly: bands
+            .absolute_anomaly
+            .visualize(&vis.tile_layer(TileLayer::AbsoluteAnomaly).vis_params()),
+    }
+}
+
+/// `[first day, last day]` of a month as `YYYY-MM-DD`, matching `calendar.monthrange`.
+fn month_bounds(year: i32, month: u32) -> (String, String) {
+    let first = chrono::NaiveDate::from_ymd_opt(year, month, 1).expect("valid month");
+    let next = if month == 12 {
+        chrono::NaiveDate::from_ymd_opt(year + 1, 1, 1)
+    } else {
+        chrono::NaiveDate::from_ymd_opt(year, month + 1, 1)
+    }
+    .expect("valid month");
+    let last = next.pred_opt().expect("valid date");
+    (first.to_string(), last.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::gee_expression::Expression;
+    use serde_json::{json, Value};
+
+    fn months() -> (Vec<ImageCollection>, Vec<ImageCollection>) {
+        (vec![MODIS.month(2024, 7)], vec![ERA5.month(2024, 7)])
+    }
+
+    fn root(image: Image) -> Value {
+        serde_json::to_value(&Expression::new(image).values["0"]).unwrap()
+    }
+
+    /// `node` without the ids of hoisted function bodies, which depend on the root
+    fn shape(node: &Value) -> Value {
+        match node {
+            Value::Object(map) => map
+                .iter()
+                .filter(|(key, _)| key.as_str() != "body")
+                .map(|(key, value)| (key.clone(), shape(value)))
+                .collect(),
+            Value::Array(items) => items.iter().map(shape).collect(),
+            other => other.clone(),
+        }
+    }
+
+    /// Function name and arguments of an invocation node
+    fn call(node: &Value) -> (&str, &Value) {
+        let invocation = &node["functionInvocationValue"];
+        (invocation["functionName"].as_str().unwrap(), &invocation["arguments"])
+    }
+
+    /// Argument `name` of `node`, which must invoke `function`
+    fn arg<'a>(node: &'a Value, function: &str, name: &str) -> &'a Value {
+        let (found, arguments) = call(node);
+        assert_eq!(found, function, "{}", node);
+        &arguments[name]
+    }
+
+    /// The MODIS climatology collection: MODIS over 2000-01-01..2025-07-31
+    fn assert_climatology(node: &Value) {
+        let filter = arg(node, "Collection.filter", "filter");
+        let range = arg(filter, "Filter.dateRangeContains", "leftValue");
+        assert_eq!(arg(range, "DateRange", "start"), &json!({ "constantValue": CLIMATOLOGY_START }));
+        assert_eq!(arg(range, "DateRange", "end"), &json!({ "constantValue": CLIMATOLOGY_END }));
+        let selected = arg(node, "Collection.filter", "collection");
+        let loaded = arg(selected, "Collection.map", "collection");
+        assert_eq!(arg(loaded, "ImageCollection.load", "id"), &json!({ "constantValue": MODIS_LST }));
+    }
+
+    #[test]
+    fn fills_modis_gaps_with_era5_and_compares_against_the_climatology() {
+        let (modis, era5) = months();
+        let bands = year_bands(modis, era5);
+
+        // LST: MODIS (0.02 scale, K to °C) unmasked with ERA5 (already K)
+        let lst = root(bands.lst);
+        let modis_lst = arg(&lst, "Image.unmask", "input");
+        assert_eq!(arg(modis_lst, "Image.rename", "names"), &json!({ "constantValue": ["LST"] }));
+        let celsius = arg(modis_lst, "Image.rename", "input");
+        assert_eq!(arg(celsius, "Image.subtract", "image2"), &root(Image::constant(273.15)));
+        let scaled = arg(celsius, "Image.subtract", "image1");
+        assert_eq!(arg(scaled, "Image.multiply", "image2"), &root(Image::constant(0.02)));
+
+        let era5_lst = arg(&lst, "Image.unmask", "value");
+        assert_eq!(arg(era5_lst, "Image.rename", "names"), &json!({ "constantValue": ["ERA5_LST"] }));
+        let celsius = arg(era5_lst, "Image.rename", "input");
+        let mean = arg(celsius, "Image.subtract", "image1");
+        assert_eq!(call(mean).0, "ImageCollection.mean", "ERA5 is neither scaled nor resampled");
+
+        // Climatology mean: MODIS scaled by 0.02 and converted to °C
+        let mean = root(bands.climatology_mean);
+        let scaled = arg(&mean, "Image.subtract", "image1");
+        assert_eq!(arg(scaled, "Image.multiply", "image2"), &root(Image::constant(0.02)));
+        let collection = arg(arg(scaled, "Image.multiply", "image1"), "ImageCollection.mean", "collection");
+        assert_climatology(collection);
+        // The mapped body selects the daytime LST band
+        let (modis, era5) = months();
+        let expression = serde_json::to_value(Expression::new(year_bands(modis, era5).climatology_mean)).unwrap();
+        assert_eq!(
+            arg(&expression["values"]["1"], "Image.select", "bandSelectors"),
+            &json!({ "constantValue": [MODIS.band] })
+        );
+
+        // z-score: (LST - mean) / (stdDev * 0.02)
+        let anomaly = root(bands.anomaly);
+        assert_eq!(arg(&anomaly, "Image.rename", "names"), &json!({ "constantValue": ["T_Anomaly"] }));
+        let ratio = arg(&anomaly, "Image.rename", "input");
+        let difference = arg(ratio, "Image.divide", "image1");
+        assert_eq!(shape(arg(difference, "Image.subtract", "image1")), shape(&lst));
+        assert_eq!(shape(arg(difference, "Image.subtract", "image2")), shape(&mean));
+        let std = arg(ratio, "Image.divide", "image2");
+        assert_eq!(arg(std, "Image.multiply", "image2"), &root(Image::constant(0.02)));
+        let reduced = arg(std, "Image.multiply", "image1");
+        assert_eq!(call(arg(reduced, "ImageCollection.reduce", "reducer")).0, "Reducer.stdDev");
+        assert_climatology(arg(reduced, "ImageCollection.reduce", "collection"));
+
+        // Absolute anomaly: LST - mean in °C
+        let absolute = root(bands.absolute_anomaly);
+        assert_eq!(arg(&absolute, "Image.rename", "names"), &json!({ "constantValue": ["T_Anomaly_C"] }));
+        assert_eq!(shape(arg(&absolute, "Image.rename", "input")), shape(difference));
+    }
+
+    #[test]
+    fn visualizes_layers_with_the_registry_params() {
+        let vis = VisRegistry::builtin();
+        let (modis, era5) = months();
+        let layers = year_layers(modis, era5, &vis);
+        let (modis, era5) = months();
+        let bands = year_bands(modis, era5);
+
+        for (layer, image, band) in [
+            (TileLayer::Lst, layers.lst, bands.lst.clone()),
+            (TileLayer::Anomaly, layers.anomaly, bands.anomaly.clone()),
+            (TileLayer::AbsoluteAnomaly, layers.absolute_anomaly, bands.absolute_anomaly.clone()),
+        ] {
+            assert_eq!(root(image), root(band.visualize(&vis.tile_layer(layer).vis_params())));
+        }
+        let params = vis.tile_layer(TileLayer::Anomaly).vis_params();
+        let anomaly = root(year_layers(months().0, months().1, &vis).anomaly);
+        assert_eq!(arg(&anomaly, "Image.visualize", "palette"), &json!({ "constantValue": params.palette }));
+    }
+}
@@ -0,0 +1,566 @@
+// src/services/gee_expression.rs
+//
+// Typed builders for Earth Engine expression graphs. Each builder wraps a
+// `ValueNode` and serializes to the same JSON the Python client sends, e.g.
+// `gee/expression.json`. Mapped function bodies are hoisted into the
+// expression's `values` table and referenced by id, as the REST API expects.
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// Argument name given to functions passed to `Collection.map`. Mapped
+/// functions are never nested here, so a single name is enough.
+const MAPPING_VAR: &str = "_MAPPING_VAR_0_0";
+
+// -------------------- Value Nodes --------------------
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ValueNode {
+    ConstantValue(Value),
+    FunctionInvocationValue(FunctionInvocation),
+    FunctionDefinitionValue(FunctionDefinition),
+    ArgumentReference(String),
+    ArrayValue { values: Vec<ValueNode> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionInvocation {
+    pub function_name: String,
+    pub arguments: BTreeMap<String, ValueNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionDefinition {
+    pub argument_names: Vec<String>,
+    /// Id of the body in the expression's `values`; filled in by `Expression::new`
+    pub body: String,
+    #[serde(skip)]
+    body_node: Option<Box<ValueNode>>,
+}
+
+impl ValueNode {
+    fn invoke<const N: usize>(function_name: &str, arguments: [(&str, ValueNode); N]) -> Self {
+        ValueNode::FunctionInvocationValue(FunctionInvocation {
+            function_name: function_name.to_string(),
+            arguments: arguments
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        })
+    }
+
+    fn constant(value: impl Serialize) -> Self {
+        ValueNode::ConstantValue(serde_json::to_value(value).expect("constant serializes"))
+    }
+
+    /// A number constant; whole numbers are encoded as integers like the Python client does.
+    fn number(value: f64) -> Self {
+        if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+            ValueNode::ConstantValue(json!(value as i64))
+        } else {
+            ValueNode::ConstantValue(json!(value))
+        }
+    }
+
+    fn function(body: ValueNode) -> Self {
+        ValueNode::FunctionDefinitionValue(FunctionDefinition {
+            argument_names: vec![MAPPING_VAR.to_string()],
+            body: String::new(),
+            body_node: Some(Box::new(body)),
+        })
+    }
+
+    /// Move function bodies out of the tree into `values`, replacing them with ids.
+    fn hoist(&mut self, values: &mut BTreeMap<String, ValueNode>) {
+        match self {
+            ValueNode::FunctionInvocationValue(invocation) => {
+                for argument in invocation.arguments.values_mut() {
+                    argument.hoist(values);
+                }
+            }
+            ValueNode::ArrayValue { values: items } => {
+                for item in items {
+                    item.hoist(values);
+                }
+            }
+            ValueNode::FunctionDefinitionValue(definition) => {
+                if let Some(mut body) = definition.body_node.take() {
+                    let id = values.len().to_string();
+                    values.insert(id.clone(), ValueNode::ConstantValue(Value::Null));
+                    body.hoist(values);
+                    values.insert(id.clone(), *body);
+                    definition.body = id;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A complete REST `Expression`: the root lives at `values["0"]`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Expression {
+    pub result: String,
+    pub values: BTreeMap<String, ValueNode>,
+}
+
+impl Expression {
+    pub fn new(root: impl Into<ValueNode>) -> Self {
+        let mut root = root.into();
+        let mut values = BTreeMap::new();
+        values.insert("0".to_string(), ValueNode::ConstantValue(Value::Null));
+        root.hoist(&mut values);
+        values.insert("0".to_string(), root);
+
+        Self {
+            result: "0".to_string(),
+            values,
+        }
+    }
+}
+
+// -------------------- Typed Builders --------------------
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageCollection(ValueNode);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image(ValueNode);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reducer(ValueNode);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geometry(ValueNode);
+
+/// Scalar result such as `Collection.size`, only useful with `value:compute`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputedValue(ValueNode);
+
+/// `min`/`max`/`palette` as accepted by `Image.visualize` and the legend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisParams {
+    pub min: f64,
+    pub max: f64,
+    pub palette: Vec<String>,
+}
+
+impl ImageCollection {
+    pub fn load(id: &str) -> Self {
+        Self(ValueNode::invoke("ImageCollection.load", [("id", ValueNode::constant(id))]))
+    }
+
+    pub fn from_images(images: Vec<Image>) -> Self {
+        let values = images.into_iter().map(|image| image.0).collect();
+        Self(ValueNode::invoke(
+            "ImageCollection.fromImages",
+            [("images", ValueNode::ArrayValue { values })],
+        ))
+    }
+
+    /// Keep images whose `system:time_start` falls in `[start, end)`.
+    pub fn filter_date(self, start: &str, end: &str) -> Self {
+        let range = ValueNode::invoke(
+            "DateRange",
+            [("start", ValueNode::constant(start)), ("end", ValueNode::constant(end))],
+        );
+        let filter = ValueNode::invoke(
+            "Filter.dateRangeContains",
+            [
+                ("leftValue", range),
+                ("rightField", ValueNode::constant("system:time_start")),
+            ],
+        );
+        Self(ValueNode::invoke(
+            "Collection.filter",
+            [("collection", self.0), ("filter", filter)],
+        ))
+    }
+
+    /// Keep images whose `field` (`month`, `day_of_year`, ...) of
+    /// `system:time_start` lies in `start..=end`.
+    pub fn filter_calendar_range(self, start: u32, end: u32, field: &str) -> Self {
+        let filter = ValueNode::invoke(
+            "Filter.calendarRange",
+            [
+                ("start", ValueNode::constant(start)),
+                ("end", ValueNode::constant(end)),
+                ("field", ValueNode::constant(field)),
+            ],
+        );
+        Self(ValueNode::invoke(
+            "Collection.filter",
+            [("collection", self.0), ("filter", filter)],
+        ))
+    }
+
+    /// Apply `f` to every image (`Collection.map`).
+    pub fn map(self, f: impl FnOnce(Image) -> Image) -> Self {
+        let argument = Image(ValueNode::ArgumentReference(MAPPING_VAR.to_string()));
+        Self(ValueNode::invoke(
+            "Collection.map",
+            [
+                ("collection", self.0),
+                ("baseAlgorithm", ValueNode::function(f(argument).0)),
+            ],
+        ))
+    }
+
+    pub fn select(self, bands: &[&str]) -> Self {
+        let bands: Vec<String> = bands.iter().map(|band| band.to_string()).collect();
+        self.map(move |image| image.select_owned(bands))
+    }
+
+    pub fn size(self) -> ComputedValue {
+        ComputedValue(ValueNode::invoke("Collection.size", [("collection", self.0)]))
+    }
+
+    pub fn mean(self) -> Image {
+        Image(ValueNode::invoke("ImageCollection.mean", [("collection", self.0)]))
+    }
+
+    /// Reduce per pixel; output bands get the reducer's suffix (e.g. `_stdDev`).
+    pub fn reduce(self, reducer: Reducer) -> Image {
+        Image(ValueNode::invoke(
+            "ImageCollection.reduce",
+            [("collection", self.0), ("reducer", reducer.0)],
+        ))
+    }
+}
+
+impl Image {
+    pub fn constant(value: f64) -> Self {
+        Self(ValueNode::invoke("Image.constant", [("value", ValueNode::number(value))]))
+    }
+
+    /// Area of each pixel in square metres
+    pub fn pixel_area() -> Self {
+        Self(ValueNode::invoke("Image.pixelArea", []))
+    }
+
+    pub fn select(self, bands: &[&str]) -> Self {
+        self.select_owned(bands.iter().map(|band| band.to_string()).collect())
+    }
+
+    fn select_owned(self, bands: Vec<String>) -> Self {
+        Self(ValueNode::invoke(
+            "Image.select",
+            [("input", self.0), ("bandSelectors", ValueNode::constant(bands))],
+        ))
+    }
+
+    pub fn rename(self, name: &str) -> Self {
+        Self(ValueNode::invoke(
+            "Image.rename",
+            [("input", self.0), ("names", ValueNode::constant([name]))],
+        ))
+    }
+
+    fn binary(self, function_name: &str, other: impl Into<Image>) -> Self {
+        Self(ValueNode::invoke(
+            function_name,
+            [("image1", self.0), ("image2", other.into().0)],
+        ))
+    }
+
+    pub fn multiply(self, other: impl Into<Image>) -> Self {
+        self.binary("Image.multiply", other)
+    }
+
+    pub fn subtract(self, other: impl Into<Image>) -> Self {
+        self.binary("Image.subtract", other)
+    }
+
+    pub fn divide(self, other: impl Into<Image>) -> Self {
+        self.binary("Image.divide", other)
+    }
+
+    /// The image's mask as an image, 0 where masked
+    pub fn mask(self) -> Self {
+        Self(ValueNode::invoke("Image.mask", [("image", self.0)]))
+    }
+
+    /// Additionally mask pixels where `mask` is 0.
+    pub fn update_mask(self, mask: Image) -> Self {
+        Self(ValueNode::invoke(
+            "Image.updateMask",
+            [("image", self.0), ("mask", mask.0)],
+        ))
+    }
+
+    /// Rename bands matching `regex`; unlike `rename`, an image without bands passes through.
+    pub fn regexp_rename(self, regex: &str, replacement: &str) -> Self {
+        Self(ValueNode::invoke(
+            "Image.regexpRename",
+            [
+                ("input", self.0),
+                ("regex", ValueNode::constant(regex)),
+                ("replacement", ValueNode::constant(replacement)),
+            ],
+        ))
+    }
+
+    /// Replace masked pixels with `other`.
+    pub fn unmask(self, other: impl Into<Image>) -> Self {
+        Self(ValueNode::invoke(
+            "Image.unmask",
+            [("input", self.0), ("value", other.into().0)],
+        ))
+    }
+
+    /// Resampling mode used when the image is reprojected: `bilinear` or `bicubic`.
+    /// The composites keep the default nearest-neighbour, as `scripts/gee_rust.py` does.
+    #[allow(dead_code)]
+    pub fn resample(self, mode: &str) -> Self {
+        Self(ValueNode::invoke(
+            "Image.resample",
+            [("image", self.0), ("mode", ValueNode::constant(mode))],
+        ))
+    }
+
+    pub fn clip(self, geometry: Geometry) -> Self {
+        Self(ValueNode::invoke(
+            "Image.clip",
+            [("input", self.0), ("geometry", geometry.0)],
+        ))
+    }
+
+    /// Append the bands of `other`.
+    pub fn add_bands(self, other: Image) -> Self {
+        Self(ValueNode::invoke(
+            "Image.addBands",
+            [("dstImg", self.0), ("srcImg", other.0)],
+        ))
+    }
+
+    /// Reduce the pixels under `geometry` at `scale` metres to a band-name → value
+    /// dictionary. Large regions are reduced at a coarser scale rather than failing.
+    pub fn reduce_region(self, reducer: Reducer, geometry: Geometry, scale: f64) -> ComputedValue {
+        ComputedValue(ValueNode::invoke(
+            "Image.reduceRegion",
+            [
+                ("image", self.0),
+                ("reducer", reducer.0),
+                ("geometry", geometry.0),
+                ("scale", ValueNode::number(scale)),
+                ("bestEffort", ValueNode::constant(true)),
+            ],
+        ))
+    }
+
+    pub fn visualize(self, vis: &VisParams) -> Self {
+        Self(ValueNode::invoke(
+            "Image.visualize",
+            [
+                ("image", self.0),
+                ("min", ValueNode::number(vis.min)),
+                ("max", ValueNode::number(vis.max)),
+                ("palette", ValueNode::constant(&vis.palette)),
+            ],
+        ))
+    }
+}
+
+impl Reducer {
+    pub fn std_dev() -> Self {
+        Self(ValueNode::invoke("Reducer.stdDev", []))
+    }
+
+    pub fn mean() -> Self {
+        Self(ValueNode::invoke("Reducer.mean", []))
+    }
+
+    pub fn sum() -> Self {
+        Self(ValueNode::invoke("Reducer.sum", []))
+    }
+
+    /// Number of unmasked pixels
+    pub fn count() -> Self {
+        Self(ValueNode::invoke("Reducer.count", []))
+    }
+
+    /// Outputs `p{n}` for each percentile
+    pub fn percentile(percentiles: &[u32]) -> Self {
+        Self(ValueNode::invoke(
+            "Reducer.percentile",
+            [("percentiles", ValueNode::constant(percentiles))],
+        ))
+    }
+
+    /// Outputs `min` and `max`
+    pub fn min_max() -> Self {
+        Self(ValueNode::invoke("Reducer.minMax", []))
+    }
+
+    /// Run both reducers on the same input; outputs are named `{band}_{output}`.
+    pub fn combine(self, other: Reducer) -> Self {
+        Self(ValueNode::invoke(
+            "Reducer.combine",
+            [
+                ("reducer1", self.0),
+                ("reducer2", other.0),
+                ("sharedInputs", ValueNode::constant(true)),
+            ],
+        ))
+    }
+
+    /// Value of the first unmasked pixel, for sampling a single point
+    pub fn first() -> Self {
+        Self(ValueNode::invoke("Reducer.first", []))
+    }
+}
+
+impl Geometry {
+    pub fn point(lng: f64, lat: f64) -> Self {
+        Self(ValueNode::invoke(
+            "GeometryConstructors.Point",
+            [("coordinates", ValueNode::constant([lng, lat]))],
+        ))
+    }
+
+    /// From GeoJSON MultiPolygon coordinates
+    pub fn multi_polygon(coordinates: Vec<Vec<Vec<[f64; 2]>>>) -> Self {
+        Self(ValueNode::invoke(
+            "GeometryConstructors.MultiPolygon",
+            [("coordinates", ValueNode::constant(coordinates))],
+        ))
+    }
+
+    /// Axis-aligned rectangle from `[west, south, east, north]`.
+    pub fn rectangle(bbox: [f64; 4]) -> Self {
+        Self(ValueNode::invoke(
+            "GeometryConstructors.Rectangle",
+            [("coordinates", ValueNode::constant(bbox))],
+        ))
+    }
+}
+
+impl From<f64> for Image {
+    fn from(value: f64) -> Self {
+        Image::constant(value)
+    }
+}
+
+impl From<Image> for ValueNode {
+    fn from(image: Image) -> Self {
+        image.0
+    }
+}
+
+impl From<ComputedValue> for ValueNode {
+    fn from(value: ComputedValue) -> Self {
+        value.0
+    }
+}
+
+impl From<ImageCollection> for ValueNode {
+    fn from(collection: ImageCollection) -> Self {
+        collection.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_golden_expression() {
+        let vis = VisParams {
+            min: 20.0,
+            max: 45.0,
+            palette: ["blue", "cyan", "green", "yellow", "orange", "red"]
+                .map(String::from)
+                .to_vec(),
+        };
+        // `Collection.first` has no builder; only the golden expression needs it
+        let first = |collection: ImageCollection| {
+            Image(ValueNode::invoke("Collection.first", [("collection", collection.0)]))
+        };
+        let image = first(ImageCollection::load("MODIS/006/MOD11A1").select(&["LST_Day_1km"]))
+            .multiply(0.02)
+            .subtract(273.15)
+            .visualize(&vis);
+
+        let expression = Expression::new(image);
+        let golden: Value =
+            serde_json::from_str(include_str!("../../gee/expression.json")).unwrap();
+
+        assert_eq!(serde_json::to_value(&expression.values["0"]).unwrap(), golden);
+        assert_eq!(
+            serde_json::to_value(&expression.values["1"]).unwrap(),
+            json!({
+                "functionInvocationValue": {
+                    "functionName": "Image.select",
+                    "arguments": {
+                        "input": { "argumentReference": "_MAPPING_VAR_0_0" },
+                        "bandSelectors": { "constantValue": ["LST_Day_1km"] },
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn nested_images_serialize_inline() {
+        let images = vec![
+            ImageCollection::load("a").mean(),
+            ImageCollection::load("b").mean(),
+        ];
+        let expression = Expression::new(ImageCollection::from_images(images).size());
+
+        assert_eq!(expression.values.len(), 1);
+        let node = serde_json::to_value(&expression.values["0"]).unwrap();
+        assert_eq!(
+            node["functionInvocationValue"]["arguments"]["collection"]["functionInvocationValue"]
+                ["arguments"]["images"]["arrayValue"]["values"][1]["functionInvocationValue"]["functionName"],
+            json!("ImageCollection.mean")
+        );
+    }
+
+    #[test]
+    fn filters_by_calendar_month() {
+        let image = ImageCollection::load("a")
+            .filter_calendar_range(7, 7, "month")
+            .reduce(Reducer::mean())
+            .regexp_rename("^b", "m07");
+        let node = serde_json::to_value(&Expression::new(image).values["0"]).unwrap();
+        let arguments = &node["functionInvocationValue"]["arguments"];
+        assert_eq!(arguments["replacement"], json!({ "constantValue": "m07" }));
+
+        let filter = &arguments["input"]["functionInvocationValue"]["arguments"]["collection"]
+            ["functionInvocationValue"]["arguments"]["filter"]["functionInvocationValue"];
+        assert_eq!(filter["functionName"], json!("Filter.calendarRange"));
+        assert_eq!(filter["arguments"]["start"], json!({ "constantValue": 7 }));
+        assert_eq!(filter["arguments"]["field"], json!({ "constantValue": "month" }));
+    }
+
+    #[test]
+    fn resamples_bilinearly() {
+        let image = ImageCollection::load("ECMWF/ERA5_LAND/DAILY_AGGR").mean().resample("bilinear");
+        assert_eq!(
+            serde_json::to_value(&Expression::new(image).values["0"]).unwrap(),
+            json!({
+                "functionInvocationValue": {
+                    "functionName": "Image.resample",
+                    "arguments": {
+                        "image": {
+                            "functionInvocationValue": {
+                                "functionName": "ImageCollection.mean",
+                                "arguments": {
+                                    "collection": {
+                                        "functionInvocationValue": {
+                                            "functionName": "ImageCollection.load",
+                                            "arguments": { "id": { "constantValue": "ECMWF/ERA5_LAND/DAILY_AGGR" } }
+                                        }
+                                    }
+                                }
+                            }
+                        },
+                        "mode": { "constantValue": "bilinear" },
+                    }
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,496 @@
+// src/services/grid_renderer.rs
+//
+// Renders XYZ tiles from local `.npy` grids so maps can be served without
+// Earth Engine. A grid is a float32 array in equirectangular (plate carrée)
+// layout, written flattened row by row from the north-west corner:
+//
+//     np.save("lst_2024.npy", grid.astype("float32").ravel())
+//
+// next to a sidecar `lst_2024.json` describing it:
+//
+//     { "width": 3600, "height": 1800, "bounds": [-180, -90, 180, 90], "nodata": -9999 }
+//
+// Tiles are sampled per output pixel in Web Mercator, coloured with the
+// layer's ramp and encoded as PNG; NaN and `nodata` cells are transparent.
+use crate::config::TilesConfig;
+use crate::models::tile_config::ChangeStats;
+use crate::models::zone::Zone;
+use crate::services::gee_expression::VisParams;
+use anyhow::{anyhow, bail, Context, Result};
+use image::{ImageFormat, Rgba, RgbaImage};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::PI;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+const TILE_SIZE: u32 = 256;
+
+/// Contents of the sidecar `{name}.json`
+#[derive(Debug, Clone, Deserialize)]
+pub struct GridGeoReference {
+    pub width: usize,
+    pub height: usize,
+    /// `[west, south, east, north]` in degrees
+    pub bounds: [f64; 4],
+    #[serde(default)]
+    pub nodata: Option<f32>,
+}
+
+pub struct Grid {
+    georef: GridGeoReference,
+    values: Vec<f32>,
+}
+
+impl Grid {
+    /// Load `{name}.npy` and its sidecar `{name}.json` from `dir`.
+    pub fn load(dir: &Path, name: &str) -> Result<Self> {
+        let sidecar_path = dir.join(format!("{}.json", name));
+        let sidecar = std::fs::read_to_string(&sidecar_path)
+            .with_context(|| format!("reading {}", sidecar_path.display()))?;
+        let georef: GridGeoReference = serde_json::from_str(&sidecar)
+            .with_context(|| format!("parsing {}", sidecar_path.display()))?;
+
+        let [west, south, east, north] = georef.bounds;
+        if west >= east || south >= north {
+            bail!("{}: bounds must be [west, south, east, north]", sidecar_path.display());
+        }
+
+        let npy_path = dir.join(format!("{}.npy", name));
+        let bytes =
+            std::fs::read(&npy_path).with_context(|| format!("reading {}", npy_path.display()))?;
+        let values = npy::NpyData::<f32>::from_bytes(&bytes)
+            .with_context(|| format!("{}: expected a flattened float32 array", npy_path.display()))?
+            .to_vec();
+
+        if values.len() != georef.width * georef.height {
+            bail!(
+                "{}: {} values, but the sidecar describes {}x{}",
+                npy_path.display(),
+                values.len(),
+                georef.width,
+                georef.height
+            );
+        }
+
+        Ok(Self { georef, values })
+    }
+
+    /// Nearest cell to a point, `None` outside the grid or on nodata.
+    fn sample(&self, lon: f64, lat: f64) -> Option<f32> {
+        let [west, south, east, north] = self.georef.bounds;
+        if lon < west || lon >= east || lat <= south || lat > north {
+            return None;
+        }
+        let col = ((lon - west) / (east - west) * self.georef.width as f64) as usize;
+        let row = ((north - lat) / (north - south) * self.georef.height as f64) as usize;
+        let value = *self
+            .values
+            .get(row.min(self.georef.height - 1) * self.georef.width + col.min(self.georef.width - 1))?;
+
+        if value.is_nan() || self.georef.nodata == Some(value) {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    /// PNG of tile `z/x/y` coloured with `vis`.
+    pub fn render_tile(&self, vis: &VisParams, z: u32, x: u32, y: u32) -> Result<Vec<u8>> {
+        render(vis, z, x, y, |lon, lat| self.sample(lon, lat))
+    }
+
+    /// Centres of the cells inside `bbox`, row by row
+    fn cell_centres(&self, bbox: [f64; 4]) -> impl Iterator<Item = (f64, f64)> + '_ {
+        let [west, south, east, north] = self.georef.bounds;
+        let cell_width = (east - west) / self.georef.width as f64;
+        let cell_height = (north - south) / self.georef.height as f64;
+        let [bbox_west, bbox_south, bbox_east, bbox_north] = bbox;
+        (0..self.georef.height)
+            .map(move |row| north - (row as f64 + 0.5) * cell_height)
+            .filter(move |lat| (bbox_south..=bbox_north).contains(lat))
+            .flat_map(move |lat| {
+                (0..self.georef.width)
+                    .map(move |col| west + (col as f64 + 0.5) * cell_width)
+                    .filter(move |lon| (bbox_west..=bbox_east).contains(lon))
+                    .map(move |lon| (lon, lat))
+            })
+    }
+}
+
+/// PNG of tile `z/x/y` with each pixel coloured from `sample(lon, lat)`.
+fn render(vis: &VisParams, z: u32, x: u32, y: u32, sample: impl Fn(f64, f64) -> Option<f32>) -> Result<Vec<u8>> {
+    let ramp = ColorRamp::new(vis)?;
+    let world = (TILE_SIZE as f64) * (1u64 << z) as f64;
+
+    let mut image = RgbaImage::new(TILE_SIZE, TILE_SIZE);
+    for py in 0..TILE_SIZE {
+        // Latitude of the pixel centre, inverting the Web Mercator projection
+        let merc_y = (y * TILE_SIZE + py) as f64 + 0.5;
+        let lat = (PI * (1.0 - 2.0 * merc_y / world)).sinh().atan().to_degrees();
+        for px in 0..TILE_SIZE {
+            let lon = ((x * TILE_SIZE + px) as f64 + 0.5) / world * 360.0 - 180.0;
+            let pixel = match sample(lon, lat) {
+                Some(value) => ramp.color(value as f64),
+                None => Rgba([0, 0, 0, 0]),
+            };
+            image.put_pixel(px, py, pixel);
+        }
+    }
+
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageFormat::Png)?;
+    Ok(png.into_inner())
+}
+
+/// Linear interpolation between evenly spaced palette colours over `min..max`.
+pub struct ColorRamp {
+    min: f64,
+    max: f64,
+    stops: Vec<[u8; 3]>,
+}
+
+impl ColorRamp {
+    pub fn new(vis: &VisParams) -> Result<Self> {
+        let stops = vis
+            .palette
+            .iter()
+            .map(|color| parse_hex(color).ok_or_else(|| anyhow!("invalid palette colour {:?}", color)))
+            .collect::<Result<Vec<_>>>()?;
+        if stops.is_empty() {
+            bail!("palette is empty");
+        }
+        Ok(Self {
+            min: vis.min,
+            max: vis.max,
+            stops,
+        })
+    }
+
+    pub fn color(&self, value: f64) -> Rgba<u8> {
+        let t = ((value - self.min) / (self.max - self.min)).clamp(0.0, 1.0);
+        let position = t * (self.stops.len() - 1) as f64;
+        let lower = position.floor() as usize;
+        let upper = (lower + 1).min(self.stops.len() - 1);
+        let fraction = position - lower as f64;
+
+        let [r, g, b] = [0, 1, 2].map(|channel| {
+            let from = self.stops[lower][channel] as f64;
+            let to = self.stops[upper][channel] as f64;
+            (from + (to - from) * fraction).round() as u8
+        });
+        Rgba([r, g, b, 255])
+    }
+}
+
+/// `ff8b13` or `#ff8b13`
+fn parse_hex(color: &str) -> Option<[u8; 3]> {
+    let hex = color.trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// A loaded grid and the modification time of the files it came from
+struct CachedGrid {
+    grid: Arc<Grid>,
+    modified: SystemTime,
+    size: u64,
+    tick: u64,
+}
+
+/// Loaded grids in least recently used order
+#[derive(Default)]
+struct GridCache {
+    entries: HashMap<String, CachedGrid>,
+    order: BTreeMap<u64, String>,
+    next_tick: u64,
+    total_bytes: u64,
+}
+
+impl GridCache {
+    fn get(&mut self, name: &str, modified: SystemTime) -> Option<Arc<Grid>> {
+        let entry = self.entries.get_mut(name).filter(|entry| entry.modified == modified)?;
+        self.order.remove(&entry.tick);
+        entry.tick = self.next_tick;
+        self.order.insert(self.next_tick, name.to_string());
+        self.next_tick += 1;
+        Some(entry.grid.clone())
+    }
+
+    /// Insert `grid`, then drop the least recently used others until the
+    /// cache fits `max_bytes`; a single grid larger than that stays alone.
+    fn insert(&mut self, name: &str, grid: Arc<Grid>, modified: SystemTime, max_bytes: u64) {
+        self.remove(name);
+        let size = (grid.values.len() * std::mem::size_of::<f32>()) as u64;
+        self.entries.insert(
+            name.to_string(),
+            CachedGrid { grid, modified, size, tick: self.next_tick },
+        );
+        self.order.insert(self.next_tick, name.to_string());
+        self.next_tick += 1;
+        self.total_bytes += size;
+
+        while self.total_bytes > max_bytes && self.entries.len() > 1 {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.total_bytes -= entry.size;
+                println!("Dropped grid {} from memory", oldest);
+            }
+        }
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Some(entry) = self.entries.remove(name) {
+            self.order.remove(&entry.tick);
+            self.total_bytes -= entry.size;
+        }
+    }
+}
+
+/// Grids under `tiles.grid_dir`, loaded on first use and kept in memory up
+/// to `max_bytes`. A grid whose files change on disk is loaded again.
+pub struct GridRenderer {
+    dir: PathBuf,
+    max_bytes: u64,
+    grids: Mutex<GridCache>,
+}
+
+impl GridRenderer {
+    pub fn new(config: &TilesConfig) -> Self {
+        Self::with_limit(&config.grid_dir, config.grid_cache_max_mb * 1024 * 1024)
+    }
+
+    pub(crate) fn with_limit(dir: &Path, max_bytes: u64) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            max_bytes,
+            grids: Mutex::new(GridCache::default()),
+        }
+    }
+
+    pub async fn render_tile(&self, name: &str, vis: &VisParams, z: u32, x: u32, y: u32) -> Result<Vec<u8>> {
+        let grid = self.grid(name).await?;
+        let vis = vis.clone();
+        tokio::task::spawn_blocking(move || grid.render_tile(&vis, z, x, y)).await?
+    }
+
+    /// PNG of grid `to` minus grid `from`; cells missing in either are transparent.
+    pub async fn render_difference(
+        &self,
+        from: &str,
+        to: &str,
+        vis: &VisParams,
+        (z, x, y): (u32, u32, u32),
+    ) -> Result<Vec<u8>> {
+        let (from, to) = (self.grid(from).await?, self.grid(to).await?);
+        let vis = vis.clone();
+        tokio::task::spawn_blocking(move || {
+            render(&vis, z, x, y, |lon, lat| Some(to.sample(lon, lat)? - from.sample(lon, lat)?))
+        })
+        .await?
+    }
+
+    /// Mean, min and max of grid `to` minus grid `from` over the cells of `to` inside `bbox`.
+    pub async fn difference_stats(&self, from: &str, to: &str, bbox: [f64; 4]) -> Result<ChangeStats> {
+        let (from, to) = (self.grid(from).await?, self.grid(to).await?);
+        tokio::task::spawn_blocking(move || {
+            let (mut sum, mut count) = (0.0, 0u64);
+            let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+            for (lon, lat) in to.cell_centres(bbox) {
+                let (Some(new), Some(old)) = (to.sample(lon, lat), from.sample(lon, lat)) else {
+                    continue;
+                };
+                let change = (new - old) as f64;
+                sum += change;
+                count += 1;
+                min = min.min(change);
+                max = max.max(change);
+            }
+            let found = count > 0;
+            ChangeStats {
+                bbox,
+                mean: found.then(|| sum / count as f64),
+                min: found.then_some(min),
+                max: found.then_some(max),
+            }
+        })
+        .await
+        .map_err(Into::into)
+    }
+
+    /// `(value, weight)` of every cell of grid `name` whose centre is inside
+    /// `zone`, weighted by the cell's relative area (cos latitude).
+    pub async fn zonal_values(&self, name: &str, zone: &Zone) -> Result<Vec<(f64, f64)>> {
+        let grid = self.grid(name).await?;
+        let zone = zone.clone();
+        tokio::task::spawn_blocking(move || {
+            grid.cell_centres(zone.bbox())
+                .filter(|&(lon, lat)| zone.contains(lon, lat))
+                .filter_map(|(lon, lat)| Some((grid.sample(lon, lat)? as f64, lat.to_radians().cos())))
+                .collect()
+        })
+        .await
+        .map_err(Into::into)
+    }
+
+    /// Value of grid `name` at a point, `None` outside it or on nodata.
+    pub async fn sample(&self, name: &str, lon: f64, lat: f64) -> Result<Option<f64>> {
+        Ok(self.grid(name).await?.sample(lon, lat).map(f64::from))
+    }
+
+    async fn grid(&self, name: &str) -> Result<Arc<Grid>> {
+        if name.is_empty() || name.contains(['/', '\\', '.']) {
+            bail!("invalid grid name {:?}", name);
+        }
+        let modified = self.modified(name).await?;
+        if let Some(grid) = self.grids.lock().unwrap().get(name, modified) {
+            return Ok(grid);
+        }
+
+        let dir = self.dir.clone();
+        let owned_name = name.to_string();
+        let grid = Arc::new(tokio::task::spawn_blocking(move || Grid::load(&dir, &owned_name)).await??);
+        println!("Loaded grid {} from {}", name, self.dir.display());
+        self.grids
+            .lock()
+            .unwrap()
+            .insert(name, grid.clone(), modified, self.max_bytes);
+        Ok(grid)
+    }
+
+    /// Latest modification time of `{name}.npy` and its sidecar
+    async fn modified(&self, name: &str) -> Result<SystemTime> {
+        let mut latest = SystemTime::UNIX_EPOCH;
+        for extension in ["npy", "json"] {
+            let path = self.dir.join(format!("{}.{}", name, extension));
+            let metadata = tokio::fs::metadata(&path)
+                .await
+                .with_context(|| format!("reading {}", path.display()))?;
+            latest = latest.max(metadata.modified()?);
+        }
+        Ok(latest)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::EarthEngineConfig;
+    use crate::services::earthengine::EarthEngineService;
+    use crate::services::vis_registry::VisRegistry;
+
+    /// Write `{name}.npy` and its sidecar to `dir`, row-major from the north-west corner.
+    pub(crate) fn write_grid(dir: &Path, name: &str, values: &[f32], width: usize, height: usize, bounds: [f64; 4]) {
+        std::fs::create_dir_all(dir).unwrap();
+        npy::to_file(dir.join(format!("{}.npy", name)), values.to_vec()).unwrap();
+        let sidecar = serde_json::json!({ "width": width, "height": height, "bounds": bounds });
+        std::fs::write(dir.join(format!("{}.json", name)), sidecar.to_string()).unwrap();
+    }
+
+    /// Write `tile_config_{year}.json` pointing the lst, anomaly and absolute
+    /// anomaly layers at local grids.
+    pub(crate) fn write_local_config(dir: &Path, year: i32, [lst, anomaly, absolute_anomaly]: [&str; 3]) {
+        std::fs::create_dir_all(dir).unwrap();
+        let url = |grid: &str| format!("local://{}/{{z}}/{{x}}/{{y}}", grid);
+        let config = serde_json::json!({
+            "lst_tile_url": url(lst),
+            "anomaly_tile_url": url(anomaly),
+            "absolute_anomaly_tile_url": url(absolute_anomaly),
+        });
+        std::fs::write(dir.join(format!("tile_config_{}.json", year)), config.to_string()).unwrap();
+    }
+
+    /// Earth Engine client for tests that must never reach it
+    pub(crate) fn offline_earth_engine() -> Arc<EarthEngineService> {
+        Arc::new(EarthEngineService::new(
+            &EarthEngineConfig::default(),
+            Arc::new(VisRegistry::builtin()),
+        ))
+    }
+
+    #[test]
+    fn renders_grid_with_ramp_and_transparency() {
+        let dir = std::env::temp_dir().join(format!("grid-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // 2x2 global grid: north-west 0, north-east 10, south-west nodata, south-east NaN
+        npy::to_file(dir.join("test.npy"), vec![0.0f32, 10.0, -9999.0, f32::NAN]).unwrap();
+        std::fs::write(
+            dir.join("test.json"),
+            r#"{ "width": 2, "height": 2, "bounds": [-180, -90, 180, 90], "nodata": -9999 }"#,
+        )
+        .unwrap();
+
+        let grid = Grid::load(&dir, "test").unwrap();
+        let vis = VisParams {
+            min: 0.0,
+            max: 10.0,
+            palette: vec!["0000ff".to_string(), "ff0000".to_string()],
+        };
+        let png = grid.render_tile(&vis, 0, 0, 0).unwrap();
+        let tile = image::load_from_memory(&png).unwrap().to_rgba8();
+
+        assert_eq!(tile.get_pixel(64, 64), &Rgba([0, 0, 255, 255]));
+        assert_eq!(tile.get_pixel(192, 64), &Rgba([255, 0, 0, 255]));
+        assert_eq!(tile.get_pixel(64, 192)[3], 0);
+        assert_eq!(tile.get_pixel(192, 192)[3], 0);
+    }
+
+    #[tokio::test]
+    async fn keeps_recent_grids_within_the_limit_and_reloads_replaced_ones() {
+        let dir = std::env::temp_dir().join(format!("grid-cache-{}", uuid::Uuid::new_v4()));
+        let global = [-180.0, -90.0, 180.0, 90.0];
+        for (name, value) in [("a", 1.0), ("b", 2.0), ("c", 3.0)] {
+            write_grid(&dir, name, &[value], 1, 1, global);
+        }
+        // Room for two single-cell grids
+        let renderer = GridRenderer::with_limit(&dir, 8);
+        let cached = |renderer: &GridRenderer| {
+            let mut names: Vec<String> = renderer.grids.lock().unwrap().entries.keys().cloned().collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(renderer.sample("a", 0.0, 0.0).await.unwrap(), Some(1.0));
+        assert_eq!(renderer.sample("b", 0.0, 0.0).await.unwrap(), Some(2.0));
+        assert_eq!(renderer.sample("a", 0.0, 0.0).await.unwrap(), Some(1.0));
+        assert_eq!(renderer.sample("c", 0.0, 0.0).await.unwrap(), Some(3.0));
+        assert_eq!(cached(&renderer), ["a", "c"]);
+        assert_eq!(renderer.grids.lock().unwrap().total_bytes, 8);
+
+        // Replace `a` on disk; the later modification time invalidates the cached copy
+        write_grid(&dir, "a", &[5.0], 1, 1, global);
+        std::fs::File::options()
+            .write(true)
+            .open(dir.join("a.npy"))
+            .unwrap()
+            .set_modified(SystemTime::now() + std::time::Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(renderer.sample("a", 0.0, 0.0).await.unwrap(), Some(5.0));
+        assert_eq!(cached(&renderer), ["a", "c"]);
+        assert_eq!(renderer.grids.lock().unwrap().total_bytes, 8);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn interpolates_between_stops() {
+        let ramp = ColorRamp::new(&VisParams {
+            min: -1.0,
+            max: 1.0,
+            palette: vec!["0000ff".to_string(), "ffffff".to_string(), "ff0000".to_string()],
+        })
+        .unwrap();
+
+        assert_eq!(ramp.color(0.0), Rgba([255, 255, 255, 255]));
+        assert_eq!(ramp.color(0.5), Rgba([255, 128, 128, 255]));
+        assert_eq!(ramp.color(-5.0), Rgba([0, 0, 255, 255]));
+    }
+}
@@ -1,52 +1,250 @@
-use crate::models::heat_alert::HeatAlert;
-use crate::utils::cities::CITIES;
+// src/services/heat_alert_services.rs
+//
+// One heat-alert run: every catalogue city is polled with bounded concurrency,
+// failures are retried with exponential backoff, and the run ends with the top
+// alerts, every city's reading and a report of which cities succeeded or failed. Provider request
+// caps are enforced by the weather chain itself.
+use crate::config::HeatAlertConfig;
+use crate::models::city::City;
+use crate::models::heat_alert::{CityFailure, HeatAlert, HeatReading, PollReport};
+use crate::services::city_catalogue::CityCatalogue;
+use crate::services::heat_severity::{Assessment, HeatSeverityEngine};
+use crate::services::weather::{CurrentConditions, WeatherChain, WeatherError};
 use chrono::Utc;
-use reqwest::Client;
+use futures_util::stream::{self, StreamExt};
+use std::sync::Arc;
+use std::time::Duration;
 
-const OPENWEATHER_API_KEY: &str = "dd759e220492384e47590a6108043aaa";
+/// Alerts kept from each run
+const TOP_ALERTS: usize = 3;
 
-pub async fn fetch_city_temperature(client: &Client, lat: f64, lon: f64) -> Option<f64> {
-    let url = format!(
-        "https://api.openweathermap.org/data/2.5/weather?lat={}&lon={}&units=metric&appid={}",
-        lat, lon, OPENWEATHER_API_KEY
-    );
-
-    let resp = client.get(&url).send().await.ok()?;
-    let json: serde_json::Value = resp.json().await.ok()?;
+/// Everything one run produced.
+pub struct PollRun {
+    /// Most severe first
+    pub top_alerts: Vec<HeatAlert>,
+    /// One per city that answered
+    pub readings: Vec<HeatReading>,
+    pub report: PollReport,
+}
 
-    json["main"]["temp"].as_f64()
+pub struct HeatAlertPoller {
+    cities: Arc<CityCatalogue>,
+    weather: Arc<WeatherChain>,
+    severity: Arc<HeatSeverityEngine>,
+    concurrency: usize,
+    retries: u32,
 }
 
-pub async fn get_top_heat_alerts() -> Vec<HeatAlert> {
-    let client = Client::new();
-    let mut alerts = Vec::new();
+impl HeatAlertPoller {
+    pub fn new(
+        config: &HeatAlertConfig,
+        cities: Arc<CityCatalogue>,
+        weather: Arc<WeatherChain>,
+        severity: Arc<HeatSeverityEngine>,
+    ) -> Self {
+        Self {
+            cities,
+            weather,
+            severity,
+            concurrency: config.concurrency,
+            retries: config.retries,
+        }
+    }
+
+    /// Poll every city.
+    pub async fn poll(&self) -> PollRun {
+        let started_at = Utc::now();
+        let now = started_at.format("%Y-%m-%d %H:%M UTC").to_string();
+        let mut alerts = Vec::new();
+        let mut readings = Vec::new();
+        let mut report = PollReport {
+            started_at,
+            finished_at: started_at,
+            duration_ms: 0,
+            cities: self.cities.all().len(),
+            succeeded: 0,
+            retried: 0,
+            failed: Vec::new(),
+        };
+
+        // Indices rather than `&City`s keep the stream's future `Send` for `tokio::spawn`
+        let cities = self.cities.all();
+        let mut results = stream::iter(0..cities.len())
+            .map(|i| self.assessed(&cities[i]))
+            .buffer_unordered(self.concurrency);
 
-    for city in CITIES.iter() {
-        if let Some(temp) = fetch_city_temperature(&client, city.lat, city.lon).await {
+        while let Some((city, outcome)) = results.next().await {
+            let (conditions, assessment) = match outcome {
+                Ok((conditions, assessment, attempts)) => {
+                    report.succeeded += 1;
+                    if attempts > 1 {
+                        report.retried += 1;
+                    }
+                    (conditions, assessment)
+                }
+                Err((e, attempts)) => {
+                    eprintln!("Heat alert: no temperature for {} after {} attempts: {}", city.name, attempts, e);
+                    report.failed.push(CityFailure {
+                        city_id: city.id.clone(),
+                        city: city.name.clone(),
+                        attempts,
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+            readings.push(HeatReading {
+                city_id: city.id.clone(),
+                city: city.name.clone(),
+                observed_at: conditions.observed_at,
+                utc_offset_seconds: conditions.utc_offset_seconds,
+                temperature: conditions.temperature,
+                humidity: conditions.humidity,
+                metric: assessment.metric,
+                metric_value: assessment.value,
+                severity: assessment.severity,
+                threshold: assessment.threshold,
+                heat_threshold: Some(assessment.heat_threshold),
+                provider: conditions.provider.to_string(),
+            });
             alerts.push(HeatAlert {
-                city: city.name.to_string(),
-                temperature: temp,
-                severity: "".to_string(),
-                time_ago: "".to_string(),
+                city_id: city.id.clone(),
+                city: city.name.clone(),
+                country: city.country.clone(),
+                temperature: conditions.temperature,
+                humidity: conditions.humidity,
+                severity: assessment.severity,
+                metric: assessment.metric,
+                metric_value: assessment.value,
+                threshold: assessment.threshold,
+                time_ago: now.clone(),
+                heatwave_id: None,
+                heatwave_day: None,
             });
         }
+
+        // Most severe first, then the most heat stress
+        alerts.sort_by(|a, b| {
+            b.severity
+                .cmp(&a.severity)
+                .then(b.metric_value.total_cmp(&a.metric_value))
+        });
+        alerts.truncate(TOP_ALERTS);
+
+        report.finished_at = Utc::now();
+        report.duration_ms = (report.finished_at - started_at).num_milliseconds().max(0) as u64;
+        report.failed.sort_by(|a, b| a.city_id.cmp(&b.city_id));
+        readings.sort_by(|a, b| a.city_id.cmp(&b.city_id));
+        PollRun {
+            top_alerts: alerts,
+            readings,
+            report,
+        }
     }
 
-    alerts.sort_by(|a, b| b.temperature.partial_cmp(&a.temperature).unwrap());
-    let mut top3 = alerts.into_iter().take(3).collect::<Vec<_>>();
+    /// Current conditions in `city` with their assessment, so climatology
+    /// lookups run concurrently too.
+    async fn assessed<'a>(&self, city: &'a City) -> (&'a City, Result<(CurrentConditions, Assessment, u32), (WeatherError, u32)>) {
+        let (city, outcome) = self.current(city).await;
+        match outcome {
+            Ok((conditions, attempts)) => {
+                let assessment = self.severity.assess(city, &conditions).await;
+                (city, Ok((conditions, assessment, attempts)))
+            }
+            Err(e) => (city, Err(e)),
+        }
+    }
 
-    let now = Utc::now().format("%Y-%m-%d %H:%M UTC").to_string();
+    /// Current conditions in `city` and the attempts they took, or the last error.
+    async fn current<'a>(&self, city: &'a City) -> (&'a City, Result<(CurrentConditions, u32), (WeatherError, u32)>) {
+        let mut attempt = 0;
+        loop {
+            match self.weather.current(city.lat, city.lon).await {
+                Ok(conditions) => return (city, Ok((conditions, attempt + 1))),
+                // An empty chain will not recover
+                Err(e) if attempt < self.retries && !e.0.is_empty() => {
+                    attempt += 1;
+                    tokio::time::sleep(Duration::from_millis(500 * 2u64.pow(attempt - 1))).await;
+                }
+                Err(e) => return (city, Err((e, attempt + 1))),
+            }
+        }
+    }
+}
 
-    for alert in &mut top3 {
-        alert.severity = if alert.temperature > 45.0 {
-            "extreme".to_string()
-        } else if alert.temperature > 40.0 {
-            "warning".to_string()
-        } else {
-            "normal".to_string()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::weather::{HourlySeries, MockProvider, WeatherProvider};
+    use async_trait::async_trait;
+    use chrono::NaiveDate;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Fails its first `failures` calls for current conditions
+    struct Flaky {
+        failures: AtomicU32,
+        inner: MockProvider,
+    }
+
+    #[async_trait]
+    impl WeatherProvider for Flaky {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        async fn current(&self, lat: f64, lng: f64) -> anyhow::Result<CurrentConditions> {
+            if self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok() {
+                anyhow::bail!("temporarily unavailable");
+            }
+            self.inner.current(lat, lng).await
+        }
+
+        async fn hourly_forecast(&self, lat: f64, lng: f64, hours: u32) -> anyhow::Result<HourlySeries> {
+            self.inner.hourly_forecast(lat, lng, hours).await
+        }
+
+        async fn historical(&self, lat: f64, lng: f64, start: NaiveDate, end: NaiveDate) -> anyhow::Result<HourlySeries> {
+            self.inner.historical(lat, lng, start, end).await
+        }
+    }
+
+    fn poller(provider: Arc<dyn WeatherProvider>, retries: u32) -> HeatAlertPoller {
+        let config = HeatAlertConfig {
+            climatology_years: 0,
+            retries,
+            ..HeatAlertConfig::default()
         };
-        alert.time_ago = now.clone();
+        let weather = Arc::new(WeatherChain::new(vec![provider]));
+        let severity = Arc::new(HeatSeverityEngine::new(&config, weather.clone()));
+        HeatAlertPoller::new(&config, Arc::new(CityCatalogue::builtin()), weather, severity)
     }
 
-    top3
+    #[tokio::test]
+    async fn retries_failed_cities_and_reports_the_rest() {
+        let flaky = Flaky {
+            failures: AtomicU32::new(2),
+            inner: MockProvider::new(46.0),
+        };
+        let PollRun {
+            top_alerts: alerts,
+            readings,
+            report,
+        } = poller(Arc::new(flaky), 1).poll().await;
+        assert_eq!(report.succeeded, report.cities);
+        assert_eq!(readings.len(), report.cities);
+        assert_eq!(report.retried, 2);
+        assert!(report.failed.is_empty());
+        assert_eq!(alerts.len(), TOP_ALERTS);
+        assert_eq!(alerts[0].severity, crate::models::heat_alert::Severity::Extreme);
+
+        let PollRun {
+            top_alerts: alerts,
+            report,
+            ..
+        } = poller(Arc::new(MockProvider::failing()), 1).poll().await;
+        assert!(alerts.is_empty());
+        assert_eq!(report.failed.len(), report.cities);
+        assert_eq!(report.failed[0].attempts, 2);
+        assert!(report.failed[0].error.contains("mock provider configured to fail"));
+    }
 }
@@ -0,0 +1,347 @@
+// src/services/heat_history.rs
+//
+// Every heat-alert run's per-city readings, appended as JSON lines to
+// `heat_alert.history_file` and kept in memory for queries. On startup the
+// file is read back, readings older than `heat_alert.history_days` are dropped
+// and the file is rewritten without them; a running server does the same once
+// a day. Days are the city's local days, in queries as in streaks, so a streak
+// of hot days matches what people there lived through.
+use crate::config::HeatAlertConfig;
+use crate::models::heat_alert::{HeatReading, Severity, Threshold};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::RwLock;
+
+/// A city's highest reading on one local day.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DailyPeak {
+    pub date: NaiveDate,
+    pub max_temperature: f64,
+    pub max_metric_value: f64,
+    pub severity: Severity,
+    /// Threshold behind `severity`
+    pub threshold: Option<Threshold>,
+}
+
+/// Consecutive local days at advisory severity or above.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Streak {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub days: u32,
+    pub peak_metric_value: f64,
+    pub peak_severity: Severity,
+}
+
+/// A city's readings over a period with their extremes and streaks.
+#[derive(Debug, Serialize)]
+pub struct CityHistory {
+    pub readings: Vec<HeatReading>,
+    /// Readings with the highest and lowest temperature
+    pub max: Option<HeatReading>,
+    pub min: Option<HeatReading>,
+    pub days: Vec<DailyPeak>,
+    pub streaks: Vec<Streak>,
+    /// Length of the streak running through the latest day with readings
+    pub current_streak_days: u32,
+}
+
+pub struct HeatHistory {
+    path: PathBuf,
+    history_days: u32,
+    /// Per city id, oldest first
+    readings: RwLock<HashMap<String, Vec<HeatReading>>>,
+    /// UTC day old readings were last dropped; guarded by `readings`' write lock
+    compacted_on: std::sync::Mutex<NaiveDate>,
+}
+
+impl HeatHistory {
+    /// Load the history file, creating its directory if needed.
+    pub async fn open(config: &HeatAlertConfig) -> Result<Self> {
+        let path = config.history_file.clone();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .await
+                .with_context(|| format!("creating {}", dir.display()))?;
+        }
+
+        let raw = match fs::read_to_string(&path).await {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+        let cutoff = cutoff(config.history_days);
+        let mut readings: HashMap<String, Vec<HeatReading>> = HashMap::new();
+        let (mut kept, mut dropped) = (0, 0);
+        for (n, line) in raw.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            match serde_json::from_str::<HeatReading>(line) {
+                Ok(reading) if reading.observed_at >= cutoff => {
+                    readings.entry(reading.city_id.clone()).or_default().push(reading);
+                    kept += 1;
+                }
+                Ok(_) => dropped += 1,
+                Err(e) => {
+                    eprintln!("Skipping line {} of {}: {}", n + 1, path.display(), e);
+                    dropped += 1;
+                }
+            }
+        }
+        for city in readings.values_mut() {
+            city.sort_by_key(|reading| reading.observed_at);
+        }
+
+        if dropped > 0 {
+            rewrite(&path, readings.values().flatten()).await?;
+        }
+        println!("Loaded {} heat-alert readings from {} ({} dropped)", kept, path.display(), dropped);
+        Ok(Self {
+            path,
+            history_days: config.history_days,
+            readings: RwLock::new(readings),
+            compacted_on: std::sync::Mutex::new(Utc::now().date_naive()),
+        })
+    }
+
+    /// Append one run's readings to the file and the in-memory history. On
+    /// the first run of a day, readings past `heat_alert.history_days` are
+    /// dropped and the file is rewritten instead.
+    pub async fn record(&self, new: &[HeatReading]) {
+        let mut readings = self.readings.write().await;
+        for reading in new {
+            readings.entry(reading.city_id.clone()).or_default().push(reading.clone());
+        }
+
+        let today = Utc::now().date_naive();
+        let compact = {
+            let mut compacted_on = self.compacted_on.lock().unwrap();
+            std::mem::replace(&mut *compacted_on, today) < today
+        };
+        let mut dropped = 0;
+        if compact {
+            let cutoff = cutoff(self.history_days);
+            for city in readings.values_mut() {
+                let before = city.len();
+                city.retain(|reading| reading.observed_at >= cutoff);
+                dropped += before - city.len();
+            }
+            readings.retain(|_, city| !city.is_empty());
+        }
+
+        let written = if dropped > 0 {
+            println!("Dropping {} heat-alert readings older than {} days", dropped, self.history_days);
+            rewrite(&self.path, readings.values().flatten()).await
+        } else {
+            append(&self.path, new).await
+        };
+        if let Err(e) = written {
+            eprintln!("Failed to write heat-alert history to {}: {:#}", self.path.display(), e);
+        }
+    }
+
+    /// Readings from `from` to `to` (inclusive local days of each city) for
+    /// one city or all, oldest first.
+    pub async fn readings(&self, city_id: Option<&str>, from: NaiveDate, to: NaiveDate) -> Vec<HeatReading> {
+        let readings = self.readings.read().await;
+        let in_range = |reading: &&HeatReading| (from..=to).contains(&local_date(reading));
+        let mut found: Vec<HeatReading> = match city_id {
+            Some(id) => readings.get(id).into_iter().flatten().filter(in_range).cloned().collect(),
+            None => readings.values().flatten().filter(in_range).cloned().collect(),
+        };
+        found.sort_by(|a, b| a.observed_at.cmp(&b.observed_at).then_with(|| a.city_id.cmp(&b.city_id)));
+        found
+    }
+
+    /// Readings, extremes and streaks of one city from `from` to `to`.
+    pub async fn city(&self, city_id: &str, from: NaiveDate, to: NaiveDate) -> CityHistory {
+        let readings = self.readings(Some(city_id), from, to).await;
+        let max = readings.iter().max_by(|a, b| a.temperature.total_cmp(&b.temperature)).cloned();
+        let min = readings.iter().min_by(|a, b| a.temperature.total_cmp(&b.temperature)).cloned();
+        let days = daily_peaks(&readings);
+        let streaks = streaks(&days);
+        let current_streak_days = match (streaks.last(), days.last()) {
+            (Some(streak), Some(day)) if streak.end == day.date => streak.days,
+            _ => 0,
+        };
+        CityHistory {
+            readings,
+            max,
+            min,
+            days,
+            streaks,
+            current_streak_days,
+        }
+    }
+}
+
+/// Oldest observation time kept
+fn cutoff(history_days: u32) -> DateTime<Utc> {
+    Utc::now() - Duration::days(history_days as i64)
+}
+
+/// The local day `reading` belongs to
+pub fn local_date(reading: &HeatReading) -> NaiveDate {
+    (reading.observed_at + Duration::seconds(reading.utc_offset_seconds as i64)).date_naive()
+}
+
+/// Highest values and severity per local day, oldest first.
+pub fn daily_peaks(readings: &[HeatReading]) -> Vec<DailyPeak> {
+    let mut days: BTreeMap<NaiveDate, DailyPeak> = BTreeMap::new();
+    for reading in readings {
+        let date = local_date(reading);
+        let day = days.entry(date).or_insert(DailyPeak {
+            date,
+            max_temperature: reading.temperature,
+            max_metric_value: reading.metric_value,
+            severity: reading.severity,
+            threshold: reading.threshold,
+        });
+        day.max_temperature = day.max_temperature.max(reading.temperature);
+        day.max_metric_value = day.max_metric_value.max(reading.metric_value);
+        if reading.severity > day.severity {
+            day.severity = reading.severity;
+            day.threshold = reading.threshold;
+        }
+    }
+    days.into_values().collect()
+}
+
+/// Runs of consecutive days at advisory or above; a day without readings ends a run.
+pub fn streaks(days: &[DailyPeak]) -> Vec<Streak> {
+    let mut streaks: Vec<Streak> = Vec::new();
+    for day in days.iter().filter(|day| day.severity >= Severity::Advisory) {
+        match streaks.last_mut() {
+            Some(streak) if streak.end.succ_opt() == Some(day.date) => {
+                streak.end = day.date;
+                streak.days += 1;
+                streak.peak_metric_value = streak.peak_metric_value.max(day.max_metric_value);
+                streak.peak_severity = streak.peak_severity.max(day.severity);
+            }
+            _ => streaks.push(Streak {
+                start: day.date,
+                end: day.date,
+                days: 1,
+                peak_metric_value: day.max_metric_value,
+                peak_severity: day.severity,
+            }),
+        }
+    }
+    streaks
+}
+
+async fn append(path: &Path, readings: &[HeatReading]) -> Result<()> {
+    let mut lines = String::new();
+    for reading in readings {
+        lines.push_str(&serde_json::to_string(reading)?);
+        lines.push('\n');
+    }
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path).await?;
+    file.write_all(lines.as_bytes()).await?;
+    Ok(())
+}
+
+/// Replace the file with `readings` through a temporary file, so a crash leaves either version
+async fn rewrite<'a>(path: &Path, readings: impl Iterator<Item = &'a HeatReading>) -> Result<()> {
+    let mut lines = String::new();
+    for reading in readings {
+        lines.push_str(&serde_json::to_string(reading)?);
+        lines.push('\n');
+    }
+    let tmp_path = path.with_extension("jsonl.tmp");
+    fs::write(&tmp_path, lines).await?;
+    fs::rename(&tmp_path, path)
+        .await
+        .with_context(|| format!("rewriting {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::heat_alert::HeatMetric;
+
+    fn reading(observed_at: &str, temperature: f64, severity: Severity) -> HeatReading {
+        HeatReading {
+            city_id: "lahore-pk".to_string(),
+            city: "Lahore".to_string(),
+            observed_at: observed_at.parse::<DateTime<Utc>>().unwrap(),
+            utc_offset_seconds: 5 * 3600,
+            temperature,
+            humidity: None,
+            metric: HeatMetric::AirTemperature,
+            metric_value: temperature,
+            severity,
+            threshold: None,
+            heat_threshold: None,
+            provider: "mock".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn persists_readings_and_counts_local_day_streaks() {
+        let dir = std::env::temp_dir().join(format!("heat-history-{}", uuid::Uuid::new_v4()));
+        let config = HeatAlertConfig {
+            history_file: dir.join("history.jsonl"),
+            history_days: 10_000,
+            ..HeatAlertConfig::default()
+        };
+
+        let history = HeatHistory::open(&config).await.unwrap();
+        history
+            .record(&[
+                // 20:00 UTC is already the next day in Lahore
+                reading("2024-06-01T20:00:00Z", 41.0, Severity::Warning),
+                reading("2024-06-03T06:00:00Z", 36.0, Severity::Advisory),
+                reading("2024-06-03T12:00:00Z", 44.0, Severity::Warning),
+                reading("2024-06-05T09:00:00Z", 30.0, Severity::Normal),
+            ])
+            .await;
+        history.record(&[reading("2024-06-06T09:00:00Z", 46.0, Severity::Extreme)]).await;
+
+        // Reloaded from the file
+        let history = HeatHistory::open(&config).await.unwrap();
+        let from = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2024, 6, 30).unwrap();
+        let city = history.city("lahore-pk", from, to).await;
+        assert_eq!(city.readings.len(), 5);
+        assert_eq!(city.max.map(|r| r.temperature), Some(46.0));
+        assert_eq!(city.min.map(|r| r.temperature), Some(30.0));
+
+        let streak = &city.streaks[0];
+        assert_eq!((streak.start, streak.days, streak.peak_metric_value), (NaiveDate::from_ymd_opt(2024, 6, 2).unwrap(), 2, 44.0));
+        assert_eq!(city.streaks.len(), 2);
+        assert_eq!(city.current_streak_days, 1);
+        assert!(history.readings(Some("delhi-in"), from, to).await.is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn drops_expired_readings_while_running() {
+        let dir = std::env::temp_dir().join(format!("heat-history-{}", uuid::Uuid::new_v4()));
+        let config = HeatAlertConfig {
+            history_file: dir.join("history.jsonl"),
+            history_days: 2,
+            ..HeatAlertConfig::default()
+        };
+        let history = HeatHistory::open(&config).await.unwrap();
+        let now = Utc::now();
+        let old = reading(&(now - Duration::days(3)).to_rfc3339(), 40.0, Severity::Warning);
+        history.record(&[old]).await;
+        assert_eq!(history.readings(None, NaiveDate::MIN, NaiveDate::MAX).await.len(), 1);
+
+        // The first run of a new day compacts
+        *history.compacted_on.lock().unwrap() = now.date_naive() - Duration::days(1);
+        history.record(&[reading(&now.to_rfc3339(), 38.0, Severity::Advisory)]).await;
+        let kept = history.readings(None, NaiveDate::MIN, NaiveDate::MAX).await;
+        assert_eq!(kept.iter().map(|r| r.temperature).collect::<Vec<_>>(), [38.0]);
+        let file = std::fs::read_to_string(&config.history_file).unwrap();
+        assert_eq!(file.lines().count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,379 @@
+// src/services/heat_severity.rs
+//
+// Heat-alert severity from a heat-stress metric rather than air temperature
+// alone. The metric (heat index, humidex or WBGT) is computed from temperature
+// and humidity and compared with two scales: the city's own climatology (the
+// 90th/95th/99th percentiles of its daily maxima within a week of today's date
+// over past years) and fixed physiological thresholds. The higher level wins,
+// so humid heat is flagged even where it is common and dry heat is only flagged
+// where it is unusual or dangerous.
+use crate::config::HeatAlertConfig;
+use crate::models::city::City;
+use crate::models::heat_alert::{HeatMetric, Severity, Threshold, ThresholdBasis};
+use crate::services::weather::{CurrentConditions, HourlyTemperature, WeatherChain};
+use crate::utils::stats::percentile;
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Days either side of today's date included in the climatology
+const WINDOW_DAYS: i64 = 7;
+
+/// Archive requests per city in flight at once
+const YEAR_FETCH_CONCURRENCY: usize = 4;
+
+/// Days with data needed before the percentiles are trusted
+const MIN_CLIMATOLOGY_DAYS: usize = 30;
+
+/// What a current observation amounts to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Assessment {
+    pub metric: HeatMetric,
+    pub value: f64,
+    pub severity: Severity,
+    pub threshold: Option<Threshold>,
+    /// Lowest value that counts as an advisory: the lower of the 90th
+    /// percentile and the fixed advisory level
+    pub heat_threshold: f64,
+}
+
+/// 90th, 95th and 99th percentiles of a city's daily maxima, in °C.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Percentiles {
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+/// City id and the ISO week the climatology window is centred on
+type ClimatologyKey = (String, i32, u32);
+
+pub struct HeatSeverityEngine {
+    weather: Arc<WeatherChain>,
+    metric: HeatMetric,
+    climatology_years: u32,
+    /// `None` where the archive could not provide them, so a failing archive
+    /// is asked again next week rather than on every poll
+    climatology: RwLock<HashMap<ClimatologyKey, Option<Percentiles>>>,
+}
+
+impl HeatSeverityEngine {
+    pub fn new(config: &HeatAlertConfig, weather: Arc<WeatherChain>) -> Self {
+        Self {
+            weather,
+            metric: config.metric,
+            climatology_years: config.climatology_years,
+            climatology: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Severity of `conditions` in `city`.
+    pub async fn assess(&self, city: &City, conditions: &CurrentConditions) -> Assessment {
+        // Without humidity every metric degrades to air temperature
+        let metric = match conditions.humidity {
+            Some(_) => self.metric,
+            None => HeatMetric::AirTemperature,
+        };
+        let value = metric_value(metric, conditions.temperature, conditions.humidity);
+        let percentiles = match metric {
+            HeatMetric::AirTemperature => None,
+            _ => self.percentiles(city).await,
+        };
+        // The city's own levels replace the metric's physiological ones
+        let levels = match city.thresholds {
+            Some(t) => Levels {
+                basis: ThresholdBasis::City,
+                values: [t.advisory, t.warning, t.extreme],
+            },
+            None => Levels {
+                basis: ThresholdBasis::Absolute,
+                values: absolute_thresholds(metric),
+            },
+        };
+        let (severity, threshold) = classify(value, &levels, percentiles.as_ref());
+        let heat_threshold = percentiles.map_or(levels.values[0], |p| p.p90.min(levels.values[0]));
+        Assessment {
+            metric,
+            value,
+            severity,
+            threshold,
+            heat_threshold,
+        }
+    }
+
+    /// The city's climatological percentiles of the metric, fetched once per
+    /// week; `None` when the archive cannot provide them, which is remembered
+    /// for the week too.
+    async fn percentiles(&self, city: &City) -> Option<Percentiles> {
+        if self.climatology_years == 0 {
+            return None;
+        }
+        let today = Utc::now().date_naive();
+        let week = today.iso_week();
+        let key = (city.id.clone(), week.year(), week.week());
+        if let Some(percentiles) = self.climatology.read().await.get(&key) {
+            return *percentiles;
+        }
+
+        let years = (1..=self.climatology_years as i32).map(|back| today.year() - back);
+        let fetched: Result<Vec<Vec<HourlyTemperature>>, _> = stream::iter(years)
+            .map(|year| async move {
+                let centre = same_day_in(today, year);
+                self.weather
+                    .historical(city.lat, city.lon, centre - Duration::days(WINDOW_DAYS), centre + Duration::days(WINDOW_DAYS))
+                    .await
+                    .map(|series| series.values)
+            })
+            .buffered(YEAR_FETCH_CONCURRENCY)
+            .try_collect()
+            .await;
+        let percentiles = match fetched {
+            Ok(hours) => daily_max_percentiles(self.metric, &hours.concat()),
+            Err(e) => {
+                eprintln!("Heat alert: no climatology for {} this week: {}", city.name, e);
+                None
+            }
+        };
+        self.climatology.write().await.insert(key, percentiles);
+        percentiles
+    }
+}
+
+/// `today`'s month and day in `year`; 29 February becomes the 28th.
+fn same_day_in(today: NaiveDate, year: i32) -> NaiveDate {
+    today
+        .with_year(year)
+        .or_else(|| NaiveDate::from_ymd_opt(year, today.month(), 28))
+        .expect("28th exists in every month")
+}
+
+/// Percentiles of each day's highest metric value; hours without humidity are skipped.
+fn daily_max_percentiles(metric: HeatMetric, hours: &[HourlyTemperature]) -> Option<Percentiles> {
+    let mut daily: HashMap<NaiveDate, f64> = HashMap::new();
+    for hour in hours {
+        let Some(humidity) = hour.humidity else {
+            continue;
+        };
+        let value = metric_value(metric, hour.temperature, Some(humidity));
+        daily
+            .entry(hour.time.date_naive())
+            .and_modify(|max| *max = max.max(value))
+            .or_insert(value);
+    }
+    if daily.len() < MIN_CLIMATOLOGY_DAYS {
+        return None;
+    }
+    let mut maxima: Vec<f64> = daily.into_values().collect();
+    maxima.sort_by(f64::total_cmp);
+    Some(Percentiles {
+        p90: percentile(&maxima, 90.0)?,
+        p95: percentile(&maxima, 95.0)?,
+        p99: percentile(&maxima, 99.0)?,
+    })
+}
+
+/// Advisory, warning and extreme levels of the fixed scale a city is judged on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Levels {
+    basis: ThresholdBasis,
+    values: [f64; 3],
+}
+
+/// The higher of the climatological and the fixed level, with the threshold behind it.
+fn classify(value: f64, levels: &Levels, percentiles: Option<&Percentiles>) -> (Severity, Option<Threshold>) {
+    let [advisory, warning, extreme] = levels.values;
+    let fixed = [
+        (Severity::Extreme, extreme),
+        (Severity::Warning, warning),
+        (Severity::Advisory, advisory),
+    ]
+    .into_iter()
+    .find(|(_, threshold)| value >= *threshold)
+    .map(|(severity, threshold)| {
+        (
+            severity,
+            Threshold {
+                basis: levels.basis,
+                value: threshold,
+            },
+        )
+    });
+    let climatological = percentiles.and_then(|p| {
+        [
+            (Severity::Extreme, ThresholdBasis::P99, p.p99),
+            (Severity::Warning, ThresholdBasis::P95, p.p95),
+            (Severity::Advisory, ThresholdBasis::P90, p.p90),
+        ]
+        .into_iter()
+        .find(|(_, _, threshold)| value >= *threshold)
+        .map(|(severity, basis, threshold)| (severity, Threshold { basis, value: threshold }))
+    });
+
+    // Ties go to the climatology, the more specific of the two
+    match (climatological, fixed) {
+        (Some(c), Some(f)) if f.0 > c.0 => (f.0, Some(f.1)),
+        (Some(c), _) => (c.0, Some(c.1)),
+        (None, Some(f)) => (f.0, Some(f.1)),
+        (None, None) => (Severity::Normal, None),
+    }
+}
+
+/// Caution, danger and extreme-danger levels of a metric in °C.
+fn absolute_thresholds(metric: HeatMetric) -> [f64; 3] {
+    match metric {
+        // NWS "extreme caution", "danger", "extreme danger"
+        HeatMetric::HeatIndex => [32.0, 41.0, 54.0],
+        // Environment Canada "great discomfort", "dangerous", "heat stroke imminent"
+        HeatMetric::Humidex => [40.0, 45.0, 54.0],
+        // Sports medicine flag levels for outdoor activity
+        HeatMetric::Wbgt => [28.0, 30.0, 32.0],
+        // The fixed cut-offs alerts used before
+        HeatMetric::AirTemperature => [35.0, 40.0, 45.0],
+    }
+}
+
+pub fn metric_value(metric: HeatMetric, temperature: f64, humidity: Option<f64>) -> f64 {
+    match (metric, humidity) {
+        (HeatMetric::HeatIndex, Some(humidity)) => heat_index(temperature, humidity),
+        (HeatMetric::Humidex, Some(humidity)) => humidex(temperature, humidity),
+        (HeatMetric::Wbgt, Some(humidity)) => wbgt(temperature, humidity),
+        _ => temperature,
+    }
+}
+
+/// Water vapour pressure in hPa (Magnus formula)
+fn vapour_pressure(temperature: f64, humidity: f64) -> f64 {
+    humidity / 100.0 * 6.105 * (17.27 * temperature / (237.7 + temperature)).exp()
+}
+
+/// NWS heat index: the Rothfusz regression with its low-humidity and
+/// high-humidity adjustments, or Steadman's simple form below 80 °F.
+pub fn heat_index(temperature: f64, humidity: f64) -> f64 {
+    let t = temperature * 9.0 / 5.0 + 32.0;
+    let rh = humidity;
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+    let hi = if (simple + t) / 2.0 < 80.0 {
+        simple
+    } else {
+        let mut hi = -42.379 + 2.04901523 * t + 10.14333127 * rh
+            - 0.22475541 * t * rh
+            - 0.00683783 * t * t
+            - 0.05481717 * rh * rh
+            + 0.00122874 * t * t * rh
+            + 0.00085282 * t * rh * rh
+            - 0.00000199 * t * t * rh * rh;
+        if rh < 13.0 && (80.0..=112.0).contains(&t) {
+            hi -= (13.0 - rh) / 4.0 * ((17.0 - (t - 95.0).abs()) / 17.0).sqrt();
+        } else if rh > 85.0 && (80.0..=87.0).contains(&t) {
+            hi += (rh - 85.0) / 10.0 * (87.0 - t) / 5.0;
+        }
+        hi
+    };
+    (hi - 32.0) * 5.0 / 9.0
+}
+
+/// Environment Canada humidex
+pub fn humidex(temperature: f64, humidity: f64) -> f64 {
+    temperature + 0.5555 * (vapour_pressure(temperature, humidity) - 10.0)
+}
+
+/// Australian Bureau of Meteorology WBGT approximation for shade and light wind
+pub fn wbgt(temperature: f64, humidity: f64) -> f64 {
+    0.567 * temperature + 0.393 * vapour_pressure(temperature, humidity) + 3.94
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::weather::{HourlySeries, MockProvider, WeatherProvider};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Answers current conditions but has no archive
+    struct NoArchive {
+        historical_calls: AtomicU32,
+        inner: MockProvider,
+    }
+
+    #[async_trait]
+    impl WeatherProvider for NoArchive {
+        fn name(&self) -> &'static str {
+            "no-archive"
+        }
+
+        async fn current(&self, lat: f64, lng: f64) -> anyhow::Result<CurrentConditions> {
+            self.inner.current(lat, lng).await
+        }
+
+        async fn hourly_forecast(&self, lat: f64, lng: f64, hours: u32) -> anyhow::Result<HourlySeries> {
+            self.inner.hourly_forecast(lat, lng, hours).await
+        }
+
+        async fn historical(&self, _lat: f64, _lng: f64, _start: NaiveDate, _end: NaiveDate) -> anyhow::Result<HourlySeries> {
+            self.historical_calls.fetch_add(1, Ordering::SeqCst);
+            anyhow::bail!("no archive")
+        }
+    }
+
+    #[tokio::test]
+    async fn a_missing_climatology_is_not_fetched_again_the_same_week() {
+        let provider = Arc::new(NoArchive {
+            historical_calls: AtomicU32::new(0),
+            inner: MockProvider::new(40.0),
+        });
+        let config = HeatAlertConfig {
+            climatology_years: 3,
+            ..HeatAlertConfig::default()
+        };
+        let engine = HeatSeverityEngine::new(&config, Arc::new(WeatherChain::new(vec![provider.clone()])));
+        let city = crate::services::city_catalogue::CityCatalogue::builtin().all()[0].clone();
+        let mut conditions = provider.current(city.lat, city.lon).await.unwrap();
+        conditions.humidity = Some(50.0);
+
+        let first = engine.assess(&city, &conditions).await;
+        // Judged on the fixed scale alone
+        assert_eq!(first.threshold.map(|t| t.basis), Some(ThresholdBasis::Absolute));
+        let calls = provider.historical_calls.load(Ordering::SeqCst);
+        assert!((1..=config.climatology_years).contains(&calls));
+
+        assert_eq!(engine.assess(&city, &conditions).await, first);
+        assert_eq!(provider.historical_calls.load(Ordering::SeqCst), calls);
+    }
+
+    #[test]
+    fn humid_heat_outranks_dry_heat() {
+        // NWS table: 90 °F at 70% is a heat index of 106 °F (41.1 °C)
+        assert!((heat_index(32.22, 70.0) - 41.1).abs() < 0.3);
+        // 30 °C at 70% humidity gives humidex ~41
+        assert!((humidex(30.0, 70.0) - 41.0).abs() < 1.0);
+        assert!((wbgt(30.0, 70.0) - 32.6).abs() < 0.1);
+
+        // 38 °C in humid Lahore against 41 °C in dry Phoenix
+        let absolute = Levels {
+            basis: ThresholdBasis::Absolute,
+            values: absolute_thresholds(HeatMetric::HeatIndex),
+        };
+        let lahore = metric_value(HeatMetric::HeatIndex, 38.0, Some(60.0));
+        let phoenix = metric_value(HeatMetric::HeatIndex, 41.0, Some(10.0));
+        assert_eq!(classify(lahore, &absolute, None).0, Severity::Extreme);
+        assert_eq!(classify(phoenix, &absolute, None).0, Severity::Advisory);
+
+        // Phoenix's heat index of ~37.7 °C is still above its 95th percentile
+        let typical = Percentiles { p90: 35.0, p95: 37.0, p99: 40.0 };
+        let (severity, threshold) = classify(phoenix, &absolute, Some(&typical));
+        assert_eq!(severity, Severity::Warning);
+        assert_eq!(threshold.map(|t| t.basis), Some(ThresholdBasis::P95));
+        assert_eq!(classify(20.0, &absolute, Some(&typical)), (Severity::Normal, None));
+
+        // A city's own levels replace the absolute ones
+        let custom = Levels {
+            basis: ThresholdBasis::City,
+            values: [30.0, 34.0, 37.0],
+        };
+        let (severity, threshold) = classify(phoenix, &custom, None);
+        assert_eq!(severity, Severity::Extreme);
+        assert_eq!(threshold.map(|t| t.basis), Some(ThresholdBasis::City));
+    }
+}
@@ -0,0 +1,332 @@
+// src/services/heatwave.rs
+//
+// Heatwave events from the stream of heat-alert readings. A local day is hot
+// when any reading reaches the city's heat threshold (its advisory level), so
+// a day's readings are gathered and the day is judged on its peak once the
+// next day's first reading arrives. Consecutive hot days form a run, and a run
+// becomes an event once it lasts `heat_alert.heatwave_min_days` days. The
+// event closes on the first cooler day, on a day without readings, or when
+// readings for the city stop arriving. The latest day counts towards its run
+// as soon as one of its readings is hot. State is rebuilt at startup by
+// replaying the stored history.
+use crate::config::HeatAlertConfig;
+use crate::models::heat_alert::{HeatAlert, HeatMetric, HeatReading, HeatwaveEvent, Severity};
+use crate::services::heat_history::local_date;
+use chrono::{Duration, NaiveDate, Utc};
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+/// Peak of one city's readings on one local day.
+struct Day {
+    date: NaiveDate,
+    hot: bool,
+    peak_metric_value: f64,
+    peak_temperature: f64,
+    peak_severity: Severity,
+    /// Highest excess over the heat threshold, in °C
+    excess: f64,
+}
+
+impl Day {
+    fn new(reading: &HeatReading, date: NaiveDate) -> Self {
+        Day {
+            date,
+            hot: is_hot(reading),
+            peak_metric_value: reading.metric_value,
+            peak_temperature: reading.temperature,
+            peak_severity: reading.severity,
+            excess: excess(reading),
+        }
+    }
+
+    fn add(&mut self, reading: &HeatReading) {
+        self.hot |= is_hot(reading);
+        self.peak_metric_value = self.peak_metric_value.max(reading.metric_value);
+        self.peak_temperature = self.peak_temperature.max(reading.temperature);
+        self.peak_severity = self.peak_severity.max(reading.severity);
+        self.excess = self.excess.max(excess(reading));
+    }
+}
+
+/// Where one city stands.
+struct CityState {
+    city_id: String,
+    city: String,
+    metric: HeatMetric,
+    utc_offset_seconds: i32,
+    /// Judged hot days ending the day before `latest`
+    run: Option<HeatwaveEvent>,
+    /// The latest day with readings, judged once the next one starts
+    latest: Option<Day>,
+}
+
+impl CityState {
+    fn start(&self, day: &Day) -> HeatwaveEvent {
+        HeatwaveEvent {
+            id: format!("{}-{}", self.city_id, day.date),
+            city_id: self.city_id.clone(),
+            city: self.city.clone(),
+            start: day.date,
+            end: day.date,
+            days: 1,
+            active: true,
+            metric: self.metric,
+            peak_metric_value: day.peak_metric_value,
+            peak_temperature: day.peak_temperature,
+            peak_severity: day.peak_severity,
+            degree_days: day.excess,
+        }
+    }
+
+    /// The run with the latest day added when it is already hot
+    fn current(&self) -> Option<HeatwaveEvent> {
+        let mut run = self.run.clone();
+        if let Some(day) = self.latest.as_ref().filter(|day| day.hot) {
+            match &mut run {
+                Some(event) => extend(event, day),
+                None => run = Some(self.start(day)),
+            }
+        }
+        run
+    }
+
+    /// True while the city is still reporting: `end` is today or yesterday, local time
+    fn is_current(&self, end: NaiveDate) -> bool {
+        let today = (Utc::now() + Duration::seconds(self.utc_offset_seconds as i64)).date_naive();
+        end >= today - Duration::days(1)
+    }
+}
+
+fn extend(event: &mut HeatwaveEvent, day: &Day) {
+    event.end = day.date;
+    event.days += 1;
+    event.peak_metric_value = event.peak_metric_value.max(day.peak_metric_value);
+    event.peak_temperature = event.peak_temperature.max(day.peak_temperature);
+    event.peak_severity = event.peak_severity.max(day.peak_severity);
+    event.degree_days += day.excess;
+}
+
+#[derive(Default)]
+struct State {
+    /// Per city id
+    cities: HashMap<String, CityState>,
+    closed: Vec<HeatwaveEvent>,
+}
+
+pub struct HeatwaveDetector {
+    min_days: u32,
+    state: RwLock<State>,
+}
+
+impl HeatwaveDetector {
+    pub fn new(config: &HeatAlertConfig) -> Self {
+        Self {
+            min_days: config.heatwave_min_days,
+            state: RwLock::new(State::default()),
+        }
+    }
+
+    /// Feed readings in the order they were observed.
+    pub async fn update(&self, readings: &[HeatReading]) {
+        let mut state = self.state.write().await;
+        let State { cities, closed } = &mut *state;
+        for reading in readings {
+            let date = local_date(reading);
+            let city = cities.entry(reading.city_id.clone()).or_insert_with(|| CityState {
+                city_id: reading.city_id.clone(),
+                city: reading.city.clone(),
+                metric: reading.metric,
+                utc_offset_seconds: reading.utc_offset_seconds,
+                run: None,
+                latest: None,
+            });
+            city.utc_offset_seconds = reading.utc_offset_seconds;
+            match &mut city.latest {
+                // Older than the day being gathered; already accounted for
+                Some(day) if date < day.date => continue,
+                Some(day) if date == day.date => {
+                    day.add(reading);
+                    continue;
+                }
+                _ => {}
+            }
+
+            // A new day: judge the previous one on its peak
+            if let Some(day) = city.latest.take() {
+                if day.hot {
+                    match &mut city.run {
+                        Some(event) => extend(event, &day),
+                        None => city.run = Some(city.start(&day)),
+                    }
+                } else {
+                    self.close(city, closed);
+                }
+            }
+            // A day without readings also ends the run
+            if city.run.as_ref().is_some_and(|event| event.end.succ_opt() != Some(date)) {
+                self.close(city, closed);
+            }
+            city.latest = Some(Day::new(reading, date));
+        }
+    }
+
+    /// End the city's run, keeping it if it was long enough to be an event.
+    fn close(&self, city: &mut CityState, closed: &mut Vec<HeatwaveEvent>) {
+        if let Some(mut event) = city.run.take().filter(|event| event.days >= self.min_days) {
+            event.active = false;
+            closed.push(event);
+        }
+    }
+
+    /// The city's ongoing event, with `active` set from how recent it is
+    fn ongoing(&self, city: &CityState) -> Option<HeatwaveEvent> {
+        city.current()
+            .filter(|event| event.days >= self.min_days)
+            .map(|event| HeatwaveEvent {
+                active: city.is_current(event.end),
+                ..event
+            })
+    }
+
+    /// Events, newest first, optionally only active ones or one city's.
+    pub async fn events(&self, active: Option<bool>, city_id: Option<&str>) -> Vec<HeatwaveEvent> {
+        let state = self.state.read().await;
+        let mut events: Vec<HeatwaveEvent> = state
+            .cities
+            .values()
+            .filter_map(|city| self.ongoing(city))
+            .chain(state.closed.iter().cloned())
+            .filter(|event| active.is_none_or(|active| event.active == active))
+            .filter(|event| city_id.is_none_or(|id| event.city_id == id))
+            .collect();
+        events.sort_by(|a, b| b.start.cmp(&a.start).then_with(|| a.city_id.cmp(&b.city_id)));
+        events
+    }
+
+    /// Fill in the active event, if any, of each alert's city.
+    pub async fn annotate(&self, alerts: &mut [HeatAlert]) {
+        let state = self.state.read().await;
+        for alert in alerts {
+            let event = state
+                .cities
+                .get(&alert.city_id)
+                .and_then(|city| self.ongoing(city))
+                .filter(|event| event.active);
+            alert.heatwave_id = event.as_ref().map(|event| event.id.clone());
+            alert.heatwave_day = event.map(|event| event.days);
+        }
+    }
+}
+
+fn is_hot(reading: &HeatReading) -> bool {
+    match reading.heat_threshold {
+        Some(threshold) => reading.metric_value >= threshold,
+        None => reading.severity >= Severity::Advisory,
+    }
+}
+
+/// How far `reading` is above the heat threshold, in °C
+fn excess(reading: &HeatReading) -> f64 {
+    reading
+        .heat_threshold
+        .map_or(0.0, |threshold| (reading.metric_value - threshold).max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(days_ago: i64, hour: u32, temperature: f64) -> HeatReading {
+        let day = Utc::now().date_naive() - Duration::days(days_ago);
+        HeatReading {
+            city_id: "lahore-pk".to_string(),
+            city: "Lahore".to_string(),
+            observed_at: day.and_hms_opt(hour, 0, 0).unwrap().and_utc(),
+            utc_offset_seconds: 0,
+            temperature,
+            humidity: None,
+            metric: HeatMetric::AirTemperature,
+            metric_value: temperature,
+            severity: if temperature >= 35.0 { Severity::Advisory } else { Severity::Normal },
+            threshold: None,
+            heat_threshold: Some(35.0),
+            provider: "mock".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn opens_after_three_hot_days_and_closes_when_it_cools() {
+        let detector = HeatwaveDetector::new(&HeatAlertConfig::default());
+        // Two hot days end before becoming an event
+        detector.update(&[reading(12, 12, 38.0), reading(11, 12, 37.0), reading(10, 12, 30.0)]).await;
+        assert!(detector.events(None, None).await.is_empty());
+
+        detector
+            .update(&[
+                reading(9, 6, 33.0),
+                reading(9, 12, 38.0),
+                reading(9, 15, 40.0),
+                reading(8, 12, 36.0),
+                reading(7, 12, 39.0),
+                reading(6, 12, 31.0),
+                reading(2, 12, 36.0),
+                reading(1, 12, 37.0),
+            ])
+            .await;
+        let events = detector.events(None, None).await;
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!((event.days, event.active, event.peak_metric_value), (3, false, 40.0));
+        // Day peaks of 40, 36 and 39 over a threshold of 35
+        assert_eq!(event.degree_days, 5.0 + 1.0 + 4.0);
+
+        // The current run becomes an active event on its third day
+        detector.update(&[reading(0, 12, 38.0)]).await;
+        let active = detector.events(Some(true), Some("lahore-pk")).await;
+        assert_eq!(active.len(), 1);
+        assert_eq!((active[0].days, active[0].degree_days), (3, 1.0 + 2.0 + 3.0));
+
+        let mut alerts = vec![HeatAlert {
+            city_id: "lahore-pk".to_string(),
+            city: "Lahore".to_string(),
+            country: "PK".to_string(),
+            temperature: 38.0,
+            humidity: None,
+            severity: Severity::Advisory,
+            metric: HeatMetric::AirTemperature,
+            metric_value: 38.0,
+            threshold: None,
+            time_ago: String::new(),
+            heatwave_id: None,
+            heatwave_day: None,
+        }];
+        detector.annotate(&mut alerts).await;
+        assert_eq!(alerts[0].heatwave_id.as_deref(), Some(active[0].id.as_str()));
+        assert_eq!(alerts[0].heatwave_day, Some(3));
+    }
+
+    #[tokio::test]
+    async fn a_cool_morning_does_not_break_a_hot_day() {
+        let detector = HeatwaveDetector::new(&HeatAlertConfig::default());
+        // Four readings a day; every morning is below the threshold
+        let readings: Vec<HeatReading> = (0..4)
+            .rev()
+            .flat_map(|days_ago| {
+                [
+                    reading(days_ago, 0, 30.0),
+                    reading(days_ago, 6, 33.0),
+                    reading(days_ago, 12, 39.0),
+                    reading(days_ago, 18, 37.0),
+                ]
+            })
+            .filter(|reading| reading.observed_at <= Utc::now())
+            .collect();
+        detector.update(&readings).await;
+
+        let events = detector.events(Some(true), None).await;
+        assert_eq!(events.len(), 1);
+        assert!(events[0].days >= 3);
+        assert_eq!(events[0].start, Utc::now().date_naive() - Duration::days(3));
+        assert_eq!(events[0].peak_metric_value, 39.0);
+    }
+}
@@ -1,4 +1,23 @@
-pub mod data_fetcher;
+pub mod city_catalogue;
 pub mod earthengine;
+pub mod gee_auth;
+pub mod gee_composites;
+pub mod gee_expression;
+pub mod grid_renderer;
 pub mod heat_alert_services;
+pub mod heat_history;
+pub mod heat_severity;
+pub mod heatwave;
+pub mod open_meteo;
+pub mod openweathermap;
+pub mod point_sampler;
+pub mod tile_cache;
+pub mod tile_compare;
 pub mod tile_config_service;
+pub mod tile_freshness;
+pub mod tile_jobs;
+pub mod tile_seeder;
+pub mod timeseries;
+pub mod vis_registry;
+pub mod weather;
+pub mod zonal_stats;
@@ -0,0 +1,224 @@
+// src/services/open_meteo.rs
+//
+// Open-Meteo: current conditions and forecasts from the forecast API, past
+// hours from the ERA5-backed archive API. No API key is needed. Times are
+// requested as unix seconds so no timezone handling is required.
+use crate::services::weather::{CurrentConditions, HourlySeries, HourlyTemperature, WeatherProvider};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::Client;
+use serde::Deserialize;
+
+const FORECAST_URL: &str = "https://api.open-meteo.com/v1/forecast";
+const ARCHIVE_URL: &str = "https://archive-api.open-meteo.com/v1/archive";
+
+/// Variables requested for current conditions
+const CURRENT_VARIABLES: &str =
+    "temperature_2m,apparent_temperature,relative_humidity_2m,wind_speed_10m,wind_direction_10m,weather_code";
+
+/// Variables requested for forecasts and history
+const HOURLY_VARIABLES: &str = "temperature_2m,relative_humidity_2m";
+
+#[derive(Deserialize)]
+struct CurrentResponse {
+    timezone: String,
+    utc_offset_seconds: i32,
+    current: Current,
+}
+
+#[derive(Deserialize)]
+struct Current {
+    time: i64,
+    temperature_2m: f64, // in Celsius
+    apparent_temperature: Option<f64>,
+    relative_humidity_2m: Option<f64>,
+    wind_speed_10m: Option<f64>, // m/s with wind_speed_unit=ms
+    wind_direction_10m: Option<f64>,
+    weather_code: Option<u8>,
+}
+
+#[derive(Deserialize)]
+struct HourlyResponse {
+    hourly: Hourly,
+}
+
+#[derive(Deserialize)]
+struct Hourly {
+    time: Vec<i64>,
+    /// `null` where the archive has no value yet
+    temperature_2m: Vec<Option<f64>>,
+    #[serde(default)]
+    relative_humidity_2m: Vec<Option<f64>>,
+}
+
+pub struct OpenMeteoProvider {
+    client: Client,
+}
+
+impl OpenMeteoProvider {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, url: &str, query: &[(&str, String)]) -> anyhow::Result<T> {
+        Ok(self
+            .client
+            .get(url)
+            .query(query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<T>()
+            .await?)
+    }
+
+    fn conditions(&self, resp: CurrentResponse) -> anyhow::Result<CurrentConditions> {
+        let current = resp.current;
+
+        Ok(CurrentConditions {
+            temperature: current.temperature_2m,
+            apparent_temperature: current.apparent_temperature,
+            humidity: current.relative_humidity_2m,
+            wind_speed: current.wind_speed_10m,
+            wind_direction: current.wind_direction_10m,
+            weather_code: current.weather_code,
+            observed_at: DateTime::from_timestamp(current.time, 0).context("observation time out of range")?,
+            timezone: resp.timezone,
+            utc_offset_seconds: resp.utc_offset_seconds,
+            provider: self.name(),
+        })
+    }
+
+    fn series(&self, hourly: Hourly) -> HourlySeries {
+        HourlySeries {
+            provider: self.name(),
+            values: hourly
+                .time
+                .into_iter()
+                .zip(hourly.temperature_2m)
+                .enumerate()
+                .filter_map(|(i, (time, temperature))| {
+                    Some(HourlyTemperature {
+                        time: DateTime::from_timestamp(time, 0)?,
+                        temperature: temperature?,
+                        humidity: hourly.relative_humidity_2m.get(i).copied().flatten(),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+fn location(lat: f64, lng: f64) -> Vec<(&'static str, String)> {
+    vec![
+        ("latitude", lat.to_string()),
+        ("longitude", lng.to_string()),
+        ("timeformat", "unixtime".to_string()),
+    ]
+}
+
+#[async_trait]
+impl WeatherProvider for OpenMeteoProvider {
+    fn name(&self) -> &'static str {
+        "open_meteo"
+    }
+
+    async fn current(&self, lat: f64, lng: f64) -> anyhow::Result<CurrentConditions> {
+        let mut query = location(lat, lng);
+        query.push(("current", CURRENT_VARIABLES.to_string()));
+        query.push(("wind_speed_unit", "ms".to_string()));
+        // Resolves the location's timezone; times stay unix seconds
+        query.push(("timezone", "auto".to_string()));
+        let resp: CurrentResponse = self.get(FORECAST_URL, &query).await?;
+        self.conditions(resp)
+    }
+
+    async fn hourly_forecast(&self, lat: f64, lng: f64, hours: u32) -> anyhow::Result<HourlySeries> {
+        let mut query = location(lat, lng);
+        query.push(("hourly", HOURLY_VARIABLES.to_string()));
+        query.push(("forecast_hours", hours.to_string()));
+        let resp: HourlyResponse = self.get(FORECAST_URL, &query).await?;
+        Ok(self.series(resp.hourly))
+    }
+
+    async fn historical(&self, lat: f64, lng: f64, start: NaiveDate, end: NaiveDate) -> anyhow::Result<HourlySeries> {
+        let mut query = location(lat, lng);
+        query.push(("hourly", HOURLY_VARIABLES.to_string()));
+        query.push(("start_date", start.to_string()));
+        query.push(("end_date", end.to_string()));
+        let resp: HourlyResponse = self.get(ARCHIVE_URL, &query).await?;
+        let mut series = self.series(resp.hourly);
+        // The archive lags a few days behind; drop anything it reports past now
+        series.values.retain(|value| value.time <= Utc::now());
+        Ok(series)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn drops_missing_archive_hours_and_keeps_humidity_aligned() {
+        let resp: HourlyResponse = serde_json::from_str(
+            r#"{"hourly": {
+                "time": [1718236800, 1718240400, 1718244000, 1718247600],
+                "temperature_2m": [null, 18.5, null, 20.25],
+                "relative_humidity_2m": [90, 81, 77, null]
+            }}"#,
+        )
+        .unwrap();
+        let series = OpenMeteoProvider::new(Client::new()).series(resp.hourly);
+
+        assert_eq!(series.provider, "open_meteo");
+        assert_eq!(
+            series.values,
+            vec![
+                HourlyTemperature {
+                    time: Utc.with_ymd_and_hms(2024, 6, 13, 1, 0, 0).unwrap(),
+                    temperature: 18.5,
+                    humidity: Some(81.0),
+                },
+                HourlyTemperature {
+                    time: Utc.with_ymd_and_hms(2024, 6, 13, 3, 0, 0).unwrap(),
+                    temperature: 20.25,
+                    humidity: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn maps_current_conditions() {
+        let resp: CurrentResponse = serde_json::from_str(
+            r#"{
+                "timezone": "Asia/Tokyo",
+                "utc_offset_seconds": 32400,
+                "current": {
+                    "time": 1718236800,
+                    "temperature_2m": 24.1,
+                    "apparent_temperature": 25.3,
+                    "relative_humidity_2m": 68,
+                    "wind_speed_10m": 3.2,
+                    "wind_direction_10m": 190,
+                    "weather_code": 2
+                }
+            }"#,
+        )
+        .unwrap();
+        let current = OpenMeteoProvider::new(Client::new()).conditions(resp).unwrap();
+
+        assert_eq!(current.temperature, 24.1);
+        assert_eq!(current.apparent_temperature, Some(25.3));
+        assert_eq!(current.humidity, Some(68.0));
+        assert_eq!(current.wind_speed, Some(3.2));
+        assert_eq!(current.wind_direction, Some(190.0));
+        assert_eq!(current.weather_code, Some(2));
+        assert_eq!(current.observed_at, Utc.with_ymd_and_hms(2024, 6, 13, 0, 0, 0).unwrap());
+        assert_eq!(current.timezone, "Asia/Tokyo");
+        assert_eq!(current.utc_offset_seconds, 32400);
+        assert_eq!(current.provider, "open_meteo");
+    }
+}
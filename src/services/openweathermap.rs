@@ -0,0 +1,196 @@
+// src/services/openweathermap.rs
+//
+// OpenWeatherMap's free 2.5 API: current weather and the 5-day forecast in
+// 3-hour steps. Historical data needs a paid subscription, so `historical`
+// always fails and the chain moves on to the next provider.
+use crate::secrets::{self, Secret};
+use crate::services::weather::{CurrentConditions, HourlySeries, HourlyTemperature, WeatherProvider};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate};
+use reqwest::Client;
+use serde::Deserialize;
+
+const API_URL: &str = "https://api.openweathermap.org/data/2.5";
+
+/// Entries in the free forecast (5 days of 3-hour steps)
+const MAX_FORECAST_STEPS: u32 = 40;
+
+#[derive(Deserialize)]
+struct Observation {
+    dt: i64,
+    main: Main,
+}
+
+#[derive(Deserialize)]
+struct Main {
+    temp: f64, // in Celsius with units=metric
+    feels_like: Option<f64>,
+    humidity: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct CurrentResponse {
+    dt: i64,
+    /// Shift from UTC in seconds
+    timezone: i32,
+    main: Main,
+    wind: Option<Wind>,
+    #[serde(default)]
+    weather: Vec<Condition>,
+}
+
+#[derive(Deserialize)]
+struct Wind {
+    speed: Option<f64>, // m/s with units=metric
+    deg: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct Condition {
+    id: u16,
+}
+
+#[derive(Deserialize)]
+struct ForecastResponse {
+    list: Vec<Observation>,
+}
+
+pub struct OpenWeatherMapProvider {
+    client: Client,
+    api_key: Secret,
+}
+
+impl OpenWeatherMapProvider {
+    pub fn new(client: Client, api_key: Secret) -> Self {
+        Self { client, api_key }
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, endpoint: &str, query: &[(&str, String)]) -> anyhow::Result<T> {
+        let resp = self
+            .client
+            .get(format!("{}/{}", API_URL, endpoint))
+            .query(query)
+            .query(&[("units", "metric"), ("appid", self.api_key.expose())])
+            .send()
+            .await
+            // The URL carries the API key
+            .map_err(secrets::redact_reqwest)?
+            .error_for_status()
+            .map_err(secrets::redact_reqwest)?;
+        resp.json::<T>().await.map_err(secrets::redact_reqwest)
+    }
+}
+
+fn observation_time(dt: i64) -> anyhow::Result<DateTime<chrono::Utc>> {
+    DateTime::from_timestamp(dt, 0).context("observation time out of range")
+}
+
+fn current_conditions(resp: CurrentResponse, provider: &'static str) -> anyhow::Result<CurrentConditions> {
+    let offset = resp.timezone;
+    Ok(CurrentConditions {
+        temperature: resp.main.temp,
+        apparent_temperature: resp.main.feels_like,
+        humidity: resp.main.humidity,
+        wind_speed: resp.wind.as_ref().and_then(|wind| wind.speed),
+        wind_direction: resp.wind.as_ref().and_then(|wind| wind.deg),
+        weather_code: resp.weather.first().and_then(|condition| wmo_code(condition.id)),
+        observed_at: observation_time(resp.dt)?,
+        // Only the offset is reported, not the zone's name
+        timezone: format!(
+            "UTC{}{:02}:{:02}",
+            if offset < 0 { '-' } else { '+' },
+            offset.abs() / 3600,
+            offset.abs() % 3600 / 60
+        ),
+        utc_offset_seconds: offset,
+        provider,
+    })
+}
+
+/// Nearest WMO weather code for an OpenWeatherMap condition id
+/// (https://openweathermap.org/weather-conditions), so both providers report the same codes.
+fn wmo_code(id: u16) -> Option<u8> {
+    Some(match id {
+        200..=232 => 95,      // thunderstorm
+        300..=321 => 53,      // drizzle
+        500 => 61,            // light rain
+        501 => 63,            // moderate rain
+        502..=504 => 65,      // heavy rain
+        511 => 66,            // freezing rain
+        520..=531 => 80,      // rain showers
+        600 => 71,            // light snow
+        601 => 73,            // snow
+        602 => 75,            // heavy snow
+        611..=616 => 67,      // sleet
+        620..=622 => 85,      // snow showers
+        701..=781 => 45,      // mist, haze, fog
+        800 => 0,             // clear sky
+        801 => 1,             // few clouds
+        802 => 2,             // scattered clouds
+        803 | 804 => 3,       // overcast
+        _ => return None,
+    })
+}
+
+#[async_trait]
+impl WeatherProvider for OpenWeatherMapProvider {
+    fn name(&self) -> &'static str {
+        "openweathermap"
+    }
+
+    async fn current(&self, lat: f64, lng: f64) -> anyhow::Result<CurrentConditions> {
+        let resp: CurrentResponse = self
+            .get("weather", &[("lat", lat.to_string()), ("lon", lng.to_string())])
+            .await?;
+        current_conditions(resp, self.name())
+    }
+
+    async fn hourly_forecast(&self, lat: f64, lng: f64, hours: u32) -> anyhow::Result<HourlySeries> {
+        let steps = hours.div_ceil(3).clamp(1, MAX_FORECAST_STEPS);
+        let resp: ForecastResponse = self
+            .get(
+                "forecast",
+                &[("lat", lat.to_string()), ("lon", lng.to_string()), ("cnt", steps.to_string())],
+            )
+            .await?;
+        Ok(HourlySeries {
+            provider: self.name(),
+            values: resp
+                .list
+                .into_iter()
+                .map(|step| {
+                    Ok(HourlyTemperature {
+                        time: observation_time(step.dt)?,
+                        temperature: step.main.temp,
+                        humidity: step.main.humidity,
+                    })
+                })
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+
+    async fn historical(&self, _lat: f64, _lng: f64, _start: NaiveDate, _end: NaiveDate) -> anyhow::Result<HourlySeries> {
+        anyhow::bail!("historical data is not available on the free OpenWeatherMap API")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_current_weather_to_provider_neutral_conditions() {
+        let resp: CurrentResponse = serde_json::from_str(
+            r#"{ "dt": 1752580800, "timezone": 18000, "weather": [{ "id": 721, "main": "Haze" }],
+                 "main": { "temp": 41.2, "feels_like": 44.9, "humidity": 38 },
+                 "wind": { "speed": 3.6, "deg": 250 } }"#,
+        )
+        .unwrap();
+        let conditions = current_conditions(resp, "openweathermap").unwrap();
+        assert_eq!(conditions.observed_at.to_rfc3339(), "2025-07-15T12:00:00+00:00");
+        assert_eq!(conditions.timezone, "UTC+05:00");
+        assert_eq!(conditions.weather_code, Some(45));
+        assert_eq!((conditions.humidity, conditions.wind_direction), (Some(38.0), Some(250.0)));
+    }
+}
@@ -0,0 +1,245 @@
+// src/services/point_sampler.rs
+//
+// LST and anomaly values of one year at a coordinate. Years whose tile config
+// points at `local://` grids are read from those grids; every other year is
+// sampled on Earth Engine from the same composites as its maps. Results are
+// cached per coordinate rounded to 0.01° (about one MODIS pixel) and year.
+use crate::models::tile_config::TileLayer;
+use crate::services::earthengine::{EarthEngineService, PointValues};
+use crate::services::gee_composites::FIRST_YEAR;
+use crate::services::grid_renderer::GridRenderer;
+use crate::services::tile_config_service::TileConfigService;
+use chrono::{DateTime, Datelike, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// Coordinates are rounded to 1 / `COORDINATE_STEPS` degrees
+const COORDINATE_STEPS: f64 = 100.0;
+
+/// Cached samples before the oldest are dropped
+const MAX_CACHED_SAMPLES: usize = 10_000;
+
+/// The current year's composite changes as new months arrive
+const CURRENT_YEAR_TTL_HOURS: i64 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PointSource {
+    EarthEngine,
+    LocalGrid,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PointSample {
+    /// The rounded coordinate that was sampled
+    pub lat: f64,
+    pub lng: f64,
+    pub year: i32,
+    #[serde(flatten)]
+    pub values: PointValues,
+    pub units: &'static str,
+    pub source: PointSource,
+    pub sampled_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum PointError {
+    InvalidYear(i32),
+    Unavailable(String),
+}
+
+impl fmt::Display for PointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PointError::InvalidYear(year) => write!(
+                f,
+                "year {} is outside {}..={}",
+                year,
+                FIRST_YEAR,
+                Utc::now().year()
+            ),
+            PointError::Unavailable(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// A coordinate rounded to 1 / `COORDINATE_STEPS` degrees, for use as a cache key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RoundedCoordinate {
+    lat: i32,
+    lng: i32,
+}
+
+impl RoundedCoordinate {
+    pub fn new(lat: f64, lng: f64) -> Self {
+        Self {
+            lat: (lat * COORDINATE_STEPS).round() as i32,
+            lng: (lng * COORDINATE_STEPS).round() as i32,
+        }
+    }
+
+    pub fn lat(&self) -> f64 {
+        self.lat as f64 / COORDINATE_STEPS
+    }
+
+    pub fn lng(&self) -> f64 {
+        self.lng as f64 / COORDINATE_STEPS
+    }
+}
+
+type PointKey = (RoundedCoordinate, i32);
+
+pub struct PointSampler {
+    earth_engine: Arc<EarthEngineService>,
+    tile_config_service: Arc<TileConfigService>,
+    renderer: Arc<GridRenderer>,
+    cache: RwLock<HashMap<PointKey, PointSample>>,
+}
+
+impl PointSampler {
+    pub fn new(
+        earth_engine: Arc<EarthEngineService>,
+        tile_config_service: Arc<TileConfigService>,
+        renderer: Arc<GridRenderer>,
+    ) -> Self {
+        Self {
+            earth_engine,
+            tile_config_service,
+            renderer,
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// The sample and whether it came from the cache.
+    pub async fn sample(&self, lat: f64, lng: f64, year: i32) -> Result<(PointSample, bool), PointError> {
+        if !(FIRST_YEAR..=Utc::now().year()).contains(&year) {
+            return Err(PointError::InvalidYear(year));
+        }
+
+        let coordinate = RoundedCoordinate::new(lat, lng);
+        let key = (coordinate, year);
+        if let Some(sample) = self.cached(&key) {
+            return Ok((sample, true));
+        }
+
+        let config = self.tile_config_service.get_config_for_year(year).await;
+        let (values, source) = match config.filter(|config| config.is_local()) {
+            Some(config) => {
+                let mut sampled = [None; 3];
+                for (value, layer) in sampled.iter_mut().zip(TileLayer::ALL) {
+                    if let Some(grid) = config.local_grid(layer) {
+                        *value = self
+                            .renderer
+                            .sample(grid, coordinate.lng(), coordinate.lat())
+                            .await
+                            .map_err(|e| PointError::Unavailable(format!("{:#}", e)))?;
+                    }
+                }
+                (from_grids(sampled), PointSource::LocalGrid)
+            }
+            None => {
+                let values = self
+                    .earth_engine
+                    .sample_point(year, coordinate.lat(), coordinate.lng())
+                    .await
+                    .map_err(|e| PointError::Unavailable(format!("{:#}", e)))?;
+                (values, PointSource::EarthEngine)
+            }
+        };
+
+        let sample = PointSample {
+            lat: coordinate.lat(),
+            lng: coordinate.lng(),
+            year,
+            values,
+            units: "°C",
+            source,
+            sampled_at: Utc::now(),
+        };
+        self.insert(key, sample.clone());
+        Ok((sample, false))
+    }
+
+    fn cached(&self, key: &PointKey) -> Option<PointSample> {
+        let sample = self.cache.read().unwrap().get(key).cloned()?;
+        let stale = key.1 == Utc::now().year()
+            && Utc::now() - sample.sampled_at > Duration::hours(CURRENT_YEAR_TTL_HOURS);
+        (!stale).then_some(sample)
+    }
+
+    fn insert(&self, key: PointKey, sample: PointSample) {
+        let mut cache = self.cache.write().unwrap();
+        if cache.len() >= MAX_CACHED_SAMPLES && !cache.contains_key(&key) {
+            let oldest = cache
+                .iter()
+                .min_by_key(|(_, sample)| sample.sampled_at)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+        cache.insert(key, sample);
+    }
+}
+
+/// Values from the `[lst, anomaly, absolute_anomaly]` grids; the climatology
+/// mean is recovered as LST minus the absolute anomaly.
+fn from_grids([lst, z_score, anomaly]: [Option<f64>; 3]) -> PointValues {
+    PointValues {
+        lst,
+        climatology_mean: lst.zip(anomaly).map(|(lst, anomaly)| lst - anomaly),
+        anomaly,
+        z_score,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::grid_renderer::tests::{offline_earth_engine, write_grid, write_local_config};
+
+    #[tokio::test]
+    async fn samples_local_grids_and_caches_by_rounded_coordinate() {
+        let dir = std::env::temp_dir().join(format!("point-sampler-{}", uuid::Uuid::new_v4()));
+        let (configs, grids) = (dir.join("configs"), dir.join("grids"));
+
+        // 2x1 global grids: western and eastern hemisphere
+        let world = [-180.0, -90.0, 180.0, 90.0];
+        write_grid(&grids, "lst", &[30.0, 20.0], 2, 1, world);
+        write_grid(&grids, "z", &[1.5, -0.5], 2, 1, world);
+        write_grid(&grids, "abs", &[2.0, -1.0], 2, 1, world);
+        write_local_config(&configs, 2020, ["lst", "z", "abs"]);
+
+        let tile_config_service = Arc::new(TileConfigService::new(&configs));
+        tile_config_service.load_all().await;
+        let sampler = PointSampler::new(
+            offline_earth_engine(),
+            tile_config_service,
+            Arc::new(GridRenderer::with_limit(&grids, u64::MAX)),
+        );
+
+        let (sample, cached) = sampler.sample(31.5204, 74.3587, 2020).await.unwrap();
+        assert!(!cached);
+        assert_eq!((sample.lat, sample.lng, sample.source), (31.52, 74.36, PointSource::LocalGrid));
+        assert_eq!(
+            sample.values,
+            PointValues {
+                lst: Some(20.0),
+                climatology_mean: Some(21.0),
+                anomaly: Some(-1.0),
+                z_score: Some(-0.5),
+            }
+        );
+
+        let (_, cached) = sampler.sample(31.5196, 74.3613, 2020).await.unwrap();
+        assert!(cached);
+        assert!(matches!(
+            sampler.sample(31.5, 74.3, 1999).await,
+            Err(PointError::InvalidYear(1999))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
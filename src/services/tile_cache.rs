@@ -0,0 +1,614 @@
+// src/services/tile_cache.rs
+//
+// Caching proxy for XYZ tiles. A tile is fetched once through the year's URL
+// template (or rendered, for `local://` grids) and kept on disk as
+// `{layer}/{year}/{version}/{z}/{x}/{y}`; after that it is served from disk, so
+// the frontend never sees map IDs and cached areas keep working when Earth
+// Engine is unreachable or the map has expired. The version is a hash of what
+// decides the pixels (data source, periods, palette and range), not of the map
+// ID, so the routine refresh of expiring URLs keeps the cached tiles, while a
+// real change starts a fresh set (and ETags) and deletes the old one. The store
+// is bounded by total size and evicts the least recently used tiles first.
+use crate::config::TilesConfig;
+use crate::models::tile_config::{TileConfig, TileLayer};
+use crate::services::grid_renderer::GridRenderer;
+use crate::services::vis_registry::VisRegistry;
+use crate::services::tile_config_service::TileConfigService;
+use reqwest::Client;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+
+/// Deepest zoom level accepted; Earth Engine serves up to 24
+pub const MAX_ZOOM: u32 = 24;
+
+/// Web Mercator stops at ±85.0511°
+pub const MAX_LATITUDE: f64 = 85.051_128_78;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileKey {
+    pub layer: TileLayer,
+    pub year: i32,
+    pub z: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl TileKey {
+    pub fn new(layer: TileLayer, year: i32, z: u32, x: u32, y: u32) -> Result<Self, TileError> {
+        if z > MAX_ZOOM {
+            return Err(TileError::InvalidTile(format!(
+                "zoom {} is above the maximum of {}",
+                z, MAX_ZOOM
+            )));
+        }
+        let size = 1u32 << z;
+        if x >= size || y >= size {
+            return Err(TileError::InvalidTile(format!(
+                "tile {}/{}/{} is outside the {}x{} grid of zoom {}",
+                z, x, y, size, size, z
+            )));
+        }
+        Ok(Self { layer, year, z, x, y })
+    }
+
+    /// `lst/2024/{version}/3/4/2`, relative to the store root
+    fn relative_path(&self, version: &str) -> PathBuf {
+        [
+            self.layer.as_str().to_string(),
+            self.year.to_string(),
+            version.to_string(),
+            self.z.to_string(),
+            self.x.to_string(),
+            self.y.to_string(),
+        ]
+        .iter()
+        .collect()
+    }
+
+    fn url(&self, template: &str) -> String {
+        template
+            .replace("{z}", &self.z.to_string())
+            .replace("{x}", &self.x.to_string())
+            .replace("{y}", &self.y.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileSource {
+    Cache,
+    Upstream,
+}
+
+pub struct Tile {
+    pub bytes: Vec<u8>,
+    pub etag: String,
+    pub source: TileSource,
+}
+
+#[derive(Debug)]
+pub enum TileError {
+    InvalidTile(String),
+    /// No tile config for the year
+    NotConfigured(i32),
+    Upstream(String),
+    /// A `local://` grid could not be loaded or rendered
+    Render(String),
+}
+
+impl fmt::Display for TileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TileError::InvalidTile(message) => write!(f, "{}", message),
+            TileError::NotConfigured(year) => {
+                write!(f, "Tile config not available yet for year {}", year)
+            }
+            TileError::Upstream(message) => write!(f, "Tile server error: {}", message),
+            TileError::Render(message) => write!(f, "Tile rendering failed: {}", message),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TileCacheStats {
+    pub tiles: usize,
+    pub bytes: u64,
+    pub max_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Sizes and recency of the stored tiles. `order` maps an access tick to the
+/// tile last touched at that tick, so its first entry is the eviction candidate.
+#[derive(Default)]
+struct LruIndex {
+    entries: HashMap<PathBuf, (u64, u64)>, // path -> (size, tick)
+    order: BTreeMap<u64, PathBuf>,
+    next_tick: u64,
+    total_bytes: u64,
+}
+
+impl LruIndex {
+    fn touch(&mut self, path: &Path) -> bool {
+        let Some((_, tick)) = self.entries.get_mut(path) else {
+            return false;
+        };
+        self.order.remove(tick);
+        *tick = self.next_tick;
+        self.order.insert(self.next_tick, path.to_path_buf());
+        self.next_tick += 1;
+        true
+    }
+
+    fn insert(&mut self, path: PathBuf, size: u64) {
+        self.remove(&path);
+        self.entries.insert(path.clone(), (size, self.next_tick));
+        self.order.insert(self.next_tick, path);
+        self.next_tick += 1;
+        self.total_bytes += size;
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some((size, tick)) = self.entries.remove(path) {
+            self.order.remove(&tick);
+            self.total_bytes -= size;
+        }
+    }
+
+    fn remove_under(&mut self, prefix: &Path) {
+        let paths: Vec<PathBuf> = self
+            .entries
+            .keys()
+            .filter(|path| path.starts_with(prefix))
+            .cloned()
+            .collect();
+        for path in paths {
+            self.remove(&path);
+        }
+    }
+
+    fn pop_lru(&mut self) -> Option<PathBuf> {
+        let (_, path) = self.order.pop_first()?;
+        if let Some((size, _)) = self.entries.remove(&path) {
+            self.total_bytes -= size;
+        }
+        Some(path)
+    }
+}
+
+pub struct TileCache {
+    root: PathBuf,
+    max_bytes: u64,
+    client: Client,
+    tile_config_service: Arc<TileConfigService>,
+    renderer: Arc<GridRenderer>,
+    vis: Arc<VisRegistry>,
+    index: Mutex<LruIndex>,
+    /// Version last served per layer and year; older versions are deleted
+    versions: Mutex<HashMap<(TileLayer, i32), String>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl TileCache {
+    pub fn new(
+        config: &TilesConfig,
+        tile_config_service: Arc<TileConfigService>,
+        renderer: Arc<GridRenderer>,
+        vis: Arc<VisRegistry>,
+    ) -> Self {
+        Self::with_limit(
+            &config.cache_dir,
+            config.cache_max_mb * 1024 * 1024,
+            tile_config_service,
+            renderer,
+            vis,
+        )
+    }
+
+    fn with_limit(
+        root: &Path,
+        max_bytes: u64,
+        tile_config_service: Arc<TileConfigService>,
+        renderer: Arc<GridRenderer>,
+        vis: Arc<VisRegistry>,
+    ) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            max_bytes,
+            client: Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .expect("reqwest client builds"),
+            tile_config_service,
+            renderer,
+            vis,
+            index: Mutex::new(LruIndex::default()),
+            versions: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Index the tiles already on disk, oldest first, so they count towards
+    /// the size bound and are evicted in a sensible order.
+    pub async fn load_index(&self) {
+        let mut found = Vec::new();
+        let mut dirs = vec![self.root.clone()];
+        while let Some(dir) = dirs.pop() {
+            let Ok(mut entries) = fs::read_dir(&dir).await else {
+                continue;
+            };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let Ok(metadata) = entry.metadata().await else {
+                    continue;
+                };
+                if metadata.is_dir() {
+                    dirs.push(entry.path());
+                } else if entry.path().extension().is_some() {
+                    // Tiles have bare names; a `.tmp` is left over from an interrupted write
+                    if entry.path().extension() == Some("tmp".as_ref()) {
+                        let _ = fs::remove_file(entry.path()).await;
+                    }
+                } else if let Ok(relative) = entry.path().strip_prefix(&self.root) {
+                    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    found.push((modified, relative.to_path_buf(), metadata.len()));
+                }
+            }
+        }
+        found.sort_by_key(|(modified, _, _)| *modified);
+
+        let count = found.len();
+        {
+            let mut index = self.index.lock().unwrap();
+            for (_, path, size) in found {
+                index.insert(path, size);
+            }
+        }
+        self.evict().await;
+        println!("Indexed {} cached tiles in {}", count, self.root.display());
+    }
+
+    /// The tile from disk if cached for the year's current config, otherwise
+    /// from the year's tile server.
+    pub async fn get_tile(&self, key: &TileKey) -> Result<Tile, TileError> {
+        let config = self
+            .tile_config_service
+            .get_config_for_year(key.year)
+            .await
+            .ok_or(TileError::NotConfigured(key.year))?;
+        let version = self.version(&config, key.layer);
+        self.retire_old_versions(key, &version).await;
+        let relative = key.relative_path(&version);
+        if let Some(bytes) = self.read_cached(&relative).await {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Tile {
+                etag: etag(&version, &bytes),
+                bytes,
+                source: TileSource::Cache,
+            });
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let bytes = match config.local_grid(key.layer) {
+            Some(grid) => {
+                self.renderer
+                    .render_tile(grid, &self.vis.tile_layer(key.layer).vis_params(), key.z, key.x, key.y)
+                    .await
+                    .map_err(|e| TileError::Render(format!("{:#}", e)))?
+            }
+            None => self.fetch_upstream(&key.url(config.tile_url(key.layer))).await?,
+        };
+
+        if let Err(e) = self.store(relative, &bytes).await {
+            eprintln!("Failed to cache tile {:?}: {}", key, e);
+        }
+
+        Ok(Tile {
+            etag: etag(&version, &bytes),
+            bytes,
+            source: TileSource::Upstream,
+        })
+    }
+
+    async fn fetch_upstream(&self, url: &str) -> Result<Vec<u8>, TileError> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| TileError::Upstream(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(TileError::Upstream(format!("HTTP {}", response.status())));
+        }
+        Ok(response
+            .bytes()
+            .await
+            .map_err(|e| TileError::Upstream(e.to_string()))?
+            .to_vec())
+    }
+
+    /// True if `year` has a tile config to fetch or render tiles from
+    pub async fn is_configured(&self, year: i32) -> bool {
+        self.tile_config_service.get_config_for_year(year).await.is_some()
+    }
+
+    /// True if the tile is cached for the year's current config
+    pub async fn is_cached(&self, key: &TileKey) -> bool {
+        let Some(config) = self.tile_config_service.get_config_for_year(key.year).await else {
+            return false;
+        };
+        let relative = key.relative_path(&self.version(&config, key.layer));
+        self.index.lock().unwrap().entries.contains_key(&relative)
+    }
+
+    pub fn stats(&self) -> TileCacheStats {
+        let index = self.index.lock().unwrap();
+        TileCacheStats {
+            tiles: index.entries.len(),
+            bytes: index.total_bytes,
+            max_bytes: self.max_bytes,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn read_cached(&self, relative: &Path) -> Option<Vec<u8>> {
+        if !self.index.lock().unwrap().touch(relative) {
+            return None;
+        }
+        match fs::read(self.root.join(relative)).await {
+            Ok(bytes) => Some(bytes),
+            Err(_) => {
+                // Removed behind our back; fetch it again
+                self.index.lock().unwrap().remove(relative);
+                None
+            }
+        }
+    }
+
+    async fn store(&self, relative: PathBuf, bytes: &[u8]) -> std::io::Result<()> {
+        let path = self.root.join(&relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, bytes).await?;
+        fs::rename(&tmp_path, &path).await?;
+
+        self.index
+            .lock()
+            .unwrap()
+            .insert(relative, bytes.len() as u64);
+        self.evict().await;
+        Ok(())
+    }
+
+    /// Identifies what a layer's tiles show: the source data and periods, the
+    /// local grid if any, and the palette and range they are coloured with
+    fn version(&self, config: &TileConfig, layer: TileLayer) -> String {
+        let vis = self.vis.tile_layer(layer);
+        let source = format!(
+            "{}|{}|{}|{}|{}|{}|{}",
+            config.data_source.as_deref().unwrap_or(""),
+            config.target_period.as_deref().unwrap_or(""),
+            config.climatology_period.as_deref().unwrap_or(""),
+            config.local_grid(layer).unwrap_or(""),
+            vis.min,
+            vis.max,
+            vis.palette.join(","),
+        );
+        format!("{:016x}", fnv1a(source.as_bytes()))
+    }
+
+    /// Delete the year's tiles of any other version the first time `version`
+    /// is served, including versions left on disk before a restart.
+    async fn retire_old_versions(&self, key: &TileKey, version: &str) {
+        {
+            let mut versions = self.versions.lock().unwrap();
+            if versions.get(&(key.layer, key.year)).map(String::as_str) == Some(version) {
+                return;
+            }
+            versions.insert((key.layer, key.year), version.to_string());
+        }
+
+        let year_dir: PathBuf = [key.layer.as_str().to_string(), key.year.to_string()].iter().collect();
+        let Ok(mut entries) = fs::read_dir(self.root.join(&year_dir)).await else {
+            return;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if entry.file_name() == version {
+                continue;
+            }
+            let relative = year_dir.join(entry.file_name());
+            self.index.lock().unwrap().remove_under(&relative);
+            if let Err(e) = fs::remove_dir_all(entry.path()).await {
+                eprintln!("Failed to remove stale tiles {}: {}", entry.path().display(), e);
+            }
+        }
+    }
+
+    async fn evict(&self) {
+        loop {
+            let victim = {
+                let mut index = self.index.lock().unwrap();
+                if index.total_bytes <= self.max_bytes {
+                    return;
+                }
+                index.pop_lru()
+            };
+            let Some(victim) = victim else {
+                return;
+            };
+            let _ = fs::remove_file(self.root.join(victim)).await;
+        }
+    }
+}
+
+/// Strong ETag from the config version and the tile bytes
+fn etag(version: &str, bytes: &[u8]) -> String {
+    format!("\"{}-{:016x}\"", version, fnv1a(bytes))
+}
+
+/// 64-bit FNV-1a hash
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Path as AxumPath, routing::get, Router};
+    use std::sync::atomic::AtomicUsize;
+    use tokio::net::TcpListener;
+
+    /// Stand-in tile server answering `/{layer}/{z}/{x}/{y}` with the path as the body.
+    async fn spawn_stub() -> (String, Arc<AtomicUsize>, tokio::task::JoinHandle<()>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let app = Router::new().route(
+            "/{layer}/{z}/{x}/{y}",
+            get(move |AxumPath(path): AxumPath<(String, u32, u32, u32)>| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async move { format!("{}/{}/{}/{}", path.0, path.1, path.2, path.3) }
+            }),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (base, requests, server)
+    }
+
+    /// Where `key` is stored for the year's current config
+    async fn stored_path(cache: &TileCache, root: &Path, key: &TileKey) -> PathBuf {
+        let config = cache.tile_config_service.get_config_for_year(key.year).await.unwrap();
+        root.join(key.relative_path(&cache.version(&config, key.layer)))
+    }
+
+    async fn cache_for(base: &str, max_bytes: u64) -> (TileCache, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("tile-cache-{}", uuid::Uuid::new_v4()));
+        let config_dir = dir.join("configs");
+        fs::create_dir_all(&config_dir).await.unwrap();
+        let config = serde_json::json!({
+            "lst_tile_url": format!("{}/lst/{{z}}/{{x}}/{{y}}", base),
+            "anomaly_tile_url": format!("{}/anomaly/{{z}}/{{x}}/{{y}}", base),
+            "absolute_anomaly_tile_url": format!("{}/absolute/{{z}}/{{x}}/{{y}}", base),
+        });
+        fs::write(config_dir.join("tile_config_2024.json"), config.to_string())
+            .await
+            .unwrap();
+
+        let tile_config_service = Arc::new(TileConfigService::new(&config_dir));
+        let root = dir.join("store");
+        let cache = TileCache::with_limit(
+            &root,
+            max_bytes,
+            tile_config_service,
+            Arc::new(GridRenderer::with_limit(&dir.join("grids"), u64::MAX)),
+            Arc::new(VisRegistry::builtin()),
+        );
+        (cache, root)
+    }
+
+    #[tokio::test]
+    async fn caches_tiles_and_serves_them_offline() {
+        let (base, requests, server) = spawn_stub().await;
+        let (cache, root) = cache_for(&base, 1024).await;
+        let key = TileKey::new(TileLayer::Lst, 2024, 2, 1, 3).unwrap();
+
+        let first = cache.get_tile(&key).await.unwrap();
+        assert_eq!(first.source, TileSource::Upstream);
+        assert_eq!(first.bytes, b"lst/2/1/3");
+        assert_eq!(fs::read(stored_path(&cache, &root, &key).await).await.unwrap(), b"lst/2/1/3");
+
+        server.abort();
+        let _ = server.await;
+
+        let second = cache.get_tile(&key).await.unwrap();
+        assert_eq!(second.source, TileSource::Cache);
+        assert_eq!(second.etag, first.etag);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let uncached = TileKey::new(TileLayer::Lst, 2024, 2, 0, 0).unwrap();
+        assert!(matches!(
+            cache.get_tile(&uncached).await,
+            Err(TileError::Upstream(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn evicts_least_recently_used_tiles() {
+        let (base, _, _server) = spawn_stub().await;
+        // Bodies are 9 bytes ("lst/1/0/0"), so three tiles don't fit in 20
+        let (cache, root) = cache_for(&base, 20).await;
+        let tile = |x, y| TileKey::new(TileLayer::Lst, 2024, 1, x, y).unwrap();
+
+        cache.get_tile(&tile(0, 0)).await.unwrap();
+        cache.get_tile(&tile(0, 1)).await.unwrap();
+        // Touch 0/0 so 0/1 becomes the oldest
+        cache.get_tile(&tile(0, 0)).await.unwrap();
+        cache.get_tile(&tile(1, 0)).await.unwrap();
+
+        assert!(stored_path(&cache, &root, &tile(0, 0)).await.exists());
+        assert!(!stored_path(&cache, &root, &tile(0, 1)).await.exists());
+        assert!(stored_path(&cache, &root, &tile(1, 0)).await.exists());
+        assert_eq!(cache.stats().tiles, 2);
+        assert_eq!(cache.stats().bytes, 18);
+    }
+
+    #[tokio::test]
+    async fn refreshed_map_urls_keep_the_cached_tiles() {
+        let (base, requests, _server) = spawn_stub().await;
+        let (cache, _root) = cache_for(&base, 1024).await;
+        let key = TileKey::new(TileLayer::Lst, 2024, 2, 1, 3).unwrap();
+        let first = cache.get_tile(&key).await.unwrap();
+
+        // A refresh mints a new map ID for the same content
+        let mut config = cache.tile_config_service.get_config_for_year(2024).await.unwrap();
+        config.lst_tile_url = format!("{}/lst/{{z}}/{{x}}/{{y}}?map=refreshed", base);
+        config.generated_at = Some("2025-07-31T12:00:00+00:00".to_string());
+        cache.tile_config_service.save_config_for_year(2024, config).await.unwrap();
+
+        let second = cache.get_tile(&key).await.unwrap();
+        assert_eq!(second.source, TileSource::Cache);
+        assert_eq!(second.etag, first.etag);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn changed_content_replaces_the_cached_tiles() {
+        let (base, requests, _server) = spawn_stub().await;
+        let (cache, root) = cache_for(&base, 1024).await;
+        let key = TileKey::new(TileLayer::Lst, 2024, 2, 1, 3).unwrap();
+        let first = cache.get_tile(&key).await.unwrap();
+        let old_path = stored_path(&cache, &root, &key).await;
+
+        let mut config = cache.tile_config_service.get_config_for_year(2024).await.unwrap();
+        config.target_period = Some("2024-01..2024-06".to_string());
+        cache.tile_config_service.save_config_for_year(2024, config).await.unwrap();
+
+        let second = cache.get_tile(&key).await.unwrap();
+        assert_eq!(second.source, TileSource::Upstream);
+        assert_ne!(second.etag, first.etag);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(cache.is_cached(&key).await);
+        // The old version's directory is gone from disk and from the index
+        assert!(!old_path.parent().unwrap().parent().unwrap().parent().unwrap().exists());
+        assert_eq!(cache.stats().tiles, 1);
+    }
+
+    #[test]
+    fn rejects_tiles_outside_the_grid() {
+        assert!(TileKey::new(TileLayer::Lst, 2024, 1, 2, 0).is_err());
+        assert!(TileKey::new(TileLayer::Lst, 2024, 25, 0, 0).is_err());
+        assert!(TileKey::new(TileLayer::Anomaly, 2024, 0, 0, 0).is_ok());
+    }
+}
@@ -0,0 +1,289 @@
+// src/services/tile_compare.rs
+//
+// Difference layers between two years ("how much hotter is 2025 than 2002").
+// When both years are served from `local://` grids the difference is rendered
+// here, tile by tile; otherwise Earth Engine builds it from the yearly
+// composites. Layers and their bbox statistics are cached per year pair in
+// `TileConfigService`; Earth Engine maps are rebuilt before they expire.
+use crate::config::TilesConfig;
+use crate::models::tile_config::{ChangeStats, ComparisonSource, TileComparison, TileLayer};
+use crate::services::earthengine::EarthEngineService;
+use crate::services::gee_composites::FIRST_YEAR;
+use crate::services::gee_expression::{Geometry, Image, Reducer};
+use crate::services::grid_renderer::GridRenderer;
+use crate::services::tile_cache::{TileError, TileKey};
+use crate::services::tile_config_service::TileConfigService;
+use crate::services::vis_registry::VisRegistry;
+use chrono::{Datelike, Duration, Utc};
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+
+/// Scale of the Earth Engine bbox statistics in metres; large boxes are coarsened further
+const STATS_SCALE_METERS: f64 = 5000.0;
+
+#[derive(Debug)]
+pub enum CompareError {
+    InvalidRequest(String),
+    Unavailable(String),
+}
+
+impl fmt::Display for CompareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompareError::InvalidRequest(e) | CompareError::Unavailable(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Grid names of `layer` for both years, if both are local
+type LocalPair = Option<(String, String)>;
+
+pub struct TileCompareService {
+    earth_engine: Arc<EarthEngineService>,
+    tile_config_service: Arc<TileConfigService>,
+    renderer: Arc<GridRenderer>,
+    vis: Arc<VisRegistry>,
+    /// Age at which an Earth Engine difference map is rebuilt
+    map_lifetime: Duration,
+}
+
+impl TileCompareService {
+    pub fn new(
+        config: &TilesConfig,
+        earth_engine: Arc<EarthEngineService>,
+        tile_config_service: Arc<TileConfigService>,
+        renderer: Arc<GridRenderer>,
+        vis: Arc<VisRegistry>,
+    ) -> Self {
+        Self {
+            earth_engine,
+            tile_config_service,
+            renderer,
+            vis,
+            map_lifetime: Duration::seconds((config.map_ttl_secs - config.refresh_margin_secs) as i64),
+        }
+    }
+
+    /// The difference layer of `to` minus `from` with statistics over `bbox`,
+    /// and whether both came from the cache.
+    pub async fn compare(
+        &self,
+        layer: TileLayer,
+        from: i32,
+        to: i32,
+        bbox: [f64; 4],
+    ) -> Result<(TileComparison, ChangeStats, bool), CompareError> {
+        validate(from, to, bbox)?;
+        let local = self.local_pair(layer, from, to).await;
+        // Built at most once, for the map and the statistics
+        let mut difference: Option<Image> = None;
+
+        let cached_comparison = self
+            .tile_config_service
+            .get_comparison(layer, from, to)
+            .await
+            .filter(|comparison| {
+                comparison.source == ComparisonSource::LocalGrid
+                    || Utc::now() - comparison.generated_at < self.map_lifetime
+            });
+        let comparison_hit = cached_comparison.is_some();
+        let comparison = match cached_comparison {
+            Some(comparison) => comparison,
+            None => {
+                let (tile_url, source) = match &local {
+                    Some(_) => (
+                        format!("/tiles/compare/{}/{}/{}/{{z}}/{{x}}/{{y}}.png", layer.as_str(), from, to),
+                        ComparisonSource::LocalGrid,
+                    ),
+                    None => {
+                        let image = self.difference(&mut difference, layer, from, to).await?;
+                        let visualized = image.visualize(&self.vis.difference_layer(layer).vis_params());
+                        let url = self
+                            .earth_engine
+                            .create_map(visualized)
+                            .await
+                            .map_err(unavailable)?;
+                        (url, ComparisonSource::EarthEngine)
+                    }
+                };
+                let comparison = TileComparison {
+                    layer,
+                    from,
+                    to,
+                    tile_url,
+                    source,
+                    generated_at: Utc::now(),
+                };
+                self.tile_config_service.save_comparison(comparison.clone()).await;
+                comparison
+            }
+        };
+
+        let cached_stats = self
+            .tile_config_service
+            .get_comparison_stats(layer, from, to, bbox)
+            .await;
+        let stats_hit = cached_stats.is_some();
+        let stats = match cached_stats {
+            Some(stats) => stats,
+            None => {
+                let stats = match &local {
+                    Some((from_grid, to_grid)) => self
+                        .renderer
+                        .difference_stats(from_grid, to_grid, bbox)
+                        .await
+                        .map_err(unavailable)?,
+                    None => {
+                        let image = self.difference(&mut difference, layer, from, to).await?;
+                        let reducer = Reducer::mean().combine(Reducer::min_max());
+                        let values = self
+                            .earth_engine
+                            .compute_value(image.reduce_region(reducer, Geometry::rectangle(bbox), STATS_SCALE_METERS))
+                            .await
+                            .map_err(unavailable)?;
+                        let value = |name: &str| values.get(format!("difference_{}", name)).and_then(Value::as_f64);
+                        ChangeStats {
+                            bbox,
+                            mean: value("mean"),
+                            min: value("min"),
+                            max: value("max"),
+                        }
+                    }
+                };
+                self.tile_config_service
+                    .save_comparison_stats(layer, from, to, stats)
+                    .await;
+                stats
+            }
+        };
+
+        Ok((comparison, stats, comparison_hit && stats_hit))
+    }
+
+    /// PNG of a local difference tile, for the `/tiles/compare/...` URLs.
+    pub async fn render_local_tile(&self, layer: TileLayer, from: i32, to: i32, z: u32, x: u32, y: u32) -> Result<Vec<u8>, TileError> {
+        let key = TileKey::new(layer, to, z, x, y)?;
+        let Some((from_grid, to_grid)) = self.local_pair(layer, from, to).await else {
+            return Err(TileError::InvalidTile(format!(
+                "{} and {} are not both served from local grids",
+                from, to
+            )));
+        };
+        self.renderer
+            .render_difference(
+                &from_grid,
+                &to_grid,
+                &self.vis.difference_layer(layer).vis_params(),
+                (key.z, key.x, key.y),
+            )
+            .await
+            .map_err(|e| TileError::Render(format!("{:#}", e)))
+    }
+
+    async fn local_pair(&self, layer: TileLayer, from: i32, to: i32) -> LocalPair {
+        let grid = |year| async move {
+            let config = self.tile_config_service.get_config_for_year(year).await?;
+            config.local_grid(layer).map(str::to_string)
+        };
+        Some((grid(from).await?, grid(to).await?))
+    }
+
+    async fn difference(
+        &self,
+        difference: &mut Option<Image>,
+        layer: TileLayer,
+        from: i32,
+        to: i32,
+    ) -> Result<Image, CompareError> {
+        if difference.is_none() {
+            *difference = Some(
+                self.earth_engine
+                    .year_difference(layer, from, to)
+                    .await
+                    .map_err(unavailable)?,
+            );
+        }
+        Ok(difference.clone().expect("set above"))
+    }
+}
+
+fn validate(from: i32, to: i32, bbox: [f64; 4]) -> Result<(), CompareError> {
+    let current_year = Utc::now().year();
+    if from == to {
+        return Err(CompareError::InvalidRequest("from and to must differ".to_string()));
+    }
+    for year in [from, to] {
+        if !(FIRST_YEAR..=current_year).contains(&year) {
+            return Err(CompareError::InvalidRequest(format!(
+                "year {} is outside {}..={}",
+                year, FIRST_YEAR, current_year
+            )));
+        }
+    }
+    let [west, south, east, north] = bbox;
+    if !(-180.0..=180.0).contains(&west)
+        || !(-180.0..=180.0).contains(&east)
+        || !(-90.0..=90.0).contains(&south)
+        || !(-90.0..=90.0).contains(&north)
+        || west >= east
+        || south >= north
+    {
+        return Err(CompareError::InvalidRequest(
+            "bbox must be [west, south, east, north] in degrees".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn unavailable(e: anyhow::Error) -> CompareError {
+    CompareError::Unavailable(format!("{:#}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::grid_renderer::tests::{offline_earth_engine, write_grid, write_local_config};
+
+    #[tokio::test]
+    async fn compares_local_grids_and_caches_by_year_pair() {
+        let dir = std::env::temp_dir().join(format!("tile-compare-{}", uuid::Uuid::new_v4()));
+        let (configs, grids) = (dir.join("configs"), dir.join("grids"));
+
+        // 2x1 global grids, west and east; the east warmed by 3 °C, the west cooled by 1 °C
+        for (year, values) in [(2002, [20.0f32, 25.0]), (2020, [19.0, 28.0])] {
+            let name = format!("lst_{}", year);
+            write_grid(&grids, &name, &values, 2, 1, [-180.0, -90.0, 180.0, 90.0]);
+            write_local_config(&configs, year, [&name, &name, &name]);
+        }
+
+        let vis = Arc::new(VisRegistry::builtin());
+        let service = TileCompareService::new(
+            &TilesConfig::default(),
+            offline_earth_engine(),
+            Arc::new(TileConfigService::new(&configs)),
+            Arc::new(GridRenderer::with_limit(&grids, u64::MAX)),
+            vis,
+        );
+
+        let world = [-180.0, -90.0, 180.0, 90.0];
+        let (comparison, stats, cached) = service.compare(TileLayer::Lst, 2002, 2020, world).await.unwrap();
+        assert!(!cached);
+        assert_eq!(comparison.source, ComparisonSource::LocalGrid);
+        assert_eq!(comparison.tile_url, "/tiles/compare/lst/2002/2020/{z}/{x}/{y}.png");
+        assert_eq!((stats.mean, stats.min, stats.max), (Some(1.0), Some(-1.0), Some(3.0)));
+
+        let east = [0.0, -90.0, 180.0, 90.0];
+        let (_, stats, cached) = service.compare(TileLayer::Lst, 2002, 2020, east).await.unwrap();
+        assert!(!cached);
+        assert_eq!(stats.mean, Some(3.0));
+        let (_, _, cached) = service.compare(TileLayer::Lst, 2002, 2020, world).await.unwrap();
+        assert!(cached);
+
+        let png = service.render_local_tile(TileLayer::Lst, 2002, 2020, 0, 0, 0).await.unwrap();
+        assert!(image::load_from_memory(&png).is_ok());
+        assert!(service.compare(TileLayer::Lst, 2020, 2020, world).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,40 +1,89 @@
 // services/tile_config_service.rs
+use crate::models::tile_config::{ChangeStats, TileComparison, TileConfig, TileLayer};
 use anyhow::Result;
-use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::Serialize;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::fs;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, RwLock};
 
-#[derive(Debug, Deserialize, Clone)]
-pub struct TileConfig {
-    pub lst_tile_url: String,
-    pub anomaly_tile_url: String,
-    pub absolute_anomaly_tile_url: String,
+/// Bboxes whose statistics are kept per year pair, oldest dropped first
+const MAX_STATS_PER_COMPARISON: usize = 64;
+
+/// Counters for changes picked up from the config directory
+#[derive(Debug, Default)]
+struct ReloadStats {
+    reloaded: AtomicU64,
+    evicted: AtomicU64,
+    rejected: AtomicU64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReloadStatsSnapshot {
+    pub reloaded: u64,
+    pub evicted: u64,
+    pub rejected: u64,
 }
 
+/// A year-pair difference layer and the bbox statistics computed for it so far
+#[derive(Debug, Default)]
+struct ComparisonEntry {
+    comparison: Option<TileComparison>,
+    stats: Vec<ChangeStats>,
+}
+
+/// (layer, from, to)
+type ComparisonKey = (TileLayer, i32, i32);
+
 #[derive(Clone)]
 pub struct TileConfigService {
     pub cache: Arc<RwLock<HashMap<i32, TileConfig>>>, // year -> config
-    base_path: String,                                // folder path where tile configs are stored
+    base_path: PathBuf,                               // folder holding tile_config_{year}.json
+    stats: Arc<ReloadStats>,
+    comparisons: Arc<RwLock<HashMap<ComparisonKey, ComparisonEntry>>>,
 }
 
 impl TileConfigService {
     /// Create a new service with a given base path
-    pub fn new(base_path: &str) -> Self {
+    pub fn new(base_path: &Path) -> Self {
         Self {
             cache: Arc::new(RwLock::new(HashMap::new())),
-            base_path: base_path.to_string(),
+            base_path: base_path.to_path_buf(),
+            stats: Arc::new(ReloadStats::default()),
+            comparisons: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
-    /// Load tile config for a specific year from disk
-    pub async fn load_config_for_year(&self, year: i32) -> Result<()> {
-        // Construct file path
-        let file_path = format!("{}/tiles/tile_config_{}.json", self.base_path, year);
+    fn config_path(&self, year: i32) -> PathBuf {
+        self.base_path.join(format!("tile_config_{}.json", year))
+    }
+
+    /// Load every tile_config_{year}.json in the base folder
+    pub async fn load_all(&self) {
+        let Ok(mut entries) = fs::read_dir(&self.base_path).await else {
+            eprintln!("Cannot read tile config dir {}", self.base_path.display());
+            return;
+        };
 
-        // Debug: print the path to make sure it's correct
-        println!("Loading tile config from: {}", file_path);
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if let Some(year) = year_from_path(&entry.path()) {
+                if let Err(e) = self.load_config_for_year(year).await {
+                    eprintln!("Failed to load {}: {}", entry.path().display(), e);
+                } else {
+                    println!("Preloaded tile config for year {}", year);
+                }
+            }
+        }
+    }
+
+    /// Load tile config for a specific year from disk. The file is fully parsed
+    /// before the cache is touched, so a bad file never replaces a good config.
+    pub async fn load_config_for_year(&self, year: i32) -> Result<()> {
+        let file_path = self.config_path(year);
 
         // Read the file
         let config_data = fs::read_to_string(&file_path).await?;
@@ -46,6 +95,97 @@ impl TileConfigService {
         Ok(())
     }
 
+    /// Write a freshly generated config to disk and cache it. The file is written
+    /// next to its final name and renamed, so watchers never see it half-written.
+    pub async fn save_config_for_year(&self, year: i32, config: TileConfig) -> Result<()> {
+        let file_path = self.config_path(year);
+        let tmp_path = file_path.with_extension("json.tmp");
+        fs::create_dir_all(&self.base_path).await?;
+        fs::write(&tmp_path, serde_json::to_string_pretty(&config)?).await?;
+        fs::rename(&tmp_path, &file_path).await?;
+        println!("Saved tile config to: {}", file_path.display());
+
+        let mut write_guard = self.cache.write().await;
+        write_guard.insert(year, config);
+        Ok(())
+    }
+
+    /// Watch the base folder and keep the cache in sync: changed files are
+    /// reloaded, deleted files evicted, and malformed files ignored in favour
+    /// of the last good config.
+    pub fn watch(&self) -> Result<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let _ = tx.send(event);
+        })?;
+        watcher.watch(&self.base_path, RecursiveMode::NonRecursive)?;
+
+        let service = self.clone();
+        tokio::spawn(async move {
+            // Keep the watcher alive for as long as the task runs
+            let _watcher = watcher;
+            while let Some(event) = rx.recv().await {
+                match event {
+                    Ok(event) => service.handle_fs_event(event).await,
+                    Err(e) => eprintln!("Tile config watcher error: {}", e),
+                }
+            }
+        });
+
+        println!("Watching {} for tile config changes", self.base_path.display());
+        Ok(())
+    }
+
+    async fn handle_fs_event(&self, event: notify::Event) {
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+
+        for path in &event.paths {
+            let Some(year) = year_from_path(path) else {
+                continue;
+            };
+
+            if let Ok(metadata) = fs::metadata(path).await {
+                if metadata.len() == 0 {
+                    // Just created; the write that follows triggers another event
+                    continue;
+                }
+                match self.load_config_for_year(year).await {
+                    Ok(()) => {
+                        self.stats.reloaded.fetch_add(1, Ordering::Relaxed);
+                        println!("Reloaded tile config for year {}", year);
+                    }
+                    Err(e) => {
+                        self.stats.rejected.fetch_add(1, Ordering::Relaxed);
+                        eprintln!(
+                            "Rejected {} (keeping previous config): {}",
+                            path.display(),
+                            e
+                        );
+                    }
+                }
+            } else if self.cache.write().await.remove(&year).is_some() {
+                self.stats.evicted.fetch_add(1, Ordering::Relaxed);
+                println!("Evicted tile config for year {} (file removed)", year);
+            }
+        }
+    }
+
+    /// Modification time of the year's file, for configs without `generated_at`
+    pub async fn config_modified(&self, year: i32) -> Option<DateTime<Utc>> {
+        let modified = fs::metadata(self.config_path(year)).await.ok()?.modified().ok()?;
+        Some(modified.into())
+    }
+
+    pub fn reload_stats(&self) -> ReloadStatsSnapshot {
+        ReloadStatsSnapshot {
+            reloaded: self.stats.reloaded.load(Ordering::Relaxed),
+            evicted: self.stats.evicted.load(Ordering::Relaxed),
+            rejected: self.stats.rejected.load(Ordering::Relaxed),
+        }
+    }
+
     /// Get tile config for a specific year (tries cache first, then disk)
     pub async fn get_config_for_year(&self, year: i32) -> Option<TileConfig> {
         // Try cache first
@@ -64,15 +204,54 @@ impl TileConfigService {
         }
     }
 
-    /// Get tile URLs for a specific year
-    pub async fn get_tile_urls_for_year(&self, year: i32) -> Option<(String, String, String)> {
-        self.get_config_for_year(year).await.map(|cfg| {
-            (
-                cfg.lst_tile_url,
-                cfg.anomaly_tile_url,
-                cfg.absolute_anomaly_tile_url,
-            )
-        })
+    /// All cached configs, ordered by year
+    pub async fn get_all_configs(&self) -> Vec<(i32, TileConfig)> {
+        let read_guard = self.cache.read().await;
+        let mut configs: Vec<(i32, TileConfig)> = read_guard
+            .iter()
+            .map(|(year, cfg)| (*year, cfg.clone()))
+            .collect();
+        configs.sort_by_key(|(year, _)| *year);
+        configs
+    }
+
+    /// Cached difference layer of `to` minus `from`
+    pub async fn get_comparison(&self, layer: TileLayer, from: i32, to: i32) -> Option<TileComparison> {
+        let read_guard = self.comparisons.read().await;
+        read_guard.get(&(layer, from, to))?.comparison.clone()
+    }
+
+    /// Cache a difference layer; statistics already computed for the pair are kept
+    pub async fn save_comparison(&self, comparison: TileComparison) {
+        let key = (comparison.layer, comparison.from, comparison.to);
+        let mut write_guard = self.comparisons.write().await;
+        write_guard.entry(key).or_default().comparison = Some(comparison);
+    }
+
+    pub async fn get_comparison_stats(
+        &self,
+        layer: TileLayer,
+        from: i32,
+        to: i32,
+        bbox: [f64; 4],
+    ) -> Option<ChangeStats> {
+        let read_guard = self.comparisons.read().await;
+        read_guard
+            .get(&(layer, from, to))?
+            .stats
+            .iter()
+            .find(|stats| stats.bbox == bbox)
+            .copied()
+    }
+
+    pub async fn save_comparison_stats(&self, layer: TileLayer, from: i32, to: i32, stats: ChangeStats) {
+        let mut write_guard = self.comparisons.write().await;
+        let entry = write_guard.entry((layer, from, to)).or_default();
+        entry.stats.retain(|cached| cached.bbox != stats.bbox);
+        if entry.stats.len() >= MAX_STATS_PER_COMPARISON {
+            entry.stats.remove(0);
+        }
+        entry.stats.push(stats);
     }
 
     /// List all loaded years
@@ -82,28 +261,67 @@ impl TileConfigService {
     }
 }
 
-// -----------------------
-// Example usage
-// -----------------------
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    // Set your folder where Python script generates tile JSON configs
-    let base_path = "C:/Users/amina/app/climate-backend";
-
-    // Create the service
-    let tile_service = TileConfigService::new(base_path);
+/// `2025` for `.../tile_config_2025.json`
+fn year_from_path(path: &Path) -> Option<i32> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix("tile_config_")?
+        .strip_suffix(".json")?
+        .parse()
+        .ok()
+}
 
-    // Preload a specific year (optional)
-    tile_service.load_config_for_year(2025).await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{ModifyKind, RemoveKind};
 
-    // Fetch tile URLs for 2025
-    if let Some((lst, anomaly, abs_anomaly)) = tile_service.get_tile_urls_for_year(2025).await {
-        println!("LST Tile: {}", lst);
-        println!("Anomaly Tile: {}", anomaly);
-        println!("Absolute Anomaly Tile: {}", abs_anomaly);
-    } else {
-        println!("Tile config not found for 2025");
+    fn config(url: &str) -> TileConfig {
+        TileConfig {
+            lst_tile_url: url.to_string(),
+            anomaly_tile_url: url.to_string(),
+            absolute_anomaly_tile_url: url.to_string(),
+            generated_at: None,
+            target_period: None,
+            climatology_period: None,
+            data_source: None,
+            description: None,
+        }
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn keeps_the_last_good_config_when_a_write_is_malformed() {
+        let dir = std::env::temp_dir().join(format!("tile-configs-{}", uuid::Uuid::new_v4()));
+        let service = TileConfigService::new(&dir);
+        service.save_config_for_year(2024, config("https://good/{z}/{x}/{y}")).await.unwrap();
+        let path = service.config_path(2024);
+        let modified = |path: &Path| {
+            notify::Event::new(EventKind::Modify(ModifyKind::Any)).add_path(path.to_path_buf())
+        };
+
+        // A truncated write is rejected and counted
+        std::fs::write(&path, "{\"lst_tile_url\": \"https://bad/").unwrap();
+        service.handle_fs_event(modified(&path)).await;
+        let cached = service.get_config_for_year(2024).await.unwrap();
+        assert_eq!(cached.lst_tile_url, "https://good/{z}/{x}/{y}");
+        let stats = service.reload_stats();
+        assert_eq!((stats.reloaded, stats.rejected, stats.evicted), (0, 1, 0));
+
+        // A complete write replaces it
+        std::fs::write(&path, serde_json::to_string(&config("https://new/{z}/{x}/{y}")).unwrap()).unwrap();
+        service.handle_fs_event(modified(&path)).await;
+        let cached = service.get_config_for_year(2024).await.unwrap();
+        assert_eq!(cached.lst_tile_url, "https://new/{z}/{x}/{y}");
+        assert_eq!(service.reload_stats().reloaded, 1);
+
+        // Deleting the file evicts the year
+        std::fs::remove_file(&path).unwrap();
+        service
+            .handle_fs_event(notify::Event::new(EventKind::Remove(RemoveKind::File)).add_path(path))
+            .await;
+        assert!(service.get_config_for_year(2024).await.is_none());
+        assert_eq!(service.reload_stats().evicted, 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
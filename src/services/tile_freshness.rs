@@ -0,0 +1,360 @@
+// src/services/tile_freshness.rs
+//
+// Earth Engine map IDs in tile URLs stop working some hours after they were
+// created. This tracks each year's age against a TTL, probes a known tile to
+// catch early expiry, and queues regeneration before the links go dead.
+use crate::config::TilesConfig;
+use crate::models::tile_config::TileConfig;
+use crate::services::tile_config_service::TileConfigService;
+use crate::services::tile_jobs::TileJobService;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use reqwest::Client;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FreshnessStatus {
+    Fresh,
+    /// Within the refresh margin of its TTL; a refresh is due
+    Expiring,
+    /// Past its TTL, or the probe tile was rejected
+    Expired,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    pub checked_at: DateTime<Utc>,
+    pub ok: bool,
+    pub http_status: Option<u16>,
+    pub error: Option<String>,
+}
+
+impl ProbeResult {
+    /// Earth Engine answers 400/404 for expired map IDs. Other statuses (403
+    /// quota, 429 throttling, ...) and network errors are inconclusive and do
+    /// not count.
+    fn map_gone(&self) -> bool {
+        matches!(self.http_status, Some(400 | 404))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct YearFreshness {
+    pub year: i32,
+    pub created_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub seconds_remaining: Option<i64>,
+    pub status: FreshnessStatus,
+    pub last_probe: Option<ProbeResult>,
+    pub last_refresh_at: Option<DateTime<Utc>>,
+    pub refresh_job: Option<Uuid>,
+}
+
+#[derive(Default)]
+struct Tracked {
+    /// Probe of the URL it was taken for; ignored once the config changes
+    probe: Option<(String, ProbeResult)>,
+    last_refresh: Option<(DateTime<Utc>, Uuid)>,
+}
+
+pub struct TileFreshnessService {
+    tile_config_service: Arc<TileConfigService>,
+    jobs: Arc<TileJobService>,
+    client: Client,
+    ttl: chrono::Duration,
+    refresh_margin: chrono::Duration,
+    check_interval: Duration,
+    auto_refresh: bool,
+    tracked: RwLock<HashMap<i32, Tracked>>,
+}
+
+impl TileFreshnessService {
+    pub fn new(
+        config: &TilesConfig,
+        tile_config_service: Arc<TileConfigService>,
+        jobs: Arc<TileJobService>,
+    ) -> Self {
+        Self {
+            tile_config_service,
+            jobs,
+            client: Client::builder()
+                .timeout(Duration::from_secs(15))
+                .build()
+                .expect("reqwest client builds"),
+            ttl: chrono::Duration::seconds(config.map_ttl_secs as i64),
+            refresh_margin: chrono::Duration::seconds(config.refresh_margin_secs as i64),
+            check_interval: Duration::from_secs(config.freshness_check_secs),
+            auto_refresh: config.auto_refresh,
+            tracked: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Probe and refresh every loaded year on a fixed interval.
+    pub fn spawn(self: &Arc<Self>) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(service.check_interval);
+            loop {
+                ticker.tick().await;
+                service.check_all().await;
+            }
+        });
+    }
+
+    async fn check_all(&self) {
+        for (year, config) in self.tile_config_service.get_all_configs().await {
+            if config.is_local() {
+                continue;
+            }
+            let probe = self.probe(&config).await;
+            if !probe.ok {
+                eprintln!(
+                    "Tile probe failed for year {}: {}",
+                    year,
+                    probe.error.as_deref().unwrap_or("unknown error")
+                );
+            }
+            self.tracked
+                .write()
+                .await
+                .entry(year)
+                .or_default()
+                .probe = Some((config.lst_tile_url.clone(), probe));
+
+            let freshness = self.freshness_of(year, &config).await;
+            if freshness.status != FreshnessStatus::Fresh {
+                self.refresh(year).await;
+            }
+        }
+    }
+
+    /// Fetch tile 0/0/0 of the LST layer.
+    async fn probe(&self, config: &TileConfig) -> ProbeResult {
+        let url = config
+            .lst_tile_url
+            .replace("{z}", "0")
+            .replace("{x}", "0")
+            .replace("{y}", "0");
+
+        match self.client.get(&url).send().await {
+            Ok(response) => ProbeResult {
+                checked_at: Utc::now(),
+                ok: response.status().is_success(),
+                http_status: Some(response.status().as_u16()),
+                error: (!response.status().is_success())
+                    .then(|| format!("HTTP {}", response.status())),
+            },
+            Err(e) => ProbeResult {
+                checked_at: Utc::now(),
+                ok: false,
+                http_status: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+
+    /// Queue regeneration unless one was queued within the refresh margin.
+    /// Returns `None` when `tiles.auto_refresh` is off.
+    pub async fn refresh(&self, year: i32) -> Option<Uuid> {
+        if !self.auto_refresh {
+            return None;
+        }
+
+        let last_refresh = self.tracked.read().await.get(&year).and_then(|entry| entry.last_refresh);
+        if let Some((at, job_id)) = last_refresh {
+            if Utc::now() - at < self.refresh_margin {
+                return Some(job_id);
+            }
+        }
+
+        // Not holding `tracked` here: submitting waits for the job service, and
+        // a racing refresh of the same year is coalesced into the same job
+        let (job, created) = self.jobs.submit(year).await;
+        if created {
+            println!("Refreshing tile URLs for year {} (job {})", year, job.id);
+        }
+        self.tracked.write().await.entry(year).or_default().last_refresh = Some((Utc::now(), job.id));
+        Some(job.id)
+    }
+
+    pub async fn freshness(&self, year: i32) -> Option<YearFreshness> {
+        let config = self.tile_config_service.get_config_for_year(year).await?;
+        Some(self.freshness_of(year, &config).await)
+    }
+
+    pub async fn all(&self) -> Vec<YearFreshness> {
+        let mut all = Vec::new();
+        for (year, config) in self.tile_config_service.get_all_configs().await {
+            all.push(self.freshness_of(year, &config).await);
+        }
+        all
+    }
+
+    async fn freshness_of(&self, year: i32, config: &TileConfig) -> YearFreshness {
+        let created_at = match config.generated_at.as_deref().and_then(parse_generated_at) {
+            Some(created_at) => Some(created_at),
+            None => self.tile_config_service.config_modified(year).await,
+        };
+        // Locally rendered grids have no map IDs to expire
+        let expires_at = created_at
+            .filter(|_| !config.is_local())
+            .map(|created_at| created_at + self.ttl);
+        let now = Utc::now();
+
+        let tracked = self.tracked.read().await;
+        let entry = tracked.get(&year);
+        let last_probe = entry
+            .and_then(|entry| entry.probe.as_ref())
+            .filter(|(url, _)| *url == config.lst_tile_url)
+            .map(|(_, probe)| probe.clone());
+
+        let status = match (expires_at, &last_probe) {
+            (_, Some(probe)) if probe.map_gone() => FreshnessStatus::Expired,
+            (Some(expires_at), _) if now >= expires_at => FreshnessStatus::Expired,
+            (Some(expires_at), _) if now >= expires_at - self.refresh_margin => {
+                FreshnessStatus::Expiring
+            }
+            _ => FreshnessStatus::Fresh,
+        };
+
+        YearFreshness {
+            year,
+            created_at,
+            expires_at,
+            seconds_remaining: expires_at.map(|expires_at| (expires_at - now).num_seconds()),
+            status,
+            last_probe,
+            last_refresh_at: entry.and_then(|entry| entry.last_refresh.map(|(at, _)| at)),
+            refresh_job: entry.and_then(|entry| entry.last_refresh.map(|(_, id)| id)),
+        }
+    }
+
+    /// True when the probe showed the year's current URLs no longer load.
+    pub async fn is_dead(&self, year: i32, config: &TileConfig) -> bool {
+        self.tracked
+            .read()
+            .await
+            .get(&year)
+            .and_then(|entry| entry.probe.as_ref())
+            .is_some_and(|(url, probe)| *url == config.lst_tile_url && probe.map_gone())
+    }
+}
+
+/// `generated_at` is RFC 3339 from both generators. Configs written by older
+/// versions of `scripts/gee_rust.py` carry a naive local time, read as UTC
+/// because the offset is unknown.
+fn parse_generated_at(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok()
+        // Legacy naive timestamp
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+                .ok()
+                .map(|dt| dt.and_utc())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::tile_jobs;
+
+    fn config(url: &str, generated_at: Option<String>) -> TileConfig {
+        TileConfig {
+            lst_tile_url: url.to_string(),
+            anomaly_tile_url: url.to_string(),
+            absolute_anomaly_tile_url: url.to_string(),
+            generated_at,
+            target_period: None,
+            climatology_period: None,
+            data_source: None,
+            description: None,
+        }
+    }
+
+    fn probe(http_status: Option<u16>) -> ProbeResult {
+        ProbeResult {
+            checked_at: Utc::now(),
+            ok: http_status == Some(200),
+            http_status,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn only_400_and_404_mean_the_map_is_gone() {
+        assert!(probe(Some(400)).map_gone());
+        assert!(probe(Some(404)).map_gone());
+        for status in [Some(200), Some(403), Some(429), Some(500), None] {
+            assert!(!probe(status).map_gone(), "{:?}", status);
+        }
+    }
+
+    #[tokio::test]
+    async fn judges_freshness_from_generated_at() {
+        let dir = std::env::temp_dir().join(format!("tile-freshness-{}", Uuid::new_v4()));
+        let tiles = TilesConfig {
+            config_dir: dir.clone(),
+            ..TilesConfig::default()
+        };
+        let freshness = TileFreshnessService::new(
+            &tiles,
+            Arc::new(TileConfigService::new(&dir)),
+            tile_jobs::tests::service(&dir, 1),
+        );
+        let url = "https://earthengine.googleapis.com/v1/maps/abc/tiles/{z}/{x}/{y}";
+        let generated = |age: chrono::Duration| Some((Utc::now() - age).to_rfc3339());
+
+        // 4h TTL with a 30 minute refresh margin
+        let status = |config: TileConfig| {
+            let freshness = &freshness;
+            async move { freshness.freshness_of(2024, &config).await }
+        };
+        let fresh = status(config(url, generated(chrono::Duration::hours(1)))).await;
+        assert_eq!(fresh.status, FreshnessStatus::Fresh);
+        let remaining = fresh.seconds_remaining.unwrap();
+        assert!((3 * 3600 - 5..=3 * 3600).contains(&remaining), "{}", remaining);
+        assert_eq!(
+            status(config(url, generated(chrono::Duration::minutes(220)))).await.status,
+            FreshnessStatus::Expiring
+        );
+        assert_eq!(
+            status(config(url, generated(chrono::Duration::hours(5)))).await.status,
+            FreshnessStatus::Expired
+        );
+
+        // Legacy naive timestamps are read as UTC
+        let legacy = (Utc::now() - chrono::Duration::hours(5)).naive_utc().format("%Y-%m-%dT%H:%M:%S%.f");
+        assert_eq!(
+            status(config(url, Some(legacy.to_string()))).await.status,
+            FreshnessStatus::Expired
+        );
+
+        // Local grids never expire
+        let local = status(config("local://lst_2024/{z}/{x}/{y}", generated(chrono::Duration::days(30)))).await;
+        assert_eq!(local.status, FreshnessStatus::Fresh);
+        assert!(local.expires_at.is_none());
+
+        // A rejected probe of the current URL expires a young config; one of an old URL does not
+        freshness.tracked.write().await.entry(2024).or_default().probe =
+            Some(("https://old/{z}/{x}/{y}".to_string(), probe(Some(404))));
+        assert_eq!(
+            status(config(url, generated(chrono::Duration::hours(1)))).await.status,
+            FreshnessStatus::Fresh
+        );
+        freshness.tracked.write().await.entry(2024).or_default().probe =
+            Some((url.to_string(), probe(Some(404))));
+        assert_eq!(
+            status(config(url, generated(chrono::Duration::hours(1)))).await.status,
+            FreshnessStatus::Expired
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,575 @@
+// src/services/tile_jobs.rs
+//
+// Background tile generation. `POST /tiles/generate` only enqueues a job; a
+// bounded pool of workers runs the generator (the Python script or the native
+// Earth Engine client) and records its output, timings and result. Every
+// output line is also published as a `JobEvent` for the SSE progress stream.
+use crate::config::{TileGenerator, TilesConfig};
+use crate::services::earthengine::EarthEngineService;
+use crate::models::tile_config::TileConfig;
+use crate::services::tile_config_service::TileConfigService;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::{broadcast, mpsc, RwLock, Semaphore};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// Finished jobs kept for `GET /tiles/jobs`; older ones are dropped first
+const FINISHED_JOBS_RETAINED: usize = 100;
+
+/// Events buffered per live subscriber before it starts missing lines
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn is_finished(self) -> bool {
+        !matches!(self, JobState::Queued | JobState::Running)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TileJob {
+    pub id: Uuid,
+    pub year: i32,
+    pub state: JobState,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub stdout: String,
+    pub stderr: String,
+    pub error: Option<String>,
+    pub result: Option<TileConfig>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Progress of a job as streamed to `GET /tiles/generate/{year}/events`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobEvent {
+    /// A generator stage began; `progress` is a rough 0..1 fraction for progress bars
+    Stage {
+        stage: &'static str,
+        progress: f32,
+        message: String,
+    },
+    /// Non-fatal problem, e.g. a month without source data
+    Warning { message: String },
+    /// Any other output line
+    Log { stream: OutputStream, line: String },
+    Completed { result: TileConfig },
+    Failed { error: String },
+    Cancelled,
+}
+
+impl JobEvent {
+    /// SSE event name
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobEvent::Stage { .. } => "stage",
+            JobEvent::Warning { .. } => "warning",
+            JobEvent::Log { .. } => "log",
+            JobEvent::Completed { .. } => "completed",
+            JobEvent::Failed { .. } => "failed",
+            JobEvent::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            JobEvent::Completed { .. } | JobEvent::Failed { .. } | JobEvent::Cancelled
+        )
+    }
+
+    /// Classify a generator output line. Stage and warning lines are the ones
+    /// printed by `scripts/gee_rust.py` (and mirrored by the native generator).
+    fn from_line(stream: OutputStream, line: String) -> Self {
+        if let OutputStream::Stdout = stream {
+            let stage = match line.as_str() {
+                "Processing MODIS data..." => Some(("modis", 0.1)),
+                "Processing ERA5 data..." => Some(("era5", 0.45)),
+                "Generating tile URLs..." => Some(("tile_urls", 0.8)),
+                _ => None,
+            };
+            if let Some((stage, progress)) = stage {
+                return JobEvent::Stage {
+                    stage,
+                    progress,
+                    message: line,
+                };
+            }
+            if line.starts_with("No data found") {
+                return JobEvent::Warning { message: line };
+            }
+        }
+        JobEvent::Log { stream, line }
+    }
+}
+
+/// Replay of a job's events so far plus, while it is still active, a live feed
+pub struct JobSubscription {
+    pub job: TileJob,
+    pub history: Vec<JobEvent>,
+    pub live: Option<broadcast::Receiver<JobEvent>>,
+}
+
+struct JobEntry {
+    job: TileJob,
+    cancel: CancellationToken,
+    history: Vec<JobEvent>,
+    events: broadcast::Sender<JobEvent>,
+}
+
+impl JobEntry {
+    fn emit(&mut self, event: JobEvent) {
+        self.history.push(event.clone());
+        // No receivers is fine: nobody is watching this job
+        let _ = self.events.send(event);
+    }
+}
+
+pub struct TileJobService {
+    jobs: RwLock<HashMap<Uuid, JobEntry>>,
+    workers: Semaphore,
+    tile_config_service: Arc<TileConfigService>,
+    earth_engine: Arc<EarthEngineService>,
+    generator: TileGenerator,
+    python_bin: String,
+    generator_script: PathBuf,
+    output_dir: PathBuf,
+    viz_file: PathBuf,
+}
+
+/// Why a job could not be cancelled
+pub enum CancelError {
+    NotFound,
+    AlreadyFinished(JobState),
+}
+
+impl TileJobService {
+    pub fn new(
+        config: &TilesConfig,
+        tile_config_service: Arc<TileConfigService>,
+        earth_engine: Arc<EarthEngineService>,
+    ) -> Self {
+        Self {
+            jobs: RwLock::new(HashMap::new()),
+            workers: Semaphore::new(config.max_concurrent_jobs),
+            tile_config_service,
+            earth_engine,
+            generator: config.generator,
+            python_bin: config.python_bin.clone(),
+            generator_script: config.generator_script.clone(),
+            output_dir: config.config_dir.clone(),
+            viz_file: config.viz_file.clone(),
+        }
+    }
+
+    /// Queue generation for `year`. If a job for that year is already queued or
+    /// running it is returned instead, with `false` as the second value.
+    pub async fn submit(self: &Arc<Self>, year: i32) -> (TileJob, bool) {
+        let mut jobs = self.jobs.write().await;
+
+        if let Some(entry) = jobs
+            .values()
+            .find(|entry| entry.job.year == year && !entry.job.state.is_finished())
+        {
+            return (entry.job.clone(), false);
+        }
+
+        let job = TileJob {
+            id: Uuid::new_v4(),
+            year,
+            state: JobState::Queued,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            error: None,
+            result: None,
+        };
+        let cancel = CancellationToken::new();
+        jobs.insert(
+            job.id,
+            JobEntry {
+                job: job.clone(),
+                cancel: cancel.clone(),
+                history: Vec::new(),
+                events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            },
+        );
+        prune_finished(&mut jobs);
+        drop(jobs);
+
+        let service = self.clone();
+        let id = job.id;
+        tokio::spawn(async move { service.run(id, year, cancel).await });
+
+        (job, true)
+    }
+
+    pub async fn get(&self, id: Uuid) -> Option<TileJob> {
+        self.jobs.read().await.get(&id).map(|entry| entry.job.clone())
+    }
+
+    /// All known jobs, newest first
+    pub async fn list(&self) -> Vec<TileJob> {
+        let jobs = self.jobs.read().await;
+        let mut list: Vec<TileJob> = jobs.values().map(|entry| entry.job.clone()).collect();
+        list.sort_by_key(|job| std::cmp::Reverse(job.created_at));
+        list
+    }
+
+    /// Events of the active job for `year`, or of its most recent job if none is active.
+    pub async fn subscribe_year(&self, year: i32) -> Option<JobSubscription> {
+        let jobs = self.jobs.read().await;
+        let entry = jobs
+            .values()
+            .filter(|entry| entry.job.year == year)
+            .max_by_key(|entry| (!entry.job.state.is_finished(), entry.job.created_at))?;
+
+        Some(JobSubscription {
+            job: entry.job.clone(),
+            history: entry.history.clone(),
+            live: (!entry.job.state.is_finished()).then(|| entry.events.subscribe()),
+        })
+    }
+
+    pub async fn cancel(&self, id: Uuid) -> Result<TileJob, CancelError> {
+        let mut jobs = self.jobs.write().await;
+        let entry = jobs.get_mut(&id).ok_or(CancelError::NotFound)?;
+
+        if entry.job.state.is_finished() {
+            return Err(CancelError::AlreadyFinished(entry.job.state));
+        }
+
+        entry.cancel.cancel();
+        entry.job.state = JobState::Cancelled;
+        entry.job.finished_at = Some(Utc::now());
+        entry.emit(JobEvent::Cancelled);
+        Ok(entry.job.clone())
+    }
+
+    async fn run(&self, id: Uuid, year: i32, cancel: CancellationToken) {
+        let _permit = tokio::select! {
+            permit = self.workers.acquire() => permit.expect("worker semaphore is never closed"),
+            _ = cancel.cancelled() => return,
+        };
+
+        self.update(id, |entry| {
+            entry.job.state = JobState::Running;
+            entry.job.started_at = Some(Utc::now());
+            entry.emit(JobEvent::Stage {
+                stage: "started",
+                progress: 0.0,
+                message: format!("Generating tiles for {}", year),
+            });
+        })
+        .await;
+        println!("Tile job {} started for year {}", id, year);
+
+        let outcome = tokio::select! {
+            outcome = self.generate(id, year) => outcome,
+            // Dropping the generator future kills the child process (kill_on_drop)
+            _ = cancel.cancelled() => {
+                println!("Tile job {} cancelled", id);
+                return;
+            }
+        };
+
+        self.update(id, |entry| {
+            let job = &mut entry.job;
+            job.finished_at = Some(Utc::now());
+            match outcome {
+                Ok(config) => {
+                    job.state = JobState::Succeeded;
+                    job.result = Some(config.clone());
+                    entry.emit(JobEvent::Completed { result: config });
+                }
+                Err(error) => {
+                    job.state = JobState::Failed;
+                    job.error = Some(error.clone());
+                    entry.emit(JobEvent::Failed { error });
+                }
+            }
+        })
+        .await;
+        println!("Tile job {} finished for year {}", id, year);
+    }
+
+    async fn update(&self, id: Uuid, f: impl FnOnce(&mut JobEntry)) {
+        if let Some(entry) = self.jobs.write().await.get_mut(&id) {
+            if entry.job.state != JobState::Cancelled {
+                f(entry);
+            }
+        }
+    }
+
+    /// Append a generator output line to the job and publish it.
+    async fn record_line(&self, id: Uuid, stream: OutputStream, line: String) {
+        self.update(id, |entry| {
+            let buffer = match stream {
+                OutputStream::Stdout => &mut entry.job.stdout,
+                OutputStream::Stderr => &mut entry.job.stderr,
+            };
+            buffer.push_str(&line);
+            buffer.push('\n');
+            entry.emit(JobEvent::from_line(stream, line));
+        })
+        .await;
+    }
+
+    async fn generate(&self, id: Uuid, year: i32) -> Result<TileConfig, String> {
+        match self.generator {
+            TileGenerator::Python => self.generate_with_python(id, year).await,
+            TileGenerator::EarthEngine => self.generate_with_earth_engine(id, year).await,
+        }
+    }
+
+    async fn generate_with_python(&self, id: Uuid, year: i32) -> Result<TileConfig, String> {
+        // Run Python with --year
+        let mut command = Command::new(&self.python_bin);
+        command
+            .arg(&self.generator_script)
+            .arg("--year")
+            .arg(year.to_string())
+            .arg("--output-dir")
+            .arg(&self.output_dir);
+        // Without the file the script falls back to the repo's gee/viz.json
+        if self.viz_file.exists() {
+            command.arg("--viz-file").arg(&self.viz_file);
+        }
+        // Otherwise the script uses GOOGLE_APPLICATION_CREDENTIALS
+        let key_file = self.earth_engine.service_account_key();
+        if key_file.is_file() {
+            command.arg("--key-file").arg(key_file);
+        }
+        let mut child = command
+            // Piped stdout is block-buffered by default; progress must arrive line by line
+            .env("PYTHONUNBUFFERED", "1")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to run Python: {}", e))?;
+
+        let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped")).lines();
+        let mut stderr = BufReader::new(child.stderr.take().expect("stderr is piped")).lines();
+        let (mut stdout_open, mut stderr_open) = (true, true);
+        let mut errors = Vec::new();
+
+        while stdout_open || stderr_open {
+            tokio::select! {
+                line = stdout.next_line(), if stdout_open => match line {
+                    Ok(Some(line)) => self.record_line(id, OutputStream::Stdout, line).await,
+                    _ => stdout_open = false,
+                },
+                line = stderr.next_line(), if stderr_open => match line {
+                    Ok(Some(line)) => {
+                        errors.push(line.clone());
+                        self.record_line(id, OutputStream::Stderr, line).await;
+                    }
+                    _ => stderr_open = false,
+                },
+            }
+        }
+
+        let status = child
+            .wait()
+            .await
+            .map_err(|e| format!("Failed to wait for Python: {}", e))?;
+        if !status.success() {
+            return Err(format!("Python failed ({}): {}", status, errors.join("\n")));
+        }
+
+        // Reload JSON config (with retries)
+        for _ in 0..3 {
+            if self.tile_config_service.load_config_for_year(year).await.is_ok() {
+                if let Some(config) = self.tile_config_service.get_config_for_year(year).await {
+                    return Ok(config);
+                }
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        }
+
+        Err(format!("Tiles generated but config not found for year {}", year))
+    }
+
+    async fn generate_with_earth_engine(&self, id: Uuid, year: i32) -> Result<TileConfig, String> {
+        let (progress, mut lines) = mpsc::unbounded_channel();
+        let generation = self.earth_engine.generate_year_tiles(year, &progress);
+        tokio::pin!(generation);
+
+        let result = loop {
+            tokio::select! {
+                result = &mut generation => break result,
+                Some(line) = lines.recv() => {
+                    self.record_line(id, OutputStream::Stdout, line).await;
+                }
+            }
+        };
+        while let Ok(line) = lines.try_recv() {
+            self.record_line(id, OutputStream::Stdout, line).await;
+        }
+
+        let config = result.map_err(|e| format!("Earth Engine generation failed: {:#}", e))?;
+
+        self.tile_config_service
+            .save_config_for_year(year, config.clone())
+            .await
+            .map_err(|e| format!("Failed to save tile config: {}", e))?;
+
+        Ok(config)
+    }
+}
+
+fn prune_finished(jobs: &mut HashMap<Uuid, JobEntry>) {
+    let mut finished: Vec<(DateTime<Utc>, Uuid)> = jobs
+        .values()
+        .filter(|entry| entry.job.state.is_finished())
+        .map(|entry| (entry.job.created_at, entry.job.id))
+        .collect();
+
+    if finished.len() > FINISHED_JOBS_RETAINED {
+        finished.sort();
+        let excess = finished.len() - FINISHED_JOBS_RETAINED;
+        for (_, id) in finished.into_iter().take(excess) {
+            jobs.remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::EarthEngineConfig;
+    use crate::services::vis_registry::VisRegistry;
+    use std::time::Duration;
+
+    /// Job service whose generator is a shell script printing the Python
+    /// script's first stage lines and then hanging until cancelled
+    pub(crate) fn service(dir: &std::path::Path, max_concurrent_jobs: usize) -> Arc<TileJobService> {
+        std::fs::create_dir_all(dir).unwrap();
+        let script = dir.join("generator.sh");
+        std::fs::write(
+            &script,
+            "echo 'Processing MODIS data...'\nsleep 1\necho 'Processing ERA5 data...'\nsleep 30\n",
+        )
+        .unwrap();
+        let config = TilesConfig {
+            config_dir: dir.to_path_buf(),
+            generator: TileGenerator::Python,
+            python_bin: "sh".to_string(),
+            generator_script: script,
+            max_concurrent_jobs,
+            ..TilesConfig::default()
+        };
+        Arc::new(TileJobService::new(
+            &config,
+            Arc::new(TileConfigService::new(dir)),
+            Arc::new(EarthEngineService::new(
+                &EarthEngineConfig::default(),
+                Arc::new(VisRegistry::builtin()),
+            )),
+        ))
+    }
+
+    pub(crate) async fn wait_for(jobs: &TileJobService, id: Uuid, done: impl Fn(&TileJob) -> bool) {
+        for _ in 0..100 {
+            if done(&jobs.get(id).await.unwrap()) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("job {} never reached the expected state", id);
+    }
+
+    #[tokio::test]
+    async fn runs_at_most_max_concurrent_jobs() {
+        let dir = std::env::temp_dir().join(format!("tile-jobs-{}", Uuid::new_v4()));
+        let jobs = service(&dir, 1);
+
+        let (first, _) = jobs.submit(2020).await;
+        let (second, _) = jobs.submit(2021).await;
+        wait_for(&jobs, first.id, |job| job.state == JobState::Running).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(jobs.get(second.id).await.unwrap().state, JobState::Queued);
+
+        // Cancelling the running job frees its worker for the queued one
+        jobs.cancel(first.id).await.ok().unwrap();
+        wait_for(&jobs, second.id, |job| job.state == JobState::Running).await;
+
+        jobs.cancel(second.id).await.ok().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn coalesces_submissions_for_an_active_year() {
+        let dir = std::env::temp_dir().join(format!("tile-jobs-{}", Uuid::new_v4()));
+        let jobs = service(&dir, 2);
+
+        let (first, created) = jobs.submit(2024).await;
+        assert!(created);
+        let (again, created) = jobs.submit(2024).await;
+        assert!(!created);
+        assert_eq!(again.id, first.id);
+        assert_eq!(jobs.list().await.len(), 1);
+
+        // A finished year gets a new job
+        jobs.cancel(first.id).await.ok().unwrap();
+        let (next, created) = jobs.submit(2024).await;
+        assert!(created);
+        assert_ne!(next.id, first.id);
+
+        jobs.cancel(next.id).await.ok().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cancels_a_running_job_once() {
+        let dir = std::env::temp_dir().join(format!("tile-jobs-{}", Uuid::new_v4()));
+        let jobs = service(&dir, 1);
+
+        let (job, _) = jobs.submit(2024).await;
+        wait_for(&jobs, job.id, |job| job.stdout.contains("MODIS")).await;
+
+        let cancelled = jobs.cancel(job.id).await.ok().unwrap();
+        assert_eq!(cancelled.state, JobState::Cancelled);
+        assert!(cancelled.finished_at.is_some());
+        assert!(matches!(
+            jobs.cancel(job.id).await,
+            Err(CancelError::AlreadyFinished(JobState::Cancelled))
+        ));
+        assert!(matches!(jobs.cancel(Uuid::new_v4()).await, Err(CancelError::NotFound)));
+
+        // The killed generator writes nothing more
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let job = jobs.get(job.id).await.unwrap();
+        assert_eq!(job.state, JobState::Cancelled);
+        assert!(!job.stdout.contains("ERA5"), "{}", job.stdout);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,444 @@
+// src/services/tile_seeder.rs
+//
+// Warms the tile cache for a region before it is needed, e.g. ahead of a
+// demo. The XYZ tiles covering a bounding box over a zoom range are pulled
+// through `TileCache` with bounded concurrency and retries. Tiles already in
+// the cache are skipped, so re-running an interrupted seed only fetches what
+// is still missing.
+use crate::config::TilesConfig;
+use crate::models::tile_config::TileLayer;
+use crate::services::tile_cache::{TileCache, TileError, TileKey, MAX_LATITUDE};
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Upper bound on tiles in one seed; zoom 12 over a country is already ~10k
+const MAX_SEED_TILES: u64 = 100_000;
+
+/// Completed runs kept for `GET /tiles/seed`, oldest dropped first
+const FINISHED_SEEDS_RETAINED: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeedRequest {
+    pub layer: TileLayer,
+    pub year: i32,
+    /// `[west, south, east, north]` in degrees
+    pub bbox: [f64; 4],
+    pub min_zoom: u32,
+    pub max_zoom: u32,
+}
+
+impl SeedRequest {
+    /// Every tile covering the bbox from `min_zoom` to `max_zoom`.
+    pub fn tiles(&self) -> Result<Vec<TileKey>, String> {
+        let [west, south, east, north] = self.bbox;
+        if !(-180.0..=180.0).contains(&west) || !(-180.0..=180.0).contains(&east) {
+            return Err("bbox longitudes must be within -180..180".to_string());
+        }
+        if !(-90.0..=90.0).contains(&south) || !(-90.0..=90.0).contains(&north) {
+            return Err("bbox latitudes must be within -90..90".to_string());
+        }
+        if west >= east || south >= north {
+            return Err("bbox must be [west, south, east, north] with west < east and south < north".to_string());
+        }
+        if self.min_zoom > self.max_zoom {
+            return Err("min_zoom must not be greater than max_zoom".to_string());
+        }
+        // Validates the zoom against the proxy's limit
+        TileKey::new(self.layer, self.year, self.max_zoom, 0, 0).map_err(|e| e.to_string())?;
+
+        let ranges: Vec<_> = (self.min_zoom..=self.max_zoom)
+            .map(|z| {
+                let (x0, y0) = tile_at(west, north, z);
+                let (x1, y1) = tile_at(east, south, z);
+                (z, x0..=x1, y0..=y1)
+            })
+            .collect();
+
+        let total: u64 = ranges
+            .iter()
+            .map(|(_, xs, ys)| xs.clone().count() as u64 * ys.clone().count() as u64)
+            .sum();
+        if total > MAX_SEED_TILES {
+            return Err(format!(
+                "{} tiles requested; narrow the bbox or zoom range (limit {})",
+                total, MAX_SEED_TILES
+            ));
+        }
+
+        let mut tiles = Vec::with_capacity(total as usize);
+        for (z, xs, ys) in ranges {
+            for x in xs {
+                for y in ys.clone() {
+                    tiles.push(TileKey::new(self.layer, self.year, z, x, y).map_err(|e| e.to_string())?);
+                }
+            }
+        }
+        Ok(tiles)
+    }
+}
+
+/// XYZ tile containing a point at zoom `z`
+fn tile_at(lon: f64, lat: f64, z: u32) -> (u32, u32) {
+    let n = (1u64 << z) as f64;
+    let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    let x = (lon + 180.0) / 360.0 * n;
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * n;
+    let max = n - 1.0;
+    (x.floor().clamp(0.0, max) as u32, y.floor().clamp(0.0, max) as u32)
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct SeedTotals {
+    pub total: usize,
+    /// Downloaded during this run
+    pub fetched: usize,
+    /// Already in the cache and skipped
+    pub cached: usize,
+    pub failed: usize,
+}
+
+impl SeedTotals {
+    pub fn done(&self) -> usize {
+        self.fetched + self.cached + self.failed
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeedState {
+    Running,
+    Completed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SeedRun {
+    pub id: Uuid,
+    pub request: SeedRequest,
+    pub state: SeedState,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub totals: SeedTotals,
+}
+
+pub struct TileSeeder {
+    cache: Arc<TileCache>,
+    concurrency: usize,
+    retries: u32,
+    runs: RwLock<HashMap<Uuid, SeedRun>>,
+}
+
+impl TileSeeder {
+    pub fn new(config: &TilesConfig, cache: Arc<TileCache>) -> Self {
+        Self {
+            cache,
+            concurrency: config.seed_concurrency,
+            retries: config.seed_retries,
+            runs: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// The tiles of a valid request for a year that has a tile config.
+    pub async fn plan(&self, request: &SeedRequest) -> Result<Vec<TileKey>, String> {
+        let tiles = request.tiles()?;
+        if !self.cache.is_configured(request.year).await {
+            return Err(TileError::NotConfigured(request.year).to_string());
+        }
+        Ok(tiles)
+    }
+
+    /// Validate the request and seed in the background; progress is readable
+    /// through `get` while it runs.
+    pub async fn start(self: &Arc<Self>, request: SeedRequest) -> Result<SeedRun, String> {
+        let tiles = self.plan(&request).await?;
+        let run = SeedRun {
+            id: Uuid::new_v4(),
+            request,
+            state: SeedState::Running,
+            started_at: Utc::now(),
+            finished_at: None,
+            totals: SeedTotals {
+                total: tiles.len(),
+                ..SeedTotals::default()
+            },
+        };
+        {
+            let mut runs = self.runs.write().unwrap();
+            runs.insert(run.id, run.clone());
+            prune_finished(&mut runs);
+        }
+
+        let seeder = self.clone();
+        let id = run.id;
+        tokio::spawn(async move {
+            let totals = seeder
+                .seed(tiles, |totals| {
+                    if let Some(run) = seeder.runs.write().unwrap().get_mut(&id) {
+                        run.totals = totals;
+                    }
+                })
+                .await;
+
+            if let Some(run) = seeder.runs.write().unwrap().get_mut(&id) {
+                run.totals = totals;
+                run.state = SeedState::Completed;
+                run.finished_at = Some(Utc::now());
+            }
+            println!(
+                "Seed {} finished: {} fetched, {} cached, {} failed",
+                id, totals.fetched, totals.cached, totals.failed
+            );
+        });
+
+        Ok(run)
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<SeedRun> {
+        self.runs.read().unwrap().get(&id).cloned()
+    }
+
+    /// All seed runs, newest first
+    pub fn list(&self) -> Vec<SeedRun> {
+        let mut runs: Vec<SeedRun> = self.runs.read().unwrap().values().cloned().collect();
+        runs.sort_by_key(|run| std::cmp::Reverse(run.started_at));
+        runs
+    }
+
+    /// Pull `tiles` into the cache, calling `on_progress` after each tile.
+    pub async fn seed(&self, tiles: Vec<TileKey>, on_progress: impl Fn(SeedTotals)) -> SeedTotals {
+        let mut totals = SeedTotals {
+            total: tiles.len(),
+            ..SeedTotals::default()
+        };
+
+        let mut results = stream::iter(tiles)
+            .map(|key| self.seed_tile(key))
+            .buffer_unordered(self.concurrency);
+
+        while let Some(outcome) = results.next().await {
+            match outcome {
+                Ok(true) => totals.cached += 1,
+                Ok(false) => totals.fetched += 1,
+                Err((key, e)) => {
+                    totals.failed += 1;
+                    eprintln!("Failed to seed tile {}/{}/{}: {}", key.z, key.x, key.y, e);
+                }
+            }
+            on_progress(totals);
+        }
+        totals
+    }
+
+    /// `Ok(true)` if the tile was already cached, `Ok(false)` once fetched.
+    async fn seed_tile(&self, key: TileKey) -> Result<bool, (TileKey, TileError)> {
+        if self.cache.is_cached(&key).await {
+            return Ok(true);
+        }
+
+        let mut attempt = 0;
+        loop {
+            match self.cache.get_tile(&key).await {
+                Ok(_) => return Ok(false),
+                Err(TileError::Upstream(_)) if attempt < self.retries => {
+                    attempt += 1;
+                    let backoff = 2u64.saturating_pow(attempt - 1).saturating_mul(500);
+                    tokio::time::sleep(Duration::from_millis(backoff)).await;
+                }
+                Err(e) => return Err((key, e)),
+            }
+        }
+    }
+}
+
+fn prune_finished(runs: &mut HashMap<Uuid, SeedRun>) {
+    let mut finished: Vec<(DateTime<Utc>, Uuid)> = runs
+        .values()
+        .filter(|run| run.state == SeedState::Completed)
+        .map(|run| (run.started_at, run.id))
+        .collect();
+
+    if finished.len() > FINISHED_SEEDS_RETAINED {
+        finished.sort();
+        let excess = finished.len() - FINISHED_SEEDS_RETAINED;
+        for (_, id) in finished.into_iter().take(excess) {
+            runs.remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::tile_config::TileConfig;
+    use crate::services::grid_renderer::GridRenderer;
+    use crate::services::tile_config_service::TileConfigService;
+    use crate::services::vis_registry::VisRegistry;
+    use axum::{
+        extract::{Path as AxumPath, State},
+        http::StatusCode,
+        routing::get,
+        Router,
+    };
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use tokio::net::TcpListener;
+
+    fn request(bbox: [f64; 4], min_zoom: u32, max_zoom: u32) -> SeedRequest {
+        SeedRequest {
+            layer: TileLayer::Lst,
+            year: 2024,
+            bbox,
+            min_zoom,
+            max_zoom,
+        }
+    }
+
+    #[test]
+    fn enumerates_tiles_covering_the_bbox() {
+        let world = request([-180.0, -90.0, 180.0, 90.0], 0, 2).tiles().unwrap();
+        assert_eq!(world.len(), 1 + 4 + 16);
+
+        // Lahore sits in tile 5/22/13
+        let lahore = request([74.2, 31.4, 74.5, 31.6], 5, 5).tiles().unwrap();
+        assert_eq!(
+            lahore.iter().map(|key| (key.z, key.x, key.y)).collect::<Vec<_>>(),
+            vec![(5, 22, 13)]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_requests() {
+        assert!(request([10.0, 0.0, 5.0, 10.0], 0, 1).tiles().is_err());
+        assert!(request([0.0, 0.0, 10.0, 10.0], 3, 2).tiles().is_err());
+        assert!(request([-180.0, -90.0, 180.0, 90.0], 0, 12).tiles().is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_years_without_a_tile_config() {
+        let dir = std::env::temp_dir().join(format!("tile-seeder-{}", Uuid::new_v4()));
+        let config = TilesConfig {
+            cache_dir: dir.join("cache"),
+            ..TilesConfig::default()
+        };
+        let cache = TileCache::new(
+            &config,
+            Arc::new(TileConfigService::new(&dir.join("configs"))),
+            Arc::new(GridRenderer::with_limit(&dir.join("grids"), u64::MAX)),
+            Arc::new(VisRegistry::builtin()),
+        );
+        let seeder = Arc::new(TileSeeder::new(&config, Arc::new(cache)));
+
+        let error = seeder.start(request([74.0, 31.0, 75.0, 32.0], 0, 3)).await.unwrap_err();
+        assert!(error.contains("2024"), "{}", error);
+        assert!(seeder.list().is_empty());
+    }
+
+    /// Stand-in tile server. The first `transient` requests answer 503, and
+    /// tile 1/1/1 keeps failing while `broken` is set.
+    #[derive(Clone, Default)]
+    struct Stub {
+        requests: Arc<AtomicUsize>,
+        in_flight: Arc<AtomicUsize>,
+        max_in_flight: Arc<AtomicUsize>,
+        transient: Arc<AtomicUsize>,
+        broken: Arc<AtomicBool>,
+    }
+
+    async fn spawn_stub(stub: Stub) -> String {
+        async fn tile(State(stub): State<Stub>, AxumPath((z, x, y)): AxumPath<(u32, u32, u32)>) -> (StatusCode, String) {
+            stub.requests.fetch_add(1, Ordering::SeqCst);
+            let in_flight = stub.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            stub.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            stub.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            if (z, x, y) == (1, 1, 1) && stub.broken.load(Ordering::SeqCst) {
+                return (StatusCode::INTERNAL_SERVER_ERROR, String::new());
+            }
+            let transient = stub
+                .transient
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1))
+                .is_ok();
+            if transient {
+                return (StatusCode::SERVICE_UNAVAILABLE, String::new());
+            }
+            (StatusCode::OK, format!("{}/{}/{}", z, x, y))
+        }
+
+        let app = Router::new().route("/lst/{z}/{x}/{y}", get(tile)).with_state(stub);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        base
+    }
+
+    fn tile_config(base: &str, map_id: &str) -> TileConfig {
+        let url = format!("{}/lst/{{z}}/{{x}}/{{y}}?map={}", base, map_id);
+        TileConfig {
+            lst_tile_url: url.clone(),
+            anomaly_tile_url: url.clone(),
+            absolute_anomaly_tile_url: url,
+            generated_at: None,
+            target_period: None,
+            climatology_period: None,
+            data_source: None,
+            description: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn seeds_with_bounded_concurrency_retries_and_resume() {
+        let stub = Stub::default();
+        stub.transient.store(2, Ordering::SeqCst);
+        stub.broken.store(true, Ordering::SeqCst);
+        let base = spawn_stub(stub.clone()).await;
+
+        let dir = std::env::temp_dir().join(format!("tile-seeder-{}", Uuid::new_v4()));
+        let config = TilesConfig {
+            cache_dir: dir.join("cache"),
+            seed_concurrency: 2,
+            seed_retries: 1,
+            ..TilesConfig::default()
+        };
+        let configs = Arc::new(TileConfigService::new(&dir.join("configs")));
+        configs.save_config_for_year(2024, tile_config(&base, "first")).await.unwrap();
+        let cache = Arc::new(TileCache::new(
+            &config,
+            configs.clone(),
+            Arc::new(GridRenderer::with_limit(&dir.join("grids"), u64::MAX)),
+            Arc::new(VisRegistry::builtin()),
+        ));
+        let seeder = TileSeeder::new(&config, cache);
+        // Zooms 0 and 1 over the world: 0/0/0 and the four zoom-1 tiles
+        let tiles = || request([-180.0, -90.0, 180.0, 90.0], 0, 1).tiles().unwrap();
+
+        let progress = Mutex::new(Vec::new());
+        let totals = seeder.seed(tiles(), |totals| progress.lock().unwrap().push(totals)).await;
+        assert_eq!((totals.total, totals.fetched, totals.cached, totals.failed), (5, 4, 0, 1));
+        // Two 503s retried once each, and 1/1/1 tried twice
+        assert_eq!(stub.requests.load(Ordering::SeqCst), 4 + 2 + 2);
+        assert_eq!(stub.max_in_flight.load(Ordering::SeqCst), 2);
+        let progress = progress.into_inner().unwrap();
+        assert_eq!(progress.iter().map(SeedTotals::done).collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+        assert_eq!(progress.last().map(|last| last.failed), Some(1));
+
+        // Re-running fetches only the tile that failed
+        stub.broken.store(false, Ordering::SeqCst);
+        let totals = seeder.seed(tiles(), |_| {}).await;
+        assert_eq!((totals.fetched, totals.cached, totals.failed), (1, 4, 0));
+        assert_eq!(stub.requests.load(Ordering::SeqCst), 9);
+
+        // A refreshed map URL for the same content keeps the seeded region
+        configs.save_config_for_year(2024, tile_config(&base, "refreshed")).await.unwrap();
+        let totals = seeder.seed(tiles(), |_| {}).await;
+        assert_eq!((totals.fetched, totals.cached, totals.failed), (0, 5, 0));
+        assert_eq!(stub.requests.load(Ordering::SeqCst), 9);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,513 @@
+// src/services/timeseries.rs
+//
+// Monthly or yearly temperature history at a coordinate, with anomalies
+// against the 2000–2025 climatology of the same variable and an OLS trend.
+// Earth Engine reduces the daily images to monthly means at the point, one
+// year (or the whole climatology) per request. Completed years and the
+// climatology never change and the current year is refetched after a few
+// hours; both caches are bounded like `PointSampler`'s.
+use crate::services::earthengine::EarthEngineService;
+use crate::services::gee_composites::{self, Dataset, CLIMATOLOGY_END, CLIMATOLOGY_START, ERA5, FIRST_YEAR, MODIS};
+use crate::services::point_sampler::RoundedCoordinate;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, RwLock};
+
+/// Years fetched from Earth Engine at once
+const YEAR_FETCH_CONCURRENCY: usize = 4;
+
+/// Two-sided p-value below which a trend is reported as significant
+const SIGNIFICANCE_LEVEL: f64 = 0.05;
+
+/// Cached years (and climatologies) before the oldest are dropped
+const MAX_CACHED_SERIES: usize = 10_000;
+
+/// The current year's months change as new days arrive
+const CURRENT_YEAR_TTL_HOURS: i64 = 6;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Variable {
+    /// MODIS daytime land surface temperature
+    #[default]
+    Lst,
+    /// ERA5-Land 2 m air temperature
+    Era5T2m,
+}
+
+impl Variable {
+    fn dataset(self) -> Dataset {
+        match self {
+            Variable::Lst => MODIS,
+            Variable::Era5T2m => ERA5,
+        }
+    }
+
+    pub fn first_year(self) -> i32 {
+        match self {
+            Variable::Lst => FIRST_YEAR,
+            Variable::Era5T2m => 1950,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregation {
+    Monthly,
+    #[default]
+    Yearly,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SeriesPoint {
+    /// `2024` or `2024-07`
+    pub period: Period,
+    pub value: f64,
+    /// Climatology mean of the same month(s)
+    pub climatology: Option<f64>,
+    pub anomaly: Option<f64>,
+    /// Days with data behind the value
+    pub days: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period {
+    pub year: i32,
+    pub month: Option<u32>,
+}
+
+impl Period {
+    /// Decimal year at the middle of the period, the regression's x
+    fn midpoint(&self) -> f64 {
+        match self.month {
+            Some(month) => self.year as f64 + (month as f64 - 0.5) / 12.0,
+            None => self.year as f64 + 0.5,
+        }
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.month {
+            Some(month) => write!(f, "{}-{:02}", self.year, month),
+            None => write!(f, "{}", self.year),
+        }
+    }
+}
+
+impl Serialize for Period {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Ordinary least squares fit of the anomalies over time
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Trend {
+    pub per_decade: f64,
+    pub std_error_per_decade: f64,
+    /// Two-sided p-value of the slope being zero (Student's t)
+    pub p_value: f64,
+    pub significant: bool,
+    pub points: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimeSeries {
+    pub lat: f64,
+    pub lng: f64,
+    pub variable: Variable,
+    pub aggregation: Aggregation,
+    pub units: &'static str,
+    pub climatology_period: String,
+    pub series: Vec<SeriesPoint>,
+    /// `None` with fewer than three points
+    pub trend: Option<Trend>,
+}
+
+#[derive(Debug)]
+pub enum TimeSeriesError {
+    InvalidRange(String),
+    Unavailable(String),
+}
+
+impl fmt::Display for TimeSeriesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeSeriesError::InvalidRange(e) | TimeSeriesError::Unavailable(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Mean and day count of one calendar month
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MonthMean {
+    mean: f64,
+    days: u32,
+}
+
+type Months = [Option<MonthMean>; 12];
+
+#[derive(Clone, Copy)]
+struct Cached {
+    months: Months,
+    fetched_at: DateTime<Utc>,
+}
+
+pub struct TimeSeriesService {
+    earth_engine: Arc<EarthEngineService>,
+    years: RwLock<HashMap<(Variable, RoundedCoordinate, i32), Cached>>,
+    climatologies: RwLock<HashMap<(Variable, RoundedCoordinate), Cached>>,
+}
+
+impl TimeSeriesService {
+    pub fn new(earth_engine: Arc<EarthEngineService>) -> Self {
+        Self {
+            earth_engine,
+            years: RwLock::new(HashMap::new()),
+            climatologies: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub async fn series(
+        &self,
+        lat: f64,
+        lng: f64,
+        from: i32,
+        to: i32,
+        variable: Variable,
+        aggregation: Aggregation,
+    ) -> Result<TimeSeries, TimeSeriesError> {
+        let current_year = Utc::now().year();
+        if from > to {
+            return Err(TimeSeriesError::InvalidRange("from must not be after to".to_string()));
+        }
+        if from < variable.first_year() || to > current_year {
+            return Err(TimeSeriesError::InvalidRange(format!(
+                "years must be within {}..={}",
+                variable.first_year(),
+                current_year
+            )));
+        }
+
+        let coordinate = RoundedCoordinate::new(lat, lng);
+        let climatology = self.climatology(variable, coordinate).await?;
+        let years: Vec<(i32, Months)> = stream::iter(from..=to)
+            .map(|year| async move { Ok((year, self.year(variable, coordinate, year).await?)) })
+            .buffered(YEAR_FETCH_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        let series = match aggregation {
+            Aggregation::Monthly => monthly_series(&years, &climatology),
+            Aggregation::Yearly => yearly_series(&years, &climatology),
+        };
+        let trend = linear_trend(
+            &series
+                .iter()
+                .filter_map(|point| Some((point.period.midpoint(), point.anomaly?)))
+                .collect::<Vec<_>>(),
+        );
+
+        Ok(TimeSeries {
+            lat: coordinate.lat(),
+            lng: coordinate.lng(),
+            variable,
+            aggregation,
+            units: "°C",
+            climatology_period: gee_composites::climatology_period(),
+            series,
+            trend,
+        })
+    }
+
+    async fn year(&self, variable: Variable, coordinate: RoundedCoordinate, year: i32) -> Result<Months, TimeSeriesError> {
+        let key = (variable, coordinate, year);
+        let cached = self.years.read().unwrap().get(&key).copied();
+        if let Some(cached) = cached {
+            let stale = year == Utc::now().year()
+                && Utc::now() - cached.fetched_at > Duration::hours(CURRENT_YEAR_TTL_HOURS);
+            if !stale {
+                return Ok(cached.months);
+            }
+        }
+
+        let start = NaiveDate::from_ymd_opt(year, 1, 1).expect("valid year");
+        let end = NaiveDate::from_ymd_opt(year + 1, 1, 1).expect("valid year");
+        let months = self.monthly_means(variable, coordinate, start, end).await?;
+        insert(&self.years, key, months);
+        Ok(months)
+    }
+
+    async fn climatology(&self, variable: Variable, coordinate: RoundedCoordinate) -> Result<Months, TimeSeriesError> {
+        let key = (variable, coordinate);
+        if let Some(cached) = self.climatologies.read().unwrap().get(&key) {
+            return Ok(cached.months);
+        }
+
+        let start = CLIMATOLOGY_START.parse().expect("valid climatology start");
+        let end = CLIMATOLOGY_END.parse().expect("valid climatology end");
+        let months = self.monthly_means(variable, coordinate, start, end).await?;
+        insert(&self.climatologies, key, months);
+        Ok(months)
+    }
+
+    async fn monthly_means(
+        &self,
+        variable: Variable,
+        coordinate: RoundedCoordinate,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Months, TimeSeriesError> {
+        let months = self
+            .earth_engine
+            .monthly_point_means(variable.dataset(), start, end, coordinate.lat(), coordinate.lng())
+            .await
+            .map_err(|e| TimeSeriesError::Unavailable(format!("{:#}", e)))?;
+        Ok(months.map(|month| month.map(|(mean, days)| MonthMean { mean, days })))
+    }
+}
+
+/// Cache `months`, dropping the oldest entry when the cache is full.
+fn insert<K: Copy + Eq + Hash>(cache: &RwLock<HashMap<K, Cached>>, key: K, months: Months) {
+    let mut cache = cache.write().unwrap();
+    if cache.len() >= MAX_CACHED_SERIES && !cache.contains_key(&key) {
+        let oldest = cache
+            .iter()
+            .min_by_key(|(_, cached)| cached.fetched_at)
+            .map(|(key, _)| *key);
+        if let Some(oldest) = oldest {
+            cache.remove(&oldest);
+        }
+    }
+    cache.insert(
+        key,
+        Cached {
+            months,
+            fetched_at: Utc::now(),
+        },
+    );
+}
+
+fn monthly_series(years: &[(i32, Months)], climatology: &Months) -> Vec<SeriesPoint> {
+    let mut series = Vec::new();
+    for (year, months) in years {
+        for (month0, month) in months.iter().enumerate() {
+            let Some(month) = month else { continue };
+            let normal = climatology[month0].map(|normal| normal.mean);
+            series.push(SeriesPoint {
+                period: Period {
+                    year: *year,
+                    month: Some(month0 as u32 + 1),
+                },
+                value: month.mean,
+                climatology: normal,
+                anomaly: normal.map(|normal| month.mean - normal),
+                days: month.days,
+            });
+        }
+    }
+    series
+}
+
+/// A year's value is the mean of its monthly means, as in the yearly
+/// composites, compared with the climatology of the same months so a
+/// year to date is not measured against a full year.
+fn yearly_series(years: &[(i32, Months)], climatology: &Months) -> Vec<SeriesPoint> {
+    years
+        .iter()
+        .filter_map(|(year, months)| {
+            let present: Vec<(usize, MonthMean)> = months
+                .iter()
+                .enumerate()
+                .filter_map(|(month0, month)| Some((month0, (*month)?)))
+                .collect();
+            if present.is_empty() {
+                return None;
+            }
+
+            let value = present.iter().map(|(_, month)| month.mean).sum::<f64>() / present.len() as f64;
+            let normals: Option<Vec<f64>> = present
+                .iter()
+                .map(|(month0, _)| climatology[*month0].map(|normal| normal.mean))
+                .collect();
+            let normal = normals.map(|normals| normals.iter().sum::<f64>() / normals.len() as f64);
+
+            Some(SeriesPoint {
+                period: Period {
+                    year: *year,
+                    month: None,
+                },
+                value,
+                climatology: normal,
+                anomaly: normal.map(|normal| value - normal),
+                days: present.iter().map(|(_, month)| month.days).sum(),
+            })
+        })
+        .collect()
+}
+
+// -------------------- Trend --------------------
+/// OLS slope of `(decimal year, °C)` points with its standard error and
+/// t-test p-value.
+fn linear_trend(points: &[(f64, f64)]) -> Option<Trend> {
+    let n = points.len();
+    if n < 3 {
+        return None;
+    }
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n as f64;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n as f64;
+    let sxx: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+
+    let slope = sxy / sxx;
+    let intercept = mean_y - slope * mean_x;
+    let residuals: f64 = points
+        .iter()
+        .map(|(x, y)| (y - intercept - slope * x).powi(2))
+        .sum();
+    let df = (n - 2) as f64;
+    let std_error = (residuals / df / sxx).sqrt();
+    let p_value = if std_error == 0.0 {
+        0.0
+    } else {
+        student_t_p_value(slope / std_error, df)
+    };
+
+    Some(Trend {
+        per_decade: slope * 10.0,
+        std_error_per_decade: std_error * 10.0,
+        p_value,
+        significant: p_value < SIGNIFICANCE_LEVEL,
+        points: n,
+    })
+}
+
+/// Two-sided p-value of `t` with `df` degrees of freedom.
+fn student_t_p_value(t: f64, df: f64) -> f64 {
+    regularized_incomplete_beta(df / (df + t * t), df / 2.0, 0.5)
+}
+
+/// I_x(a, b) by its continued fraction (Numerical Recipes `betai`).
+fn regularized_incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_continued_fraction(1.0 - x, b, a) / b
+    }
+}
+
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+
+    for m in 1..=300 {
+        let m = m as f64;
+        for numerator in [
+            m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0)),
+        ] {
+            d = 1.0 + numerator * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1.0 + numerator / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            h *= d * c;
+        }
+        if (d * c - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    h
+}
+
+/// Lanczos approximation of ln Γ(x) for x > 0.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.120_865_097_386_617_9e-2,
+        -0.539_523_938_495_3e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000_000_000_190_015, |sum, (i, c)| sum + c / (x + 1.0 + i as f64));
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_trend_with_t_test() {
+        // 0.3 °C/decade plus noise that is uncorrelated with time
+        let points: Vec<(f64, f64)> = (0..20)
+            .map(|i| (2000.0 + i as f64, 0.03 * i as f64 + [0.1, -0.1, -0.1, 0.1][i % 4]))
+            .collect();
+        let trend = linear_trend(&points).unwrap();
+        assert!((trend.per_decade - 0.3).abs() < 1e-9, "{:?}", trend);
+        assert!(trend.significant && trend.p_value < 1e-6);
+
+        let flat: Vec<(f64, f64)> = (0..10).map(|i| (i as f64, [0.5, -0.5][i % 2])).collect();
+        assert!(!linear_trend(&flat).unwrap().significant);
+        assert!(linear_trend(&points[..2]).is_none());
+
+        // Critical value of t with 10 degrees of freedom at the 5% level
+        assert!((student_t_p_value(2.228, 10.0) - 0.05).abs() < 1e-3);
+    }
+
+    /// `(month, mean, days)` per month with data
+    fn months(values: &[(usize, f64, u32)]) -> Months {
+        let mut months = [None; 12];
+        for &(month, mean, days) in values {
+            months[month - 1] = Some(MonthMean { mean, days });
+        }
+        months
+    }
+
+    #[test]
+    fn yearly_values_compare_against_the_same_months() {
+        let climatology = months(&[(1, 10.0, 1), (2, 20.0, 1), (3, 30.0, 1)]);
+        // A year to date with January and February only
+        let months = months(&[(1, 12.0, 2), (2, 21.0, 1)]);
+
+        let yearly = yearly_series(&[(2025, months)], &climatology);
+        assert_eq!(yearly.len(), 1);
+        assert_eq!((yearly[0].value, yearly[0].climatology, yearly[0].days), (16.5, Some(15.0), 3));
+        assert_eq!(yearly[0].anomaly, Some(1.5));
+
+        let monthly = monthly_series(&[(2025, months)], &climatology);
+        assert_eq!(monthly[0].period.to_string(), "2025-01");
+        assert_eq!(monthly[1].anomaly, Some(1.0));
+    }
+}
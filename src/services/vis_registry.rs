@@ -0,0 +1,212 @@
+// src/services/vis_registry.rs
+//
+// Named palettes and per-layer visualization parameters from `gee/viz.json`.
+// Earth Engine map generation, local `.npy` rendering and the legend endpoint
+// all read them from here, so a map and its legend always agree. The Python
+// generator reads the same file.
+use crate::models::tile_config::TileLayer;
+use crate::services::gee_expression::VisParams;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Copy of `gee/viz.json` compiled in, used when no file is configured
+const BUILTIN_VIZ: &str = include_str!("../../gee/viz.json");
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VizFile {
+    palettes: BTreeMap<String, Vec<String>>,
+    layers: BTreeMap<String, LayerEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayerEntry {
+    title: String,
+    units: String,
+    min: f64,
+    max: f64,
+    /// Name of an entry in `palettes`
+    palette: String,
+}
+
+/// Resolved visualization of one layer
+#[derive(Debug, Clone, Serialize)]
+pub struct LayerVis {
+    pub title: String,
+    pub units: String,
+    pub min: f64,
+    pub max: f64,
+    pub palette_name: String,
+    pub palette: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LegendStop {
+    pub value: f64,
+    pub color: String,
+}
+
+impl LayerVis {
+    pub fn vis_params(&self) -> VisParams {
+        VisParams {
+            min: self.min,
+            max: self.max,
+            palette: self.palette.clone(),
+        }
+    }
+
+    /// One stop per palette colour, evenly spaced from `min` to `max`
+    pub fn stops(&self) -> Vec<LegendStop> {
+        let steps = (self.palette.len() - 1).max(1) as f64;
+        self.palette
+            .iter()
+            .enumerate()
+            .map(|(i, color)| LegendStop {
+                value: self.min + (self.max - self.min) * i as f64 / steps,
+                color: format!("#{}", color),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VisRegistry {
+    layers: BTreeMap<String, LayerVis>,
+}
+
+impl VisRegistry {
+    /// The compiled-in `gee/viz.json`
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN_VIZ).expect("gee/viz.json is valid")
+    }
+
+    /// `path` if it exists, the built-in copy otherwise
+    pub fn load_or_builtin(path: &Path) -> Result<Self> {
+        if path.exists() {
+            Self::load(path)
+        } else {
+            println!("{} not found, using built-in visualization parameters", path.display());
+            Ok(Self::builtin())
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::parse(&raw).with_context(|| format!("invalid {}", path.display()))
+    }
+
+    fn parse(raw: &str) -> Result<Self> {
+        let file: VizFile = serde_json::from_str(raw)?;
+
+        for (name, colors) in &file.palettes {
+            if colors.is_empty() {
+                bail!("palette {:?} is empty", name);
+            }
+            if let Some(color) = colors.iter().find(|color| !is_hex_color(color)) {
+                bail!("palette {:?}: {:?} is not a six-digit hex colour", name, color);
+            }
+        }
+
+        let mut layers = BTreeMap::new();
+        for (name, entry) in file.layers {
+            let Some(palette) = file.palettes.get(&entry.palette) else {
+                bail!("layer {:?} uses unknown palette {:?}", name, entry.palette);
+            };
+            if entry.min >= entry.max {
+                bail!("layer {:?}: min must be below max", name);
+            }
+            layers.insert(
+                name,
+                LayerVis {
+                    title: entry.title,
+                    units: entry.units,
+                    min: entry.min,
+                    max: entry.max,
+                    palette_name: entry.palette,
+                    palette: palette.clone(),
+                },
+            );
+        }
+
+        for layer in TileLayer::ALL {
+            for name in [layer.as_str().to_string(), difference_name(layer)] {
+                if !layers.contains_key(&name) {
+                    bail!("missing layer {:?}", name);
+                }
+            }
+        }
+
+        Ok(Self { layers })
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&LayerVis> {
+        self.layers.get(name)
+    }
+
+    /// Visualization of a tile layer; every tile layer is checked to exist at load.
+    pub fn tile_layer(&self, layer: TileLayer) -> &LayerVis {
+        &self.layers[layer.as_str()]
+    }
+
+    /// Visualization of the change in a tile layer between two years
+    pub fn difference_layer(&self, layer: TileLayer) -> &LayerVis {
+        &self.layers[&difference_name(layer)]
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.layers.keys().map(String::as_str).collect()
+    }
+}
+
+fn difference_name(layer: TileLayer) -> String {
+    format!("{}_difference", layer.as_str())
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color.len() == 6 && color.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_viz_covers_every_tile_layer() {
+        let registry = VisRegistry::builtin();
+        let lst = registry.tile_layer(TileLayer::Lst);
+        assert_eq!((lst.min, lst.max, lst.palette.len()), (-20.0, 50.0, 29));
+
+        let stops = registry.tile_layer(TileLayer::Anomaly).stops();
+        assert_eq!(stops.len(), 3);
+        assert_eq!((stops[1].value, stops[1].color.as_str()), (0.0, "#ffffff"));
+        assert!(registry.layer("z_score").is_some());
+        assert_eq!(registry.difference_layer(TileLayer::Lst).palette_name, "diverging");
+    }
+
+    #[test]
+    fn spaces_legend_stops_evenly() {
+        let layer = |palette: &[&str]| LayerVis {
+            title: String::new(),
+            units: String::new(),
+            min: -10.0,
+            max: 20.0,
+            palette_name: "test".to_string(),
+            palette: palette.iter().map(|color| color.to_string()).collect(),
+        };
+        let values = |layer: LayerVis| layer.stops().iter().map(|stop| stop.value).collect::<Vec<_>>();
+
+        assert_eq!(values(layer(&["000000", "808080", "c0c0c0", "ffffff"])), [-10.0, 0.0, 10.0, 20.0]);
+        // A single colour is one stop at `min`
+        let single = layer(&["ff0000"]).stops();
+        assert_eq!((single.len(), single[0].value, single[0].color.as_str()), (1, -10.0, "#ff0000"));
+    }
+
+    #[test]
+    fn rejects_unknown_palette_references() {
+        let raw = r#"{ "palettes": {}, "layers": { "lst": { "title": "", "units": "", "min": 0, "max": 1, "palette": "nope" } } }"#;
+        assert!(VisRegistry::parse(raw).is_err());
+    }
+}
@@ -0,0 +1,444 @@
+// src/services/weather.rs
+//
+// Station-style weather behind one trait, so `/climate/current` and the
+// heat-alert scheduler agree on what "current temperature" means. Providers
+// are tried in the configured order (`weather.providers`): the first is the
+// primary and each later one is a fallback used only when those before it fail.
+use crate::config::{WeatherConfig, WeatherProviderKind};
+use crate::secrets::{self, Secret};
+use crate::services::open_meteo::OpenMeteoProvider;
+use crate::services::openweathermap::OpenWeatherMapProvider;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio::time::Instant;
+
+/// Conditions observed at a location, temperatures in °C and wind in m/s.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CurrentConditions {
+    pub temperature: f64,
+    /// "Feels like" temperature
+    pub apparent_temperature: Option<f64>,
+    /// Relative humidity in %
+    pub humidity: Option<f64>,
+    pub wind_speed: Option<f64>,
+    /// Direction the wind blows from, degrees clockwise from north
+    pub wind_direction: Option<f64>,
+    /// WMO weather interpretation code (0 clear sky ... 99 thunderstorm with hail)
+    pub weather_code: Option<u8>,
+    pub observed_at: DateTime<Utc>,
+    /// IANA name of the location's timezone, or its UTC offset when the provider gives no name
+    pub timezone: String,
+    pub utc_offset_seconds: i32,
+    /// Name of the provider that answered
+    pub provider: &'static str,
+}
+
+impl CurrentConditions {
+    /// Seconds since the observation, zero for clocks slightly ahead of ours
+    pub fn age_secs(&self, now: DateTime<Utc>) -> i64 {
+        (now - self.observed_at).num_seconds().max(0)
+    }
+}
+
+/// Unit systems for current conditions, as in `?units=`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Units {
+    /// °C and m/s
+    #[default]
+    Metric,
+    /// °F and mph
+    Imperial,
+    /// K and m/s
+    Kelvin,
+}
+
+impl Units {
+    pub fn temperature(self, celsius: f64) -> f64 {
+        match self {
+            Units::Metric => celsius,
+            Units::Imperial => celsius * 9.0 / 5.0 + 32.0,
+            Units::Kelvin => celsius + 273.15,
+        }
+    }
+
+    pub fn wind_speed(self, metres_per_second: f64) -> f64 {
+        match self {
+            Units::Imperial => metres_per_second * 3600.0 / 1609.344,
+            Units::Metric | Units::Kelvin => metres_per_second,
+        }
+    }
+
+    pub fn temperature_unit(self) -> &'static str {
+        match self {
+            Units::Metric => "C",
+            Units::Imperial => "F",
+            Units::Kelvin => "K",
+        }
+    }
+
+    pub fn wind_speed_unit(self) -> &'static str {
+        match self {
+            Units::Imperial => "mph",
+            Units::Metric | Units::Kelvin => "m/s",
+        }
+    }
+}
+
+/// One hourly (or coarser, depending on the provider) temperature in °C.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct HourlyTemperature {
+    pub time: DateTime<Utc>,
+    pub temperature: f64,
+    /// Relative humidity in %, where the provider has it
+    pub humidity: Option<f64>,
+}
+
+/// Hourly temperatures and the provider they came from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HourlySeries {
+    pub provider: &'static str,
+    pub values: Vec<HourlyTemperature>,
+}
+
+#[async_trait]
+pub trait WeatherProvider: Send + Sync {
+    /// Short identifier, as used in `weather.providers`
+    fn name(&self) -> &'static str;
+
+    async fn current(&self, lat: f64, lng: f64) -> anyhow::Result<CurrentConditions>;
+
+    /// Forecast temperatures for the next `hours` hours.
+    async fn hourly_forecast(&self, lat: f64, lng: f64, hours: u32) -> anyhow::Result<HourlySeries>;
+
+    /// Observed hourly temperatures from `start` to `end`, both inclusive (UTC days).
+    async fn historical(&self, lat: f64, lng: f64, start: NaiveDate, end: NaiveDate) -> anyhow::Result<HourlySeries>;
+}
+
+/// Every provider in the chain failed; one message per provider, in order.
+#[derive(Debug)]
+pub struct WeatherError(pub Vec<String>);
+
+impl fmt::Display for WeatherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "no weather provider is enabled");
+        }
+        write!(f, "all weather providers failed: {}", self.0.join("; "))
+    }
+}
+
+/// Primary provider followed by its fallbacks.
+pub struct WeatherChain {
+    providers: Vec<Arc<dyn WeatherProvider>>,
+}
+
+impl WeatherChain {
+    pub fn new(providers: Vec<Arc<dyn WeatherProvider>>) -> Self {
+        Self { providers }
+    }
+
+    /// The chains described by `weather.providers`, each provider held to its
+    /// configured requests per minute. OpenWeatherMap is left out without an API key.
+    ///
+    /// Returns `(background, interactive)`. Both draw on the same per-provider
+    /// caps, but the interactive chain skips a provider whose next free slot is
+    /// more than `weather.interactive_max_wait_ms` away instead of queueing.
+    pub fn from_config(config: &WeatherConfig, openweathermap_api_key: Option<Secret>) -> (Self, Self) {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(config.timeout_secs))
+            .build()
+            .expect("static reqwest client configuration");
+        let max_wait = std::time::Duration::from_millis(config.interactive_max_wait_ms);
+        let (background, interactive) = config
+            .providers
+            .iter()
+            .filter_map(|kind| -> Option<(Arc<dyn WeatherProvider>, Arc<dyn WeatherProvider>)> {
+                let (provider, per_minute): (Arc<dyn WeatherProvider>, u32) = match kind {
+                    WeatherProviderKind::OpenMeteo => {
+                        (Arc::new(OpenMeteoProvider::new(client.clone())), config.open_meteo_per_minute)
+                    }
+                    WeatherProviderKind::OpenWeatherMap => (
+                        Arc::new(OpenWeatherMapProvider::new(client.clone(), openweathermap_api_key.clone()?)),
+                        config.openweathermap_per_minute,
+                    ),
+                    WeatherProviderKind::Mock => {
+                        let mock: Arc<dyn WeatherProvider> = Arc::new(MockProvider::new(config.mock_temperature));
+                        return Some((mock.clone(), mock));
+                    }
+                };
+                let limiter = Arc::new(RateLimiter::per_minute(per_minute));
+                Some((
+                    Arc::new(RateLimited::new(provider.clone(), limiter.clone(), None)),
+                    Arc::new(RateLimited::new(provider, limiter, Some(max_wait))),
+                ))
+            })
+            .unzip();
+        (Self::new(background), Self::new(interactive))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.providers.iter().map(|provider| provider.name()).collect()
+    }
+
+    pub async fn current(&self, lat: f64, lng: f64) -> Result<CurrentConditions, WeatherError> {
+        self.first_success(|provider| provider.current(lat, lng)).await
+    }
+
+    pub async fn hourly_forecast(&self, lat: f64, lng: f64, hours: u32) -> Result<HourlySeries, WeatherError> {
+        self.first_success(|provider| provider.hourly_forecast(lat, lng, hours)).await
+    }
+
+    pub async fn historical(&self, lat: f64, lng: f64, start: NaiveDate, end: NaiveDate) -> Result<HourlySeries, WeatherError> {
+        self.first_success(|provider| provider.historical(lat, lng, start, end)).await
+    }
+
+    async fn first_success<'a, T>(
+        &'a self,
+        call: impl Fn(&'a dyn WeatherProvider) -> BoxFuture<'a, anyhow::Result<T>>,
+    ) -> Result<T, WeatherError> {
+        let mut failures = Vec::new();
+        for provider in &self.providers {
+            match call(provider.as_ref()).await {
+                Ok(value) => return Ok(value),
+                Err(e) => failures.push(secrets::redact(&format!("{}: {:#}", provider.name(), e))),
+            }
+        }
+        Err(WeatherError(failures))
+    }
+}
+
+// -------------------- Rate Limiting --------------------
+/// Spaces calls evenly so no more than `per_minute` start in any minute.
+pub struct RateLimiter {
+    interval: std::time::Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn per_minute(per_minute: u32) -> Self {
+        Self {
+            interval: std::time::Duration::from_secs(60) / per_minute,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for the next free slot.
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+
+    /// Wait for the next free slot if it comes within `max_wait`; otherwise
+    /// leave the queue untouched and return how long the wait would be.
+    pub async fn acquire_within(&self, max_wait: std::time::Duration) -> Result<(), std::time::Duration> {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let slot = (*next).max(now);
+            if slot > now + max_wait {
+                return Err(slot - now);
+            }
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+        Ok(())
+    }
+}
+
+/// A provider whose calls, from every caller, share one rate limit. With
+/// `max_wait` a call fails rather than wait longer, so the chain moves on.
+struct RateLimited {
+    provider: Arc<dyn WeatherProvider>,
+    limiter: Arc<RateLimiter>,
+    max_wait: Option<std::time::Duration>,
+}
+
+impl RateLimited {
+    fn new(provider: Arc<dyn WeatherProvider>, limiter: Arc<RateLimiter>, max_wait: Option<std::time::Duration>) -> Self {
+        Self {
+            provider,
+            limiter,
+            max_wait,
+        }
+    }
+
+    async fn acquire(&self) -> anyhow::Result<()> {
+        match self.max_wait {
+            None => self.limiter.acquire().await,
+            Some(max_wait) => {
+                if let Err(wait) = self.limiter.acquire_within(max_wait).await {
+                    anyhow::bail!("rate limit reached, next request in {} ms", wait.as_millis());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for RateLimited {
+    fn name(&self) -> &'static str {
+        self.provider.name()
+    }
+
+    async fn current(&self, lat: f64, lng: f64) -> anyhow::Result<CurrentConditions> {
+        self.acquire().await?;
+        self.provider.current(lat, lng).await
+    }
+
+    async fn hourly_forecast(&self, lat: f64, lng: f64, hours: u32) -> anyhow::Result<HourlySeries> {
+        self.acquire().await?;
+        self.provider.hourly_forecast(lat, lng, hours).await
+    }
+
+    async fn historical(&self, lat: f64, lng: f64, start: NaiveDate, end: NaiveDate) -> anyhow::Result<HourlySeries> {
+        self.acquire().await?;
+        self.provider.historical(lat, lng, start, end).await
+    }
+}
+
+// -------------------- Mock Provider --------------------
+/// Fixed temperatures, for development without network access and for tests.
+pub struct MockProvider {
+    /// `None` makes every call fail
+    temperature: Option<f64>,
+}
+
+impl MockProvider {
+    pub fn new(temperature: f64) -> Self {
+        Self {
+            temperature: Some(temperature),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn failing() -> Self {
+        Self { temperature: None }
+    }
+
+    fn temperature(&self) -> anyhow::Result<f64> {
+        self.temperature
+            .ok_or_else(|| anyhow::anyhow!("mock provider configured to fail"))
+    }
+
+    fn series(&self, start: DateTime<Utc>, hours: i64) -> anyhow::Result<HourlySeries> {
+        let temperature = self.temperature()?;
+        Ok(HourlySeries {
+            provider: self.name(),
+            values: (0..hours)
+                .map(|hour| HourlyTemperature {
+                    time: start + Duration::hours(hour),
+                    temperature,
+                    humidity: None,
+                })
+                .collect(),
+        })
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for MockProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn current(&self, _lat: f64, _lng: f64) -> anyhow::Result<CurrentConditions> {
+        let temperature = self.temperature()?;
+        Ok(CurrentConditions {
+            temperature,
+            apparent_temperature: Some(temperature),
+            humidity: None,
+            wind_speed: None,
+            wind_direction: None,
+            weather_code: Some(0),
+            observed_at: Utc::now(),
+            timezone: "UTC".to_string(),
+            utc_offset_seconds: 0,
+            provider: self.name(),
+        })
+    }
+
+    async fn hourly_forecast(&self, _lat: f64, _lng: f64, hours: u32) -> anyhow::Result<HourlySeries> {
+        self.series(Utc::now(), hours as i64)
+    }
+
+    async fn historical(&self, _lat: f64, _lng: f64, start: NaiveDate, end: NaiveDate) -> anyhow::Result<HourlySeries> {
+        let days = (end - start).num_days() + 1;
+        self.series(start.and_time(chrono::NaiveTime::MIN).and_utc(), days.max(0) * 24)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn falls_back_in_order_and_reports_every_failure() {
+        let chain = WeatherChain::new(vec![Arc::new(MockProvider::failing()), Arc::new(MockProvider::new(31.5))]);
+        let current = chain.current(33.4, -112.1).await.unwrap();
+        assert_eq!((current.temperature, current.provider), (31.5, "mock"));
+
+        let day = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+        assert_eq!(chain.historical(33.4, -112.1, day, day).await.unwrap().values.len(), 24);
+
+        let chain = WeatherChain::new(vec![Arc::new(MockProvider::failing()), Arc::new(MockProvider::failing())]);
+        let error = chain.hourly_forecast(33.4, -112.1, 6).await.unwrap_err();
+        assert_eq!(error.0.len(), 2);
+    }
+
+    #[test]
+    fn converts_units_and_labels_them() {
+        assert_eq!(Units::Metric.temperature(21.5), 21.5);
+        assert_eq!(Units::Imperial.temperature(40.0), 104.0);
+        assert_eq!(Units::Kelvin.temperature(-273.15), 0.0);
+        assert_eq!(Units::Metric.wind_speed(10.0), 10.0);
+        assert_eq!(Units::Kelvin.wind_speed(10.0), 10.0);
+        assert!((Units::Imperial.wind_speed(10.0) - 22.369).abs() < 1e-3);
+
+        let labels = |units: Units| (units.temperature_unit(), units.wind_speed_unit());
+        assert_eq!(labels(Units::Metric), ("C", "m/s"));
+        assert_eq!(labels(Units::Imperial), ("F", "mph"));
+        assert_eq!(labels(Units::Kelvin), ("K", "m/s"));
+        assert_eq!(serde_json::from_str::<Units>(r#""imperial""#).unwrap(), Units::Imperial);
+    }
+
+    #[tokio::test]
+    async fn measures_the_age_of_an_observation() {
+        let current = MockProvider::new(31.5).current(33.4, -112.1).await.unwrap();
+        let now = current.observed_at + Duration::seconds(95);
+        assert_eq!(current.age_secs(now), 95);
+        // A provider clock ahead of ours does not give a negative age
+        assert_eq!(current.age_secs(current.observed_at - Duration::seconds(5)), 0);
+    }
+
+    #[tokio::test]
+    async fn interactive_calls_skip_a_provider_over_its_cap() {
+        let limiter = Arc::new(RateLimiter::per_minute(1));
+        let primary: Arc<dyn WeatherProvider> = Arc::new(MockProvider::new(31.5));
+        let background = RateLimited::new(primary.clone(), limiter.clone(), None);
+        let interactive = WeatherChain::new(vec![
+            Arc::new(RateLimited::new(primary, limiter, Some(std::time::Duration::from_millis(50)))),
+            Arc::new(MockProvider::new(25.0)),
+        ]);
+
+        // The scheduler takes this minute's only slot
+        background.current(33.4, -112.1).await.unwrap();
+        let current = interactive.current(33.4, -112.1).await.unwrap();
+        assert_eq!(current.temperature, 25.0);
+    }
+}
@@ -0,0 +1,226 @@
+// src/services/zonal_stats.rs
+//
+// Statistics of a tile layer over an arbitrary polygon, one result per year.
+// Years backed by `local://` grids are summarised from the grid cells whose
+// centres fall inside the zone; other years are reduced on Earth Engine with
+// `reduceRegion`. The area-weighted anomaly is the mean absolute anomaly (°C)
+// weighted by pixel area, so high-latitude pixels do not dominate.
+use crate::models::tile_config::TileLayer;
+use crate::models::zone::Zone;
+use crate::services::earthengine::EarthEngineService;
+use crate::services::gee_composites::FIRST_YEAR;
+use crate::services::gee_expression::{Geometry, Image, Reducer};
+use crate::services::grid_renderer::GridRenderer;
+use crate::services::tile_config_service::TileConfigService;
+use crate::utils::stats::percentile;
+use chrono::{Datelike, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+
+/// Years summarised in one request
+pub const MAX_ZONAL_YEARS: usize = 10;
+
+/// Scale of the Earth Engine reduction in metres, the MODIS LST resolution
+const ZONAL_SCALE_METERS: f64 = 1000.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ZonalStats {
+    pub year: i32,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    pub p10: Option<f64>,
+    pub p90: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Pixels with data inside the zone
+    pub count: u64,
+    /// Mean absolute anomaly (°C) weighted by pixel area
+    pub area_weighted_anomaly: Option<f64>,
+}
+
+#[derive(Debug)]
+pub enum ZonalError {
+    InvalidRequest(String),
+    Unavailable(String),
+}
+
+impl fmt::Display for ZonalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZonalError::InvalidRequest(e) | ZonalError::Unavailable(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+pub struct ZonalStatsService {
+    earth_engine: Arc<EarthEngineService>,
+    tile_config_service: Arc<TileConfigService>,
+    renderer: Arc<GridRenderer>,
+}
+
+impl ZonalStatsService {
+    pub fn new(
+        earth_engine: Arc<EarthEngineService>,
+        tile_config_service: Arc<TileConfigService>,
+        renderer: Arc<GridRenderer>,
+    ) -> Self {
+        Self {
+            earth_engine,
+            tile_config_service,
+            renderer,
+        }
+    }
+
+    /// Statistics of `layer` over `zone` for each year, in year order.
+    pub async fn stats(&self, zone: &Zone, layer: TileLayer, years: &[i32]) -> Result<Vec<ZonalStats>, ZonalError> {
+        let current_year = Utc::now().year();
+        if years.is_empty() || years.len() > MAX_ZONAL_YEARS {
+            return Err(ZonalError::InvalidRequest(format!(
+                "between 1 and {} years are required",
+                MAX_ZONAL_YEARS
+            )));
+        }
+        if let Some(year) = years.iter().find(|year| !(FIRST_YEAR..=current_year).contains(*year)) {
+            return Err(ZonalError::InvalidRequest(format!(
+                "year {} is outside {}..={}",
+                year, FIRST_YEAR, current_year
+            )));
+        }
+
+        let mut years = years.to_vec();
+        years.sort_unstable();
+        years.dedup();
+
+        let mut results = Vec::with_capacity(years.len());
+        for year in years {
+            let config = self.tile_config_service.get_config_for_year(year).await;
+            let local = config.and_then(|config| {
+                Some((
+                    config.local_grid(layer)?.to_string(),
+                    config.local_grid(TileLayer::AbsoluteAnomaly)?.to_string(),
+                ))
+            });
+            let stats = match local {
+                Some((grid, anomaly_grid)) => self.local_stats(year, zone, &grid, &anomaly_grid).await,
+                None => self.earth_engine_stats(year, zone, layer).await,
+            };
+            results.push(stats.map_err(|e| ZonalError::Unavailable(format!("{}: {:#}", year, e)))?);
+        }
+        Ok(results)
+    }
+
+    async fn local_stats(&self, year: i32, zone: &Zone, grid: &str, anomaly_grid: &str) -> anyhow::Result<ZonalStats> {
+        let mut values: Vec<f64> = self
+            .renderer
+            .zonal_values(grid, zone)
+            .await?
+            .into_iter()
+            .map(|(value, _)| value)
+            .collect();
+        let anomalies = self.renderer.zonal_values(anomaly_grid, zone).await?;
+        values.sort_by(f64::total_cmp);
+
+        let weight: f64 = anomalies.iter().map(|(_, weight)| weight).sum();
+        let count = values.len();
+        Ok(ZonalStats {
+            year,
+            mean: (count > 0).then(|| values.iter().sum::<f64>() / count as f64),
+            median: percentile(&values, 50.0),
+            p10: percentile(&values, 10.0),
+            p90: percentile(&values, 90.0),
+            min: values.first().copied(),
+            max: values.last().copied(),
+            count: count as u64,
+            area_weighted_anomaly: (weight > 0.0)
+                .then(|| anomalies.iter().map(|(anomaly, weight)| anomaly * weight).sum::<f64>() / weight),
+        })
+    }
+
+    async fn earth_engine_stats(&self, year: i32, zone: &Zone, layer: TileLayer) -> anyhow::Result<ZonalStats> {
+        let bands = self.earth_engine.year_bands(year).await?;
+        let anomaly = bands.absolute_anomaly.clone();
+        let area = Image::pixel_area();
+        let stack = bands
+            .layer(layer)
+            .rename("value")
+            .add_bands(anomaly.clone().multiply(area.clone()).rename("weighted"))
+            .add_bands(area.update_mask(anomaly.mask()).rename("area"));
+        let reducer = Reducer::mean()
+            .combine(Reducer::percentile(&[10, 50, 90]))
+            .combine(Reducer::min_max())
+            .combine(Reducer::count())
+            .combine(Reducer::sum());
+
+        let result = self
+            .earth_engine
+            .compute_value(stack.reduce_region(
+                reducer,
+                Geometry::multi_polygon(zone.coordinates()),
+                ZONAL_SCALE_METERS,
+            ))
+            .await?;
+        let value = |name: &str| result.get(name).and_then(Value::as_f64);
+
+        Ok(ZonalStats {
+            year,
+            mean: value("value_mean"),
+            median: value("value_p50"),
+            p10: value("value_p10"),
+            p90: value("value_p90"),
+            min: value("value_min"),
+            max: value("value_max"),
+            count: value("value_count").unwrap_or(0.0) as u64,
+            area_weighted_anomaly: value("weighted_sum")
+                .zip(value("area_sum"))
+                .filter(|(_, area)| *area > 0.0)
+                .map(|(weighted, area)| weighted / area),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::zone::tests::zone;
+    use crate::services::grid_renderer::tests::{offline_earth_engine, write_grid, write_local_config};
+
+    #[tokio::test]
+    async fn summarises_local_grids_inside_the_zone() {
+        let dir = std::env::temp_dir().join(format!("zonal-{}", uuid::Uuid::new_v4()));
+        let (configs, grids) = (dir.join("configs"), dir.join("grids"));
+
+        // 4x1 grids over 0..40°E along the equator band 0..10°N
+        write_grid(&grids, "lst", &[10.0, 20.0, 30.0, 40.0], 4, 1, [0.0, 0.0, 40.0, 10.0]);
+        write_grid(&grids, "abs", &[1.0, 2.0, 3.0, f32::NAN], 4, 1, [0.0, 0.0, 40.0, 10.0]);
+        write_local_config(&configs, 2020, ["lst", "abs", "abs"]);
+
+        let service = ZonalStatsService::new(
+            offline_earth_engine(),
+            Arc::new(TileConfigService::new(&configs)),
+            Arc::new(GridRenderer::with_limit(&grids, u64::MAX)),
+        );
+
+        // Covers the centres of all four cells
+        let zone = zone(r#"{ "type": "Polygon", "coordinates": [[[0, 0], [40, 0], [40, 10], [0, 10], [0, 0]]] }"#).unwrap();
+        let stats = service.stats(&zone, TileLayer::Lst, &[2020]).await.unwrap();
+        assert_eq!(
+            stats,
+            vec![ZonalStats {
+                year: 2020,
+                mean: Some(25.0),
+                median: Some(25.0),
+                p10: Some(13.0),
+                p90: Some(37.0),
+                min: Some(10.0),
+                max: Some(40.0),
+                count: 4,
+                area_weighted_anomaly: Some(2.0),
+            }]
+        );
+        assert!(service.stats(&zone, TileLayer::Lst, &[1990]).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1 +1 @@
-pub mod cities;
+pub mod stats;